@@ -0,0 +1,144 @@
+// --- AGGREGATES ---
+// COUNT, SUM, AVG, MIN and MAX over the rows a SELECT matched. Without GROUP BY the whole
+// result collapses into a single row. Integer and NUMERIC sums and averages stay exact.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use sqlparser::ast::{BinaryOperator, Expr, Function, FunctionArg, FunctionArgExpr, Ident, SelectItem};
+
+use crate::Value;
+use crate::decimal::Decimal;
+use crate::expr::{binary_op, compare_values, eval_expr, truth};
+
+type Row = BTreeMap<String, Value>;
+
+pub fn is_aggregate_name(name: &str) -> bool {
+    matches!(name, "count" | "sum" | "avg" | "min" | "max")
+}
+
+fn contains_aggregate(expr: &Expr) -> bool {
+    match expr {
+        Expr::Function(f) => {
+            is_aggregate_name(&f.name.to_string().to_lowercase())
+                || f.args.iter().any(|a| matches!(a, FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) if contains_aggregate(e)))
+        }
+        Expr::BinaryOp { left, right, .. } => contains_aggregate(left) || contains_aggregate(right),
+        Expr::UnaryOp { expr, .. } | Expr::Nested(expr) | Expr::Cast { expr, .. } => contains_aggregate(expr),
+        _ => false,
+    }
+}
+
+// True if the projection computes aggregates, e.g. SELECT SUM(price) FROM products.
+pub fn is_aggregate_query(projection: &[SelectItem]) -> bool {
+    projection.iter().any(|item| match item {
+        SelectItem::UnnamedExpr(e) | SelectItem::ExprWithAlias { expr: e, .. } => contains_aggregate(e),
+        _ => false,
+    })
+}
+
+// Evaluates an expression that may contain aggregates over `rows`. Each aggregate call is
+// computed once and replaced by a reference to its result, so SUM(a) / COUNT(*) also works.
+pub fn eval_aggregate(expr: &Expr, rows: &[&Row]) -> Result<Value, String> {
+    let mut results = Row::new();
+    let rewritten = rewrite(expr, rows, &mut results)?;
+    eval_expr(&rewritten, &results)
+}
+
+fn rewrite(expr: &Expr, rows: &[&Row], results: &mut Row) -> Result<Expr, String> {
+    Ok(match expr {
+        Expr::Function(f) if is_aggregate_name(&f.name.to_string().to_lowercase()) => {
+            let key = expr.to_string();
+            let value = compute(f, rows)?;
+            results.insert(key.clone(), value);
+            Expr::Identifier(Ident::new(key))
+        }
+        Expr::Function(f) => {
+            let mut f = f.clone();
+            for arg in &mut f.args {
+                if let FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) = arg {
+                    *e = rewrite(e, rows, results)?;
+                }
+            }
+            Expr::Function(f)
+        }
+        Expr::BinaryOp { left, op, right } => Expr::BinaryOp {
+            left: Box::new(rewrite(left, rows, results)?),
+            op: op.clone(),
+            right: Box::new(rewrite(right, rows, results)?),
+        },
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp { op: *op, expr: Box::new(rewrite(expr, rows, results)?) },
+        Expr::Nested(inner) => Expr::Nested(Box::new(rewrite(inner, rows, results)?)),
+        Expr::Cast { expr, data_type, format } => Expr::Cast {
+            expr: Box::new(rewrite(expr, rows, results)?),
+            data_type: data_type.clone(),
+            format: format.clone(),
+        },
+        // A bare column has no single value once the rows are collapsed
+        Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+            return Err(format!("Column '{}' must be used in an aggregate function", expr));
+        }
+        other => other.clone(),
+    })
+}
+
+fn compute(f: &Function, rows: &[&Row]) -> Result<Value, String> {
+    let name = f.name.to_string().to_lowercase();
+    let arg = match f.args.as_slice() {
+        [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)] if name == "count" && !f.distinct => None,
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(e))] => Some(e),
+        _ => return Err(format!("{}() expects exactly one argument", name)),
+    };
+
+    // The non-NULL argument values of the rows that pass FILTER (WHERE ...)
+    let mut values = Vec::new();
+    for row in rows {
+        if let Some(filter) = &f.filter
+            && truth(&eval_expr(filter, row)?)? != Some(true)
+        {
+            continue;
+        }
+        let value = match arg {
+            Some(e) => eval_expr(e, row)?,
+            None => Value::Bool(true), // COUNT(*) counts rows, not values
+        };
+        if value != Value::Null && !(f.distinct && values.contains(&value)) {
+            values.push(value);
+        }
+    }
+
+    match name.as_str() {
+        "count" => Ok(Value::Integer(values.len() as i64)),
+        "sum" | "avg" => {
+            let mut total = match values.first() {
+                None => return Ok(Value::Null),
+                Some(Value::Integer(_) | Value::Float(_) | Value::Decimal(_)) => Value::Integer(0),
+                Some(v) => return Err(format!("{}() expects numbers, got {:?}", name, v)),
+            };
+            for v in &values {
+                total = binary_op(&total, &BinaryOperator::Plus, v)?;
+            }
+            if name == "sum" {
+                return Ok(total);
+            }
+            // The average of integers is an exact NUMERIC, as in PostgreSQL
+            if let Value::Integer(i) = total {
+                total = Value::Decimal(Decimal::from(i));
+            }
+            binary_op(&total, &BinaryOperator::Divide, &Value::Integer(values.len() as i64))
+        }
+        _ => {
+            let wanted = if name == "min" { Ordering::Less } else { Ordering::Greater };
+            let mut best: Option<Value> = None;
+            for v in values {
+                best = match best {
+                    Some(b) => {
+                        let ord = compare_values(&v, &b).ok_or(format!("Cannot compare {:?} with {:?}", v, b))?;
+                        Some(if ord == wanted { v } else { b })
+                    }
+                    None => Some(v),
+                };
+            }
+            Ok(best.unwrap_or(Value::Null))
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// --- ARRAYS ---
+// One-dimensional array values (INT[], TEXT[], ...): the PostgreSQL text form '{1,2,3}',
+// ANY/ALL, containment (@>, <@), overlap (&&), subscripts and the unnest table function.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use sqlparser::ast::BinaryOperator;
+
+use crate::Value;
+use crate::decimal::Decimal;
+use crate::expr::{binary_op, compare_values, display_value, truth};
+
+// Splits '{a,"b c",NULL}' into its elements; None is an unquoted NULL.
+pub fn parse_literal(s: &str) -> Result<Vec<Option<String>>, String> {
+    let invalid = || format!("Invalid array literal: '{}'", s);
+    let inner = s.trim().strip_prefix('{').and_then(|t| t.strip_suffix('}')).ok_or_else(invalid)?;
+    let mut items = Vec::new();
+    if inner.trim().is_empty() {
+        return Ok(items);
+    }
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut item = String::new();
+        let quoted = chars.peek() == Some(&'"');
+        if quoted {
+            chars.next();
+            loop {
+                match chars.next().ok_or_else(invalid)? {
+                    '"' => break,
+                    '\\' => item.push(chars.next().ok_or_else(invalid)?),
+                    c => item.push(c),
+                }
+            }
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                if c == '{' || c == '"' {
+                    return Err(format!("{} (only one-dimensional arrays are supported)", invalid()));
+                }
+                item.push(c);
+                chars.next();
+            }
+            item = item.trim_end().to_string();
+        }
+        let is_null = !quoted && item.eq_ignore_ascii_case("null");
+        items.push(if is_null { None } else { Some(item) });
+        match chars.next() {
+            Some(',') => continue,
+            None => break,
+            Some(_) => return Err(invalid()),
+        }
+    }
+    Ok(items)
+}
+
+// An array literal written as text ('{1,2}') where an array is expected, e.g. in
+// `tags && '{go,rust}'`. Numbers become numbers; everything else stays text.
+pub fn from_text(s: &str) -> Result<Vec<Value>, String> {
+    Ok(parse_literal(s)?.into_iter().map(|item| match item {
+        None => Value::Null,
+        Some(t) => match (t.parse::<i64>(), Decimal::parse(&t)) {
+            (Ok(i), _) => Value::Integer(i),
+            (_, Ok(d)) => Value::Decimal(d),
+            _ => Value::Text(t),
+        },
+    }).collect())
+}
+
+// The PostgreSQL text form: {1,2,NULL} and {"a b",c}.
+pub fn display(items: &[Value]) -> String {
+    let parts: Vec<String> = items.iter().map(|v| match v {
+        Value::Null => "NULL".to_string(),
+        Value::Text(s) => {
+            let plain = !s.is_empty() && !s.eq_ignore_ascii_case("null")
+                && !s.chars().any(|c| matches!(c, ',' | '{' | '}' | '"' | '\\') || c.is_whitespace());
+            if plain { s.clone() } else { format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")) }
+        }
+        other => display_value(other),
+    }).collect();
+    format!("{{{}}}", parts.join(","))
+}
+
+// Element-by-element, then by length (as PostgreSQL orders arrays).
+pub fn compare(a: &[Value], b: &[Value]) -> Option<Ordering> {
+    for (x, y) in a.iter().zip(b) {
+        let ord = match (x, y) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            _ => compare_values(x, y)?,
+        };
+        if ord != Ordering::Equal {
+            return Some(ord);
+        }
+    }
+    Some(a.len().cmp(&b.len()))
+}
+
+fn has(items: &[Value], wanted: &Value) -> bool {
+    items.iter().any(|v| *v != Value::Null && compare_values(v, wanted) == Some(Ordering::Equal))
+}
+
+// a @> b: every element of b is in a.
+pub fn contains(a: &[Value], b: &[Value]) -> bool {
+    b.iter().all(|v| has(a, v))
+}
+
+// a && b: the arrays share an element.
+pub fn overlaps(a: &[Value], b: &[Value]) -> bool {
+    b.iter().any(|v| has(a, v))
+}
+
+// `v op ANY(items)` / `v op ALL(items)` with SQL's NULL rules.
+pub fn any_all(v: &Value, op: &BinaryOperator, items: &[Value], all: bool) -> Result<Value, String> {
+    let mut saw_null = false;
+    for item in items {
+        match truth(&binary_op(v, op, item)?)? {
+            Some(b) if b != all => return Ok(Value::Bool(!all)),
+            None => saw_null = true,
+            _ => {}
+        }
+    }
+    Ok(if saw_null { Value::Null } else { Value::Bool(all) })
+}
+
+// arr[i], 1-based; out of range is NULL.
+pub fn subscript(items: &[Value], index: &Value) -> Result<Value, String> {
+    match index {
+        Value::Null => Ok(Value::Null),
+        Value::Integer(i) if *i >= 1 => Ok(items.get(*i as usize - 1).cloned().unwrap_or(Value::Null)),
+        Value::Integer(_) => Ok(Value::Null),
+        other => Err(format!("Array subscript must be an integer, got {:?}", other)),
+    }
+}
+
+pub const UNNEST_COLUMNS: [&str; 1] = ["unnest"];
+
+// unnest(arr): one row per element.
+pub fn unnest(v: &Value) -> Result<Vec<BTreeMap<String, Value>>, String> {
+    match v {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(items) => Ok(items.iter().map(|item| BTreeMap::from([("unnest".to_string(), item.clone())])).collect()),
+        other => Err(format!("unnest() expects an array, got {:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{run, setup};
+
+    #[test]
+    fn literals_split_into_elements() {
+        assert_eq!(parse_literal(r#"{a, "b c",NULL,"null"}"#), Ok(vec![Some("a".to_string()), Some("b c".to_string()), None, Some("null".to_string())]));
+        assert_eq!(parse_literal("{}"), Ok(Vec::new()));
+        assert!(parse_literal("{1,{2}}").is_err());
+        assert!(parse_literal("1,2").is_err());
+        assert_eq!(display(&[Value::Text("b c".to_string()), Value::Null, Value::Integer(1)]), r#"{"b c",NULL,1}"#);
+    }
+
+    #[test]
+    fn enum_and_array_columns_check_their_elements() {
+        let mut db = setup("
+            CREATE TYPE mood AS ENUM ('happy', 'sad');
+            CREATE TABLE person (id INTEGER PRIMARY KEY, m mood, tags TEXT[], nums INT[]);
+            INSERT INTO person VALUES (1, 'happy', '{go,rust}', ARRAY[1, 2]);
+        ");
+        assert_eq!(
+            run(&mut db, "INSERT INTO person VALUES (2, 'angry', '{}', '{}')"),
+            Err("Invalid value for enum mood in column 'm': 'angry' (expected one of: 'happy', 'sad')".to_string())
+        );
+        assert!(run(&mut db, "INSERT INTO person VALUES (3, 'sad', '{c}', '{1,x}')").is_err());
+        assert_eq!(run(&mut db, "DROP TYPE mood"), Err("Cannot drop type 'mood': column person.m uses it".to_string()));
+        assert_eq!(
+            run(&mut db, "SELECT tags[1], cardinality(tags), array_append(nums, 3), tags @> '{go}', tags && '{c}' FROM person WHERE 'go' = ANY(tags)"),
+            Ok("ID | tags[1] | cardinality(tags) | array_append(nums, 3) | tags @> '{go}' | tags && '{c}'\n1  | go | 2 | {1,2,3} | true | false".to_string())
+        );
+        assert_eq!(run(&mut db, "SELECT n FROM unnest(ARRAY[3, 1]) AS t(n)"), Ok("ID | n\n1  | 3\n2  | 1".to_string()));
+    }
+}
@@ -0,0 +1,130 @@
+// --- BINARY DATA ---
+// Raw bytes for BLOB/BYTEA columns. Shown as PostgreSQL-style hex (\x89504e47) and
+// persisted as a base64 string, which is far smaller than a JSON array of numbers.
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Blob(pub Vec<u8>);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl Blob {
+    // Hex digits without prefix, as in X'ABCD'. Whitespace between bytes is ignored.
+    pub fn from_hex(s: &str) -> Result<Self, String> {
+        let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        if !digits.len().is_multiple_of(2) {
+            return Err(format!("Invalid hex string: '{}' has an odd number of digits", s));
+        }
+        let nibble = |b: u8| (b as char).to_digit(16).map(|d| d as u8).ok_or(format!("Invalid hex digit '{}' in '{}'", b as char, s));
+        let mut bytes = Vec::with_capacity(digits.len() / 2);
+        for pair in digits.chunks(2) {
+            bytes.push(nibble(pair[0])? << 4 | nibble(pair[1])?);
+        }
+        Ok(Blob(bytes))
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn from_base64(s: &str) -> Result<Self, String> {
+        let invalid = || "Invalid base64 data".to_string();
+        let mut bytes = Vec::with_capacity(s.len() / 4 * 3);
+        let (mut acc, mut bits) = (0u32, 0);
+        for c in s.bytes().filter(|b| !b.is_ascii_whitespace() && *b != b'=') {
+            let v = BASE64.iter().position(|&x| x == c).ok_or_else(invalid)? as u32;
+            acc = acc << 6 | v;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((acc >> bits) as u8);
+            }
+        }
+        Ok(Blob(bytes))
+    }
+
+    pub fn to_base64(&self) -> String {
+        let mut out = String::with_capacity(self.0.len().div_ceil(3) * 4);
+        for chunk in self.0.chunks(3) {
+            let n = chunk.iter().enumerate().fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    // Text assigned to a binary column: '\xDEADBEEF' is hex, anything else is taken as UTF-8 bytes.
+    pub fn from_text(s: &str) -> Result<Self, String> {
+        match s.strip_prefix("\\x") {
+            Some(hex) => Blob::from_hex(hex),
+            None => Ok(Blob(s.as_bytes().to_vec())),
+        }
+    }
+}
+
+impl fmt::Display for Blob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\\x{}", self.to_hex())
+    }
+}
+
+impl fmt::Debug for Blob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_base64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        Blob::from_base64(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{run, setup};
+
+    #[test]
+    fn hex_and_base64_round_trip() {
+        let png = Blob::from_hex("89 50 4E 47").unwrap();
+        assert_eq!(png.0, vec![0x89, 0x50, 0x4e, 0x47]);
+        assert_eq!(png.to_string(), "\\x89504e47");
+        for len in 0..5 {
+            let blob = Blob((0..len).map(|i| i * 60).collect());
+            assert_eq!(Blob::from_base64(&blob.to_base64()), Ok(blob));
+        }
+        assert_eq!(Blob(b"Man".to_vec()).to_base64(), "TWFu");
+        assert_eq!(Blob(b"M".to_vec()).to_base64(), "TQ==");
+        assert!(Blob::from_hex("abc").is_err());
+        assert!(Blob::from_hex("zz").is_err());
+        assert_eq!(Blob::from_text("\\x00ff"), Ok(Blob(vec![0, 255])));
+        assert_eq!(Blob::from_text("hi"), Ok(Blob(b"hi".to_vec())));
+    }
+
+    #[test]
+    fn blob_columns_limit_length_and_support_functions() {
+        let mut db = setup("CREATE TABLE f (id INTEGER PRIMARY KEY, data BLOB(3))");
+        run(&mut db, "INSERT INTO f VALUES (1, X'DEAD'), (2, '\\x01')").unwrap();
+        assert_eq!(run(&mut db, "INSERT INTO f VALUES (3, X'00010203')"), Err("Value too long for column 'data' of type BLOB(3)".to_string()));
+        assert_eq!(
+            run(&mut db, "SELECT length(data), encode(data, 'base64'), data || X'BE' FROM f WHERE id = 1"),
+            Ok("ID | length(data) | encode(data, 'base64') | data || X'BE'\n1  | 2 | 3q0= | \\xdeadbe".to_string())
+        );
+        assert_eq!(run(&mut db, "SELECT hex(data) FROM f WHERE data = decode('01', 'hex')"), Ok("ID | hex(data)\n2  | 01".to_string()));
+    }
+}
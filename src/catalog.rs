@@ -0,0 +1,264 @@
+// --- SYSTEM CATALOG ---
+// Read-only relations describing the database: information_schema.tables,
+// information_schema.columns, sqlite_master and sqlite_stat (ANALYZE's figures). They are
+// built from the schema on every read, so they can be queried (and joined, and used in views)
+// like any table. SHOW TABLES, SHOW COLUMNS, DESCRIBE and SHOW CREATE are rewritten into
+// SELECTs over them.
+use std::collections::BTreeMap;
+
+use sqlparser::ast::{Query, ShowCreateObject, ShowStatementFilter, Statement};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+
+use crate::schema::split;
+use crate::stats;
+use crate::view::Snapshot;
+use crate::{object_name, Database, Row, Table, Value};
+
+// The catalog relation called `name`, if it is one.
+pub fn relation(db: &Database, name: &str) -> Option<Snapshot> {
+    let (columns, rows): (&[&str], _) = match name.to_lowercase().as_str() {
+        "information_schema.tables" => (&["table_schema", "table_name", "table_type"], tables(db)),
+        "information_schema.columns" => (&[
+            "table_schema", "table_name", "column_name", "ordinal_position", "data_type", "is_nullable",
+            "column_default", "is_generated", "generation_expression", "is_identity",
+        ], columns(db)),
+        "sqlite_master" | "sqlite_schema" => (&["type", "name", "tbl_name", "sql"], master(db)),
+        "sqlite_stat" => (&["table_name", "column_name", "row_count", "null_count", "distinct_count", "histogram"], stats::catalog_rows(db)),
+        _ => return None,
+    };
+    let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+    let rows = rows.into_iter().enumerate().map(|(i, values)| Row {
+        id: i as u64 + 1,
+        data: columns.iter().cloned().zip(values).collect::<BTreeMap<_, _>>(),
+    }).collect();
+    Some(Snapshot { columns, rows })
+}
+
+fn text(s: impl Into<String>) -> Value {
+    Value::Text(s.into())
+}
+
+fn yes_no(b: bool) -> Value {
+    text(if b { "YES" } else { "NO" })
+}
+
+fn sorted_tables(db: &Database) -> Vec<&Table> {
+    let mut tables: Vec<&Table> = db.tables.values().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    tables
+}
+
+fn tables(db: &Database) -> Vec<Vec<Value>> {
+    let mut objects: Vec<(&str, &str)> = db.tables.keys().map(|name| (name.as_str(), "BASE TABLE")).collect();
+    for view in db.views.values() {
+        objects.push((&view.name, if view.is_materialized() { "MATERIALIZED VIEW" } else { "VIEW" }));
+    }
+    objects.sort();
+    objects.into_iter().map(|(name, kind)| {
+        let (schema, name) = split(name);
+        vec![text(schema), text(name), text(kind)]
+    }).collect()
+}
+
+fn columns(db: &Database) -> Vec<Vec<Value>> {
+    let mut rows = Vec::new();
+    for table in sorted_tables(db) {
+        let (schema, name) = split(&table.name);
+        for (i, (col, col_type)) in table.columns.iter().enumerate() {
+            let generated = table.generated_column(col);
+            let nullable = !table.not_null.contains(col) && !table.primary_key.contains(col);
+            rows.push(vec![
+                text(schema),
+                text(name),
+                text(col),
+                Value::Integer(i as i64 + 1),
+                text(col_type.to_string()),
+                yes_no(nullable),
+                table.defaults.get(col).map(text).unwrap_or(Value::Null),
+                text(if generated.is_some() { "ALWAYS" } else { "NEVER" }),
+                generated.map(|g| text(&g.expr)).unwrap_or(Value::Null),
+                yes_no(identity(db, table, col).is_some()),
+            ]);
+        }
+    }
+    // A view's column types are not declared; only its names are known
+    for view in db.views.values() {
+        let names = match &view.snapshot {
+            Some(snapshot) => snapshot.columns.clone(),
+            None => view.evaluate(db).map(|s| s.columns).unwrap_or_default(),
+        };
+        let (schema, name) = split(&view.name);
+        for (i, col) in names.iter().enumerate() {
+            rows.push(vec![
+                text(schema), text(name), text(col), Value::Integer(i as i64 + 1), Value::Null,
+                yes_no(true), Value::Null, text("NEVER"), Value::Null, yes_no(false),
+            ]);
+        }
+    }
+    rows
+}
+
+fn master(db: &Database) -> Vec<Vec<Value>> {
+    let mut rows: Vec<Vec<Value>> = sorted_tables(db).into_iter()
+        .map(|t| vec![text("table"), text(&t.name), text(&t.name), text(create_table_sql(db, t))])
+        .collect();
+    for table in sorted_tables(db) {
+        for index in &table.indexes {
+            // Like SQLite's autoindexes, a constraint's index has no SQL of its own
+            let sql = if index.constraint { Value::Null } else { text(index.sql(&table.name)) };
+            rows.push(vec![text("index"), text(&index.name), text(&table.name), sql]);
+        }
+        for index in &table.fulltext {
+            rows.push(vec![text("index"), text(&index.name), text(&table.name), text(index.sql(&table.name))]);
+        }
+    }
+    for view in db.views.values() {
+        rows.push(vec![text("view"), text(&view.name), text(&view.name), text(view.sql())]);
+    }
+    for trigger in db.triggers.values() {
+        rows.push(vec![text("trigger"), text(&trigger.name), text(&trigger.table), text(trigger.sql())]);
+    }
+    rows
+}
+
+// For a SERIAL or IDENTITY column, how the column is numbered.
+fn identity(db: &Database, table: &Table, col: &str) -> Option<&'static str> {
+    let owner = format!("{}.{}", table.name, col);
+    db.sequences.values().find(|s| s.owned_by.as_deref() == Some(&owner))?;
+    Some(if table.identity_always.iter().any(|c| c == col) { "ALWAYS" } else { "BY DEFAULT" })
+}
+
+// A CREATE TABLE statement that rebuilds the table's schema.
+pub fn create_table_sql(db: &Database, table: &Table) -> String {
+    let mut parts = Vec::new();
+    for (col, col_type) in &table.columns {
+        let mut def = format!("{} {}", col, col_type);
+        if let Some(g) = table.generated_column(col) {
+            def.push_str(&format!(" GENERATED ALWAYS AS ({}) {}", g.expr, if g.stored { "STORED" } else { "VIRTUAL" }));
+        } else if let Some(kind) = identity(db, table, col) {
+            def.push_str(&format!(" GENERATED {} AS IDENTITY", kind));
+        } else {
+            if table.not_null.contains(col) {
+                def.push_str(" NOT NULL");
+            }
+            if let Some(default) = table.defaults.get(col) {
+                def.push_str(&format!(" DEFAULT {}", default));
+            }
+        }
+        parts.push(def);
+    }
+    if !table.primary_key.is_empty() {
+        parts.push(format!("PRIMARY KEY ({})", table.primary_key.join(", ")));
+    }
+    for unique in &table.uniques {
+        parts.push(format!("CONSTRAINT {} UNIQUE ({})", unique.name, unique.columns.join(", ")));
+    }
+    for check in &table.checks {
+        parts.push(format!("CONSTRAINT {} CHECK ({})", check.name, check.expr));
+    }
+    for fk in &table.foreign_keys {
+        parts.push(format!("CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({}) ON DELETE {} ON UPDATE {}",
+            fk.name, fk.columns.join(", "), fk.ref_table, fk.ref_columns.join(", "), fk.on_delete.sql(), fk.on_update.sql()));
+    }
+    format!("CREATE TABLE {} ({})", table.name, parts.join(", "))
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+// `LIKE 'x%'` or `WHERE cond` after a SHOW, as a condition on `column`.
+fn filter_sql(filter: &Option<ShowStatementFilter>, column: &str) -> Option<String> {
+    filter.as_ref().map(|f| match f {
+        ShowStatementFilter::Like(pattern) => format!("{} LIKE {}", column, quote(pattern)),
+        ShowStatementFilter::ILike(pattern) => format!("{} ILIKE {}", column, quote(pattern)),
+        ShowStatementFilter::Where(expr) => expr.to_string(),
+    })
+}
+
+fn columns_query(db: &Database, table: &str, filter: Option<String>) -> Result<String, String> {
+    if !db.tables.contains_key(table) && !db.views.contains_key(table) {
+        return Err(format!("Table '{}' not found", table));
+    }
+    let (schema, name) = split(table);
+    let filter = filter.map(|f| format!(" AND ({})", f)).unwrap_or_default();
+    Ok(format!(
+        "SELECT column_name, data_type, is_nullable, column_default FROM information_schema.columns \
+         WHERE table_schema = {} AND table_name = {}{} ORDER BY ordinal_position",
+        quote(schema), quote(name), filter))
+}
+
+// The SELECT that answers SHOW TABLES, SHOW COLUMNS, DESCRIBE or SHOW CREATE.
+pub fn show_query(db: &Database, stmt: &Statement) -> Result<Query, String> {
+    let sql = match stmt {
+        // SHOW [FULL] TABLES [FROM schema] [LIKE 'pattern' | WHERE cond]
+        Statement::ShowTables { full, db_name, filter, .. } => {
+            let mut conditions: Vec<String> = db_name.iter().map(|s| format!("table_schema = {}", quote(&s.value))).collect();
+            conditions.extend(filter_sql(filter, "table_name"));
+            let filter = if conditions.is_empty() { String::new() } else { format!(" WHERE {}", conditions.join(" AND ")) };
+            let columns = if *full { "table_name, table_type" } else { "table_name" };
+            format!("SELECT {} FROM information_schema.tables{} ORDER BY table_name", columns, filter)
+        }
+        Statement::ShowColumns { table_name, filter, .. } => {
+            columns_query(db, &object_name(table_name), filter_sql(filter, "column_name"))?
+        }
+        Statement::ExplainTable { table_name, .. } => columns_query(db, &object_name(table_name), None)?,
+        Statement::ShowCreate { obj_type, obj_name } => {
+            let name = object_name(obj_name);
+            let (kind, exists) = match obj_type {
+                ShowCreateObject::Table => ("table", db.tables.contains_key(&name)),
+                ShowCreateObject::View => ("view", db.views.contains_key(&name)),
+                ShowCreateObject::Trigger => ("trigger", db.triggers.contains_key(&name)),
+                other => return Err(format!("SHOW CREATE {} is not supported", other)),
+            };
+            if !exists {
+                return Err(format!("{}{} '{}' not found", kind[..1].to_uppercase(), &kind[1..], name));
+            }
+            format!("SELECT name, sql FROM sqlite_master WHERE type = {} AND name = {}", quote(kind), quote(&name))
+        }
+        _ => return Err("Not a SHOW statement".to_string()),
+    };
+    Parser::new(&GenericDialect {}).try_with_sql(&sql)
+        .and_then(|mut p| p.parse_query())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{rows, run, setup};
+    use crate::Value;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    #[test]
+    fn catalog_tables_describe_the_schema() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT NOT NULL DEFAULT 'x');
+            CREATE VIEW v AS SELECT id FROM t;
+        ");
+        assert_eq!(
+            rows(&mut db, "SELECT table_name, table_type FROM information_schema.tables"),
+            vec![vec![text("t"), text("BASE TABLE")], vec![text("v"), text("VIEW")]]
+        );
+        assert_eq!(
+            rows(&mut db, "SELECT column_name, data_type, is_nullable, column_default FROM information_schema.columns WHERE table_name = 't' AND ordinal_position = 2"),
+            vec![vec![text("name"), text("TEXT"), text("NO"), text("'x'")]]
+        );
+        assert_eq!(rows(&mut db, "SELECT name FROM sqlite_master WHERE type = 'index'"), vec![vec![text("t_pkey")]]);
+        assert!(run(&mut db, "INSERT INTO sqlite_master VALUES ('table', 'x', 'x', '')").is_err());
+    }
+
+    #[test]
+    fn stored_sql_rebuilds_the_table() {
+        let sql = "CREATE TABLE t (id INTEGER, name VARCHAR(5) NOT NULL DEFAULT 'x', CHECK (id > 0), PRIMARY KEY (id))";
+        let mut db = setup(sql);
+        let saved = rows(&mut db, "SELECT sql FROM sqlite_master WHERE name = 't'");
+        let Value::Text(rebuilt) = &saved[0][0] else { panic!("no sql for t") };
+        let mut copy = setup(rebuilt);
+        assert_eq!(rows(&mut copy, "SELECT sql FROM sqlite_master WHERE name = 't'"), saved);
+        assert!(run(&mut copy, "INSERT INTO t VALUES (0, 'a')").is_err());
+    }
+}
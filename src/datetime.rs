@@ -0,0 +1,726 @@
+// --- DATE / TIME ---
+// DATE, TIME, TIMESTAMP [WITH TIME ZONE] and INTERVAL values: parsing, formatting,
+// calendar arithmetic and the date functions (NOW, DATE_TRUNC, EXTRACT, strftime).
+// Everything is stored as integers (days or microseconds since 1970-01-01) so values
+// compare and sort correctly; mydb.json holds them as ISO strings.
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlparser::ast::{BinaryOperator, DateTimeField};
+
+use crate::Value;
+
+const MICROS_PER_SEC: i64 = 1_000_000;
+const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SEC;
+
+// Supported years: 4713 BC to 294246 AD, the span where every date also has a timestamp
+// (PostgreSQL's timestamps end at 294276 AD; counting from 1970 rather than 2000 moves the end back).
+const MIN_YEAR: i64 = -4712;
+const MAX_YEAR: i64 = 294_246;
+const MIN_DAY: i64 = days_from_civil(MIN_YEAR, 1, 1);
+const MAX_DAY: i64 = days_from_civil(MAX_YEAR, 12, 31);
+
+// Days since 1970-01-01.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date(pub i32);
+
+// Microseconds since midnight.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Time(pub i64);
+
+// Microseconds since 1970-01-01 00:00:00. For TIMESTAMP WITH TIME ZONE this is UTC.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub i64);
+
+// Months and days are kept apart from the clock part, as in PostgreSQL, because
+// their length depends on the date they are added to.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+// --- Calendar math (proleptic Gregorian, Howard Hinnant's algorithms) ---
+
+const fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
+
+fn is_leap(y: i64) -> bool {
+    (y % 4 == 0 && y % 100 != 0) || y % 400 == 0
+}
+
+// Splits a trailing " BC" off a date or timestamp.
+fn split_era(s: &str) -> (&str, bool) {
+    match s.len().checked_sub(3) {
+        Some(i) if s.is_char_boundary(i) && s[i..].eq_ignore_ascii_case(" BC") => (s[..i].trim_end(), true),
+        _ => (s, false),
+    }
+}
+
+// Years before 1 AD are written as their BC year with a " BC" suffix, e.g. year 0 is "0001 BC".
+fn write_ymd(f: &mut fmt::Formatter, (y, m, d): (i64, u32, u32)) -> fmt::Result {
+    write!(f, "{:04}-{:02}-{:02}", if y < 1 { 1 - y } else { y }, m, d)
+}
+
+fn write_era(f: &mut fmt::Formatter, y: i64) -> fmt::Result {
+    if y < 1 { write!(f, " BC") } else { Ok(()) }
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if is_leap(y) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    pub fn ymd(self) -> (i64, u32, u32) {
+        civil_from_days(self.0 as i64)
+    }
+
+    // Every date in the supported range has a timestamp, so this cannot overflow
+    pub fn to_timestamp(self) -> Timestamp {
+        Timestamp(self.0 as i64 * MICROS_PER_DAY)
+    }
+
+    pub fn from_days(days: i64) -> Result<Self, String> {
+        if !(MIN_DAY..=MAX_DAY).contains(&days) {
+            return Err("Date out of range".to_string());
+        }
+        Ok(Date(days as i32))
+    }
+
+    // "YYYY-MM-DD", with a " BC" suffix for years before 1 AD as PostgreSQL writes them
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let err = || format!("Invalid date: '{}'", s);
+        let (ymd, bc) = split_era(s.trim());
+        let parts: Vec<&str> = ymd.split('-').collect();
+        if parts.len() != 3 {
+            return Err(err());
+        }
+        let mut y: i64 = parts[0].parse().map_err(|_| err())?;
+        let m: u32 = parts[1].parse().map_err(|_| err())?;
+        let d: u32 = parts[2].parse().map_err(|_| err())?;
+        if bc {
+            // There is no year 0: 1 BC is the year before 1 AD
+            if y < 1 {
+                return Err(err());
+            }
+            y = 1 - y;
+        }
+        if !(1..=12).contains(&m) || d == 0 || d > days_in_month(y, m) {
+            return Err(err());
+        }
+        if !(MIN_YEAR..=MAX_YEAR).contains(&y) {
+            return Err(format!("Date out of range: '{}'", s));
+        }
+        Ok(Date(days_from_civil(y, m, d) as i32))
+    }
+}
+
+impl Time {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let err = || format!("Invalid time: '{}'", s);
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(err());
+        }
+        let h: i64 = parts[0].parse().map_err(|_| err())?;
+        let m: i64 = parts[1].parse().map_err(|_| err())?;
+        let secs: f64 = match parts.get(2) {
+            Some(p) => p.parse().map_err(|_| err())?,
+            None => 0.0,
+        };
+        if !(0..24).contains(&h) || !(0..60).contains(&m) || !(0.0..60.0).contains(&secs) {
+            return Err(err());
+        }
+        Ok(Time((h * 3600 + m * 60) * MICROS_PER_SEC + (secs * MICROS_PER_SEC as f64).round() as i64))
+    }
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Timestamp(since.as_micros() as i64)
+    }
+
+    // Fails for a value outside the supported dates
+    fn checked(micros: i64) -> Result<Self, String> {
+        if !(MIN_DAY..=MAX_DAY).contains(&micros.div_euclid(MICROS_PER_DAY)) {
+            return Err("Timestamp out of range".to_string());
+        }
+        Ok(Timestamp(micros))
+    }
+
+    pub fn date(self) -> Date {
+        Date(self.0.div_euclid(MICROS_PER_DAY) as i32)
+    }
+
+    pub fn time(self) -> Time {
+        Time(self.0.rem_euclid(MICROS_PER_DAY))
+    }
+
+    // Accepts "YYYY-MM-DD", "YYYY-MM-DD HH:MM[:SS[.ffffff]]" (or with a 'T'), optionally followed
+    // by a UTC offset ("Z", "+02", "-05:30"). Returns the local timestamp and the offset in seconds.
+    pub fn parse(s: &str) -> Result<(Self, Option<i64>), String> {
+        let err = || format!("Invalid timestamp: '{}'", s);
+        let s = s.trim();
+        let (body, bc) = split_era(s);
+        let (date_part, rest) = match body.find([' ', 'T']) {
+            Some(i) => (&body[..i], body[i + 1..].trim()),
+            None => (body, ""),
+        };
+        let date_part = if bc { format!("{} BC", date_part) } else { date_part.to_string() };
+        let date = Date::parse(&date_part).map_err(|e| if e.starts_with("Date out of range") { format!("Timestamp out of range: '{}'", s) } else { err() })?;
+        if rest.is_empty() {
+            return Ok((date.to_timestamp(), None));
+        }
+        let (time_part, offset) = if let Some(stripped) = rest.strip_suffix('Z') {
+            (stripped, Some(0))
+        } else if let Some(i) = rest.rfind(['+', '-']) {
+            let (sign, tz) = (if &rest[i..i + 1] == "-" { -1 } else { 1 }, &rest[i + 1..]);
+            let mut hm = tz.split(':');
+            let h: i64 = hm.next().unwrap_or("0").parse().map_err(|_| err())?;
+            let m: i64 = hm.next().unwrap_or("0").parse().map_err(|_| err())?;
+            if !(0..=15).contains(&h) || !(0..60).contains(&m) {
+                return Err(err());
+            }
+            (&rest[..i], Some(sign * (h * 3600 + m * 60)))
+        } else {
+            (rest, None)
+        };
+        let time = Time::parse(time_part.trim()).map_err(|_| err())?;
+        Ok((Timestamp::checked(date.to_timestamp().0 + time.0)?, offset))
+    }
+
+    // Parses a TIMESTAMP WITH TIME ZONE literal into UTC. Without an offset, UTC is assumed.
+    pub fn parse_utc(s: &str) -> Result<Self, String> {
+        let (ts, offset) = Timestamp::parse(s)?;
+        Timestamp::checked(ts.0 - offset.unwrap_or(0) * MICROS_PER_SEC)
+    }
+
+    pub fn add_interval(self, iv: &Interval, sign: i64) -> Result<Self, String> {
+        let (y, m, d) = self.date().ymd();
+        let total = y * 12 + (m as i64 - 1) + sign * iv.months as i64;
+        let (ny, nm) = (total.div_euclid(12), (total.rem_euclid(12) + 1) as u32);
+        // Adding a month to Jan 31 lands on the last day of February
+        let nd = d.min(days_in_month(ny, nm));
+        let days = days_from_civil(ny, nm, nd) + sign * iv.days as i64;
+        days.checked_mul(MICROS_PER_DAY)
+            .and_then(|t| t.checked_add(self.time().0))
+            .and_then(|t| t.checked_add(iv.micros.checked_mul(sign)?))
+            .ok_or("Timestamp out of range".to_string())
+            .and_then(Timestamp::checked)
+    }
+}
+
+fn out_of_range() -> String {
+    "Interval out of range".to_string()
+}
+
+impl Interval {
+    pub fn from_micros(micros: i64) -> Self {
+        Interval { months: 0, days: 0, micros }
+    }
+
+    // PostgreSQL-style text: "1 day", "2 hours 30 minutes", "1 year 2 mons 3 days 04:05:06", "-1 week".
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let err = || format!("Invalid interval: '{}'", s);
+        let mut iv = Interval { months: 0, days: 0, micros: 0 };
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        let mut i = 0;
+        while i < tokens.len() {
+            let tok = tokens[i];
+            if tok.contains(':') {
+                let (neg, clock) = match tok.strip_prefix('-') {
+                    Some(t) => (true, t),
+                    None => (false, tok),
+                };
+                let t = Time::parse(clock).map_err(|_| err())?.0;
+                iv.micros = iv.micros.checked_add(if neg { -t } else { t }).ok_or_else(out_of_range)?;
+                i += 1;
+                continue;
+            }
+            let n: f64 = tok.parse().map_err(|_| err())?;
+            // A bare number with no unit counts as seconds
+            let unit = tokens.get(i + 1).map(|u| u.to_lowercase()).unwrap_or_else(|| "second".to_string());
+            Interval::add_unit(&mut iv, n, unit.trim_end_matches('s'))?.ok_or_else(err)?;
+            i += 2;
+        }
+        Ok(iv)
+    }
+
+    // Adds `n` of `unit` (singular, lowercase). Returns None for an unknown unit.
+    pub fn add_unit(iv: &mut Interval, n: f64, unit: &str) -> Result<Option<()>, String> {
+        // Whole months and days; a fraction of either is dropped
+        let whole = |n: f64| i32::try_from(n as i64).map_err(|_| out_of_range());
+        let micros = |n: f64| i64::try_from(n.round() as i128).map_err(|_| out_of_range());
+        let (months, days, micros) = match unit {
+            "microsecond" => (0, 0, micros(n)?),
+            "millisecond" => (0, 0, micros(n * 1000.0)?),
+            "second" | "sec" | "" => (0, 0, micros(n * MICROS_PER_SEC as f64)?),
+            "minute" | "min" => (0, 0, micros(n * 60.0 * MICROS_PER_SEC as f64)?),
+            "hour" | "hr" | "h" => (0, 0, micros(n * 3600.0 * MICROS_PER_SEC as f64)?),
+            "day" | "d" => (0, whole(n)?, 0),
+            "week" | "w" => (0, whole(n * 7.0)?, 0),
+            "month" | "mon" => (whole(n)?, 0, 0),
+            "year" | "yr" | "y" => (whole(n * 12.0)?, 0, 0),
+            "decade" => (whole(n * 120.0)?, 0, 0),
+            "century" | "centurie" => (whole(n * 1200.0)?, 0, 0),
+            _ => return Ok(None),
+        };
+        *iv = iv.add(&Interval { months, days, micros }, 1)?;
+        Ok(Some(()))
+    }
+
+    // a + sign * b, field by field
+    fn add(&self, other: &Interval, sign: i32) -> Result<Interval, String> {
+        let (months, days, micros) = if sign < 0 {
+            (self.months.checked_sub(other.months), self.days.checked_sub(other.days), self.micros.checked_sub(other.micros))
+        } else {
+            (self.months.checked_add(other.months), self.days.checked_add(other.days), self.micros.checked_add(other.micros))
+        };
+        match (months, days, micros) {
+            (Some(months), Some(days), Some(micros)) => Ok(Interval { months, days, micros }),
+            _ => Err(out_of_range()),
+        }
+    }
+
+    fn approx_micros(&self) -> i128 {
+        (self.months as i128 * 30 + self.days as i128) * MICROS_PER_DAY as i128 + self.micros as i128
+    }
+
+    fn scale(&self, factor: f64) -> Result<Interval, String> {
+        // Fractional months and days spill into the smaller units, as in PostgreSQL
+        let months = self.months as f64 * factor;
+        let days = self.days as f64 * factor + months.fract() * 30.0;
+        let micros = self.micros as f64 * factor + days.fract() * MICROS_PER_DAY as f64;
+        Ok(Interval {
+            months: i32::try_from(months as i64).map_err(|_| out_of_range())?,
+            days: i32::try_from(days as i64).map_err(|_| out_of_range())?,
+            micros: i64::try_from(micros.round() as i128).map_err(|_| out_of_range())?,
+        })
+    }
+}
+
+// --- Formatting ---
+
+// Microseconds as HH:MM:SS[.ffffff]; an interval's hours go past 24
+struct Clock(u64);
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.0 / MICROS_PER_SEC as u64;
+        let frac = self.0 % MICROS_PER_SEC as u64;
+        write!(f, "{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)?;
+        if frac != 0 {
+            write!(f, "{}", format!(".{:06}", frac).trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ymd = self.ymd();
+        write_ymd(f, ymd)?;
+        write_era(f, ymd.0)
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", Clock(self.0 as u64))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ymd = self.date().ymd();
+        write_ymd(f, ymd)?;
+        write!(f, " {}", Clock(self.time().0 as u64))?;
+        write_era(f, ymd.0)
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        let plural = |n: i32, unit: &str| format!("{} {}{}", n, unit, if n.abs() == 1 { "" } else { "s" });
+        if years != 0 { parts.push(plural(years, "year")); }
+        if months != 0 { parts.push(plural(months, "mon")); }
+        if self.days != 0 { parts.push(plural(self.days, "day")); }
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 { "-" } else { "" };
+            parts.push(format!("{}{}", sign, Clock(self.micros.unsigned_abs())));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+// Debug matches Display so JOIN output reads `Date(2024-01-31)` rather than raw day counts.
+macro_rules! text_repr {
+    ($t:ty, $parse:expr) => {
+        impl fmt::Debug for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(self, f)
+            }
+        }
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_str(&self.to_string())
+            }
+        }
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(d)?;
+                $parse(&s).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+text_repr!(Date, Date::parse);
+text_repr!(Time, Time::parse);
+// Persisted TIMESTAMPTZ values are UTC, so the plain form round-trips for both kinds
+text_repr!(Timestamp, |s: &str| Timestamp::parse(s).map(|(ts, _)| ts));
+text_repr!(Interval, Interval::parse);
+
+// --- Value-level operations, called from the expression evaluator ---
+
+fn as_timestamp(v: &Value) -> Option<Timestamp> {
+    match v {
+        Value::Timestamp(ts) | Value::TimestampTz(ts) => Some(*ts),
+        Value::Date(d) => Some(d.to_timestamp()),
+        _ => None,
+    }
+}
+
+// Reads a string compared against a temporal value as that value's type, so that
+// `placed >= '2024-01-01'` works the way it does in PostgreSQL.
+fn text_as(like: &Value, s: &str) -> Option<Value> {
+    match like {
+        Value::Date(_) => Date::parse(s).ok().map(Value::Date),
+        Value::Time(_) => Time::parse(s).ok().map(Value::Time),
+        Value::Timestamp(_) => Timestamp::parse(s).ok().map(|(ts, _)| Value::Timestamp(ts)),
+        Value::TimestampTz(_) => Timestamp::parse_utc(s).ok().map(Value::TimestampTz),
+        Value::Interval(_) => Interval::parse(s).ok().map(Value::Interval),
+        _ => None,
+    }
+}
+
+// Orders two temporal values; a DATE compares as midnight of that day.
+pub fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Text(s), other) => compare(&text_as(other, s)?, other),
+        (other, Value::Text(s)) => compare(other, &text_as(other, s)?),
+        (Value::Time(x), Value::Time(y)) => Some(x.cmp(y)),
+        (Value::Interval(x), Value::Interval(y)) => Some(x.approx_micros().cmp(&y.approx_micros())),
+        _ => Some(as_timestamp(a)?.cmp(&as_timestamp(b)?)),
+    }
+}
+
+// Date/time arithmetic. Returns None if neither operand is temporal so the caller can
+// fall back to numeric arithmetic.
+pub fn arithmetic(l: &Value, op: &BinaryOperator, r: &Value) -> Option<Result<Value, String>> {
+    use BinaryOperator::{Divide, Minus, Multiply, Plus};
+    let with_kind = |v: &Value, ts: Timestamp| match v {
+        Value::TimestampTz(_) => Value::TimestampTz(ts),
+        _ => Value::Timestamp(ts),
+    };
+    Some(match (l, op, r) {
+        // date +/- integer days stays a date
+        (Value::Date(d), Plus | Minus, Value::Integer(n)) | (Value::Integer(n), Plus, Value::Date(d)) => {
+            let days = if *op == Minus { (d.0 as i64).checked_sub(*n) } else { (d.0 as i64).checked_add(*n) };
+            days.ok_or("Date out of range".to_string()).and_then(Date::from_days).map(Value::Date)
+        }
+        (Value::Date(a), Minus, Value::Date(b)) => Ok(Value::Integer(a.0 as i64 - b.0 as i64)),
+        (Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_), Plus | Minus, Value::Interval(iv)) => {
+            let sign = if *op == Minus { -1 } else { 1 };
+            as_timestamp(l)?.add_interval(iv, sign).map(|ts| with_kind(l, ts))
+        }
+        (Value::Interval(iv), Plus, Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_)) => {
+            as_timestamp(r)?.add_interval(iv, 1).map(|ts| with_kind(r, ts))
+        }
+        (Value::Time(t), Plus | Minus, Value::Interval(iv)) => {
+            // Only the clock part counts, and it wraps around midnight
+            let delta = iv.micros.rem_euclid(MICROS_PER_DAY);
+            let delta = if *op == Minus { MICROS_PER_DAY - delta } else { delta };
+            Ok(Value::Time(Time((t.0 + delta).rem_euclid(MICROS_PER_DAY))))
+        }
+        (Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_), Minus, Value::Date(_) | Value::Timestamp(_) | Value::TimestampTz(_)) => {
+            let Some(diff) = as_timestamp(l)?.0.checked_sub(as_timestamp(r)?.0) else {
+                return Some(Err(out_of_range()));
+            };
+            Ok(Value::Interval(Interval { months: 0, days: (diff / MICROS_PER_DAY) as i32, micros: diff % MICROS_PER_DAY }))
+        }
+        (Value::Time(a), Minus, Value::Time(b)) => Ok(Value::Interval(Interval::from_micros(a.0 - b.0))),
+        (Value::Interval(a), Plus | Minus, Value::Interval(b)) => {
+            a.add(b, if *op == Minus { -1 } else { 1 }).map(Value::Interval)
+        }
+        (Value::Interval(iv), Multiply, n @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_)))
+        | (n @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_)), Multiply, Value::Interval(iv)) => {
+            iv.scale(crate::expr::as_f64(n)?).map(Value::Interval)
+        }
+        (Value::Interval(iv), Divide, n @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_))) => {
+            let n = crate::expr::as_f64(n)?;
+            if n == 0.0 { Err("Division by zero".to_string()) } else { iv.scale(1.0 / n).map(Value::Interval) }
+        }
+        _ => {
+            let temporal = |v: &Value| matches!(v, Value::Date(_) | Value::Time(_) | Value::Timestamp(_) | Value::TimestampTz(_) | Value::Interval(_));
+            if temporal(l) || temporal(r) {
+                Err(format!("Cannot apply {} to {:?} and {:?}", op, l, r))
+            } else {
+                return None;
+            }
+        }
+    })
+}
+
+// Parses a field name such as 'day' or 'month' (DATE_TRUNC / EXTRACT arguments).
+pub fn field_from_name(name: &str) -> Result<DateTimeField, String> {
+    Ok(match name.to_lowercase().trim_end_matches('s') {
+        "microsecond" => DateTimeField::Microsecond,
+        "millisecond" => DateTimeField::Millisecond,
+        "second" => DateTimeField::Second,
+        "minute" => DateTimeField::Minute,
+        "hour" => DateTimeField::Hour,
+        "day" => DateTimeField::Day,
+        "week" => DateTimeField::Week,
+        "month" => DateTimeField::Month,
+        "quarter" => DateTimeField::Quarter,
+        "year" => DateTimeField::Year,
+        "decade" => DateTimeField::Decade,
+        "century" | "centurie" => DateTimeField::Century,
+        "dow" => DateTimeField::Dow,
+        "doy" => DateTimeField::Doy,
+        "epoch" => DateTimeField::Epoch,
+        other => return Err(format!("Unknown date field '{}'", other)),
+    })
+}
+
+// DATE_TRUNC(field, ts): zeroes every unit smaller than `field`.
+pub fn date_trunc(field: &DateTimeField, v: &Value) -> Result<Value, String> {
+    let ts = as_timestamp(v).ok_or(format!("DATE_TRUNC expects a date or timestamp, got {:?}", v))?;
+    let (y, m, _) = ts.date().ymd();
+    let day_start = ts.date().to_timestamp().0;
+    let micros = ts.0;
+    let truncated = match field {
+        DateTimeField::Microsecond | DateTimeField::Microseconds => micros,
+        DateTimeField::Millisecond | DateTimeField::Milliseconds => micros - micros.rem_euclid(1000),
+        DateTimeField::Second => micros - micros.rem_euclid(MICROS_PER_SEC),
+        DateTimeField::Minute => micros - micros.rem_euclid(60 * MICROS_PER_SEC),
+        DateTimeField::Hour => micros - micros.rem_euclid(3600 * MICROS_PER_SEC),
+        DateTimeField::Day => day_start,
+        // ISO weeks start on Monday; 1970-01-01 was a Thursday
+        DateTimeField::Week => day_start - ((ts.date().0 as i64 + 3).rem_euclid(7)) * MICROS_PER_DAY,
+        DateTimeField::Month => days_from_civil(y, m, 1) * MICROS_PER_DAY,
+        DateTimeField::Quarter => days_from_civil(y, (m - 1) / 3 * 3 + 1, 1) * MICROS_PER_DAY,
+        DateTimeField::Year => days_from_civil(y, 1, 1) * MICROS_PER_DAY,
+        DateTimeField::Decade => days_from_civil(y.div_euclid(10) * 10, 1, 1) * MICROS_PER_DAY,
+        DateTimeField::Century => days_from_civil((y - 1).div_euclid(100) * 100 + 1, 1, 1) * MICROS_PER_DAY,
+        other => return Err(format!("DATE_TRUNC does not support '{}'", other)),
+    };
+    Ok(match v {
+        Value::TimestampTz(_) => Value::TimestampTz(Timestamp(truncated)),
+        _ => Value::Timestamp(Timestamp(truncated)),
+    })
+}
+
+// EXTRACT(field FROM value). Seconds and epoch keep their fractional part.
+pub fn extract(field: &DateTimeField, v: &Value) -> Result<Value, String> {
+    if let Value::Interval(iv) = v {
+        let secs = iv.micros / MICROS_PER_SEC;
+        return Ok(match field {
+            DateTimeField::Year => Value::Integer(iv.months as i64 / 12),
+            DateTimeField::Month => Value::Integer(iv.months as i64 % 12),
+            DateTimeField::Day => Value::Integer(iv.days as i64),
+            DateTimeField::Hour => Value::Integer(secs / 3600),
+            DateTimeField::Minute => Value::Integer(secs / 60 % 60),
+            DateTimeField::Second => Value::Float((iv.micros % (60 * MICROS_PER_SEC)) as f64 / MICROS_PER_SEC as f64),
+            DateTimeField::Epoch => Value::Float(iv.approx_micros() as f64 / MICROS_PER_SEC as f64),
+            other => return Err(format!("EXTRACT does not support '{}' for intervals", other)),
+        });
+    }
+    let (date, clock) = match v {
+        Value::Time(t) => (None, t.0),
+        _ => {
+            let ts = as_timestamp(v).ok_or(format!("EXTRACT expects a date/time value, got {:?}", v))?;
+            (Some(ts), ts.time().0)
+        }
+    };
+    let secs = clock / MICROS_PER_SEC;
+    let need_date = || date.ok_or(format!("EXTRACT({}) needs a date", field));
+    Ok(match field {
+        DateTimeField::Hour => Value::Integer(secs / 3600),
+        DateTimeField::Minute => Value::Integer(secs / 60 % 60),
+        DateTimeField::Second => Value::Float((clock % (60 * MICROS_PER_SEC)) as f64 / MICROS_PER_SEC as f64),
+        DateTimeField::Millisecond | DateTimeField::Milliseconds => Value::Float((clock % (60 * MICROS_PER_SEC)) as f64 / 1000.0),
+        DateTimeField::Microsecond | DateTimeField::Microseconds => Value::Integer(clock % (60 * MICROS_PER_SEC)),
+        DateTimeField::Epoch => {
+            let ts = need_date()?;
+            if ts.0 % MICROS_PER_SEC == 0 { Value::Integer(ts.0 / MICROS_PER_SEC) } else { Value::Float(ts.0 as f64 / MICROS_PER_SEC as f64) }
+        }
+        _ => {
+            let ts = need_date()?;
+            let (y, m, d) = ts.date().ymd();
+            let days = ts.date().0 as i64;
+            match field {
+                DateTimeField::Year => Value::Integer(y),
+                DateTimeField::Month => Value::Integer(m as i64),
+                DateTimeField::Day => Value::Integer(d as i64),
+                DateTimeField::Quarter => Value::Integer((m as i64 - 1) / 3 + 1),
+                DateTimeField::Decade => Value::Integer(y.div_euclid(10)),
+                DateTimeField::Century => Value::Integer((y - 1).div_euclid(100) + 1),
+                // 0 = Sunday, like PostgreSQL
+                DateTimeField::Dow | DateTimeField::DayOfWeek => Value::Integer((days + 4).rem_euclid(7)),
+                DateTimeField::Isodow => Value::Integer((days + 3).rem_euclid(7) + 1),
+                DateTimeField::Doy | DateTimeField::DayOfYear => Value::Integer(days - days_from_civil(y, 1, 1) + 1),
+                DateTimeField::Week | DateTimeField::IsoWeek => Value::Integer(iso_week(ts.date())),
+                other => return Err(format!("EXTRACT does not support '{}'", other)),
+            }
+        }
+    })
+}
+
+fn iso_week(date: Date) -> i64 {
+    let days = date.0 as i64;
+    // The Thursday of this ISO week decides which year the week belongs to
+    let thursday = days - (days + 3).rem_euclid(7) + 3;
+    let (y, _, _) = civil_from_days(thursday);
+    (thursday - days_from_civil(y, 1, 1)) / 7 + 1
+}
+
+// SQLite-style strftime: %Y %m %d %H %M %S %f %j %w %u %W %s %%.
+pub fn strftime(format: &str, v: &Value) -> Result<Value, String> {
+    let ts = match v {
+        Value::Text(s) if s.eq_ignore_ascii_case("now") => Timestamp::now(),
+        Value::Text(s) => Timestamp::parse_utc(s)?,
+        Value::Time(t) => Timestamp(t.0),
+        _ => as_timestamp(v).ok_or(format!("strftime expects a date/time value, got {:?}", v))?,
+    };
+    let (y, m, d) = ts.date().ymd();
+    let clock = ts.time().0;
+    let secs = clock / MICROS_PER_SEC;
+    let days = ts.date().0 as i64;
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", y)),
+            Some('m') => out.push_str(&format!("{:02}", m)),
+            Some('d') => out.push_str(&format!("{:02}", d)),
+            Some('H') => out.push_str(&format!("{:02}", secs / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", secs / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", secs % 60)),
+            Some('f') => out.push_str(&format!("{:06.3}", (clock % (60 * MICROS_PER_SEC)) as f64 / MICROS_PER_SEC as f64)),
+            Some('j') => out.push_str(&format!("{:03}", days - days_from_civil(y, 1, 1) + 1)),
+            Some('w') => out.push_str(&((days + 4).rem_euclid(7)).to_string()),
+            Some('u') => out.push_str(&((days + 3).rem_euclid(7) + 1).to_string()),
+            Some('W') => out.push_str(&format!("{:02}", iso_week(ts.date()))),
+            Some('s') => out.push_str(&ts.0.div_euclid(MICROS_PER_SEC).to_string()),
+            Some('%') => out.push('%'),
+            Some(other) => return Err(format!("Unsupported strftime directive '%{}'", other)),
+            None => return Err("strftime format ends with '%'".to_string()),
+        }
+    }
+    Ok(Value::Text(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(s: &str) -> Value {
+        Value::Interval(Interval::parse(s).unwrap())
+    }
+
+    #[test]
+    fn parses_and_formats_round_trip() {
+        assert_eq!(Date::parse("2024-02-29").unwrap().to_string(), "2024-02-29");
+        assert!(Date::parse("2023-02-29").is_err());
+        assert_eq!(Time::parse("04:05:06.5").unwrap().to_string(), "04:05:06.5");
+        assert_eq!(Timestamp::parse_utc("2024-01-31T10:00:00+02:00").unwrap().to_string(), "2024-01-31 08:00:00");
+        assert_eq!(Interval::parse("1 year 2 mons 3 days 04:05:06").unwrap().to_string(), "1 year 2 mons 3 days 04:05:06");
+    }
+
+    #[test]
+    fn dates_outside_the_supported_range_are_rejected() {
+        assert_eq!(Date::parse("5000000-01-01").err(), Some("Date out of range: '5000000-01-01'".to_string()));
+        assert_eq!(Timestamp::parse("5000000-01-01").err(), Some("Timestamp out of range: '5000000-01-01'".to_string()));
+        assert!(Date::parse("-4713-12-31").is_err());
+        // The last supported instant still has a timestamp
+        let (last, _) = Timestamp::parse("294246-12-31 23:59:59.999999").unwrap();
+        assert_eq!(last.to_string(), "294246-12-31 23:59:59.999999");
+        assert_eq!(Date::parse("294246-12-31").unwrap().to_timestamp().date().to_string(), "294246-12-31");
+        let end = Value::Date(Date::parse("294246-12-31").unwrap());
+        assert_eq!(arithmetic(&end, &BinaryOperator::Plus, &Value::Integer(1)), Some(Err("Date out of range".to_string())));
+        assert_eq!(arithmetic(&end, &BinaryOperator::Minus, &Value::Integer(i64::MIN)), Some(Err("Date out of range".to_string())));
+        assert_eq!(arithmetic(&end, &BinaryOperator::Plus, &interval("1 day")), Some(Err("Timestamp out of range".to_string())));
+    }
+
+    #[test]
+    fn years_before_1_ad_are_written_bc() {
+        let first = Date::parse("4713-01-01 BC").unwrap();
+        assert_eq!(first.to_string(), "4713-01-01 BC");
+        assert_eq!(Date::from_days(MIN_DAY).map(|d| d.to_string()), Ok(first.to_string()));
+        assert!(Date::from_days(MIN_DAY - 1).is_err());
+        // The day before 1 AD is the last day of 1 BC
+        let ad = Date::parse("0001-01-01").unwrap();
+        assert_eq!(Date(ad.0 - 1).to_string(), "0001-12-31 BC");
+        assert_eq!(Date::parse("0001-12-31 bc").unwrap().0, ad.0 - 1);
+        assert!(Date::parse("0000-01-01 BC").is_err());
+        let (ides, _) = Timestamp::parse("0044-03-15 12:30:00 BC").unwrap();
+        assert_eq!(ides.to_string(), "0044-03-15 12:30:00 BC");
+        assert_eq!(ides.date().to_string(), "0044-03-15 BC");
+    }
+
+    #[test]
+    fn month_arithmetic_clamps_to_the_last_day() {
+        let jan31 = Value::Date(Date::parse("2024-01-31").unwrap());
+        let Some(Ok(Value::Timestamp(ts))) = arithmetic(&jan31, &BinaryOperator::Plus, &interval("1 month")) else { panic!() };
+        assert_eq!(ts.to_string(), "2024-02-29 00:00:00");
+    }
+
+    #[test]
+    fn interval_overflow_is_an_error() {
+        let out_of_range = Some("Interval out of range".to_string());
+        assert_eq!(Interval::parse("3000000000 days").err(), out_of_range);
+        assert_eq!(Interval::parse("2000000000 days 2000000000 days").err(), out_of_range);
+        assert_eq!(Interval::parse("1e300 seconds").err(), out_of_range);
+        let big = interval("2000000000 mons");
+        assert_eq!(arithmetic(&big, &BinaryOperator::Plus, &big).and_then(Result::err), out_of_range);
+        assert_eq!(arithmetic(&big, &BinaryOperator::Multiply, &Value::Float(1e300)).and_then(Result::err), out_of_range);
+        let min = Value::Interval(Interval::from_micros(i64::MIN));
+        assert_eq!(arithmetic(&Value::Time(Time(0)), &BinaryOperator::Minus, &min), Some(Ok(Value::Time(Time::parse("04:00:54.775808").unwrap()))));
+        let Value::Interval(min) = min else { unreachable!() };
+        assert_eq!(min.to_string(), "-2562047788:00:54.775808");
+    }
+}
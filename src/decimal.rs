@@ -0,0 +1,335 @@
+// --- EXACT DECIMALS ---
+// Fixed-point numbers for NUMERIC/DECIMAL columns: an integer mantissa and a decimal scale,
+// so 0.1 + 0.2 is exactly 0.3. Up to 38 significant digits (the NUMERIC precision limit).
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub const MAX_DIGITS: u32 = 38;
+// Fractional digits kept by division when neither operand asks for more (as in PostgreSQL).
+const DIV_SCALE: u32 = 20;
+
+#[derive(Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+fn overflow() -> String {
+    "Numeric value out of range".to_string()
+}
+
+fn pow10(n: u32) -> Option<i128> {
+    10i128.checked_pow(n)
+}
+
+// Divides rounding half away from zero.
+fn div_round(n: i128, d: i128) -> i128 {
+    let (q, r) = (n / d, n % d);
+    if r.unsigned_abs() * 2 >= d.unsigned_abs() {
+        q + if (n < 0) == (d < 0) { 1 } else { -1 }
+    } else {
+        q
+    }
+}
+
+impl Decimal {
+    pub fn new(mantissa: i128, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    // The result of an arithmetic step, or an overflow error. i128::MIN is out of range too: it
+    // has no positive counterpart, so negating it or parsing it back from mydb.json would fail.
+    fn checked(mantissa: Option<i128>, scale: u32) -> Result<Self, String> {
+        match mantissa {
+            Some(m) if m != i128::MIN => Ok(Decimal::new(m, scale)),
+            _ => Err(overflow()),
+        }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    // Accepts "12", "-0.05", ".5", "1.25e3".
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid numeric value: '{}'", s);
+        let t = s.trim();
+        let (num, exp) = match t.find(['e', 'E']) {
+            Some(i) => (&t[..i], t[i + 1..].parse::<i32>().map_err(|_| invalid())?),
+            None => (t, 0),
+        };
+        let (negative, num) = match num.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, num.strip_prefix('+').unwrap_or(num)),
+        };
+        let (int_part, frac_part) = num.split_once('.').unwrap_or((num, ""));
+        if int_part.is_empty() && frac_part.is_empty()
+            || !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let digit = |c: char| c.to_digit(10).unwrap_or(0) as i128;
+        let push = |m: i128, c: char| m.checked_mul(10).and_then(|m| m.checked_add(digit(c)));
+        let mut mantissa: i128 = 0;
+        for c in int_part.chars() {
+            mantissa = push(mantissa, c).ok_or_else(overflow)?;
+        }
+        // Fractional digits beyond what fits are rounded away
+        let mut frac_len = 0;
+        for c in frac_part.chars() {
+            match push(mantissa, c) {
+                Some(m) if frac_len < MAX_DIGITS => {
+                    mantissa = m;
+                    frac_len += 1;
+                }
+                _ => {
+                    if digit(c) >= 5 {
+                        mantissa = mantissa.checked_add(1).ok_or_else(overflow)?;
+                    }
+                    break;
+                }
+            }
+        }
+        let mut scale = (frac_len as i32).checked_sub(exp).ok_or_else(overflow)?;
+        if scale < 0 {
+            mantissa = mantissa.checked_mul(pow10(scale.unsigned_abs()).ok_or_else(overflow)?).ok_or_else(overflow)?;
+            scale = 0;
+        }
+        let d = Decimal::new(if negative { -mantissa } else { mantissa }, scale as u32);
+        // Very long fractions are rounded to what fits
+        if d.scale > MAX_DIGITS { d.rescale(MAX_DIGITS) } else { Ok(d) }
+    }
+
+    pub fn from_f64(f: f64) -> Result<Self, String> {
+        if !f.is_finite() {
+            return Err(format!("Cannot convert {} to NUMERIC", f));
+        }
+        // Display gives the shortest string that round-trips, so 0.1 becomes exactly 0.1
+        Decimal::parse(&f.to_string())
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    // Rounds (half away from zero) or pads to the given number of fractional digits.
+    pub fn rescale(self, scale: u32) -> Result<Self, String> {
+        let mantissa = match scale.cmp(&self.scale) {
+            Ordering::Equal => self.mantissa,
+            Ordering::Greater => self.mantissa.checked_mul(pow10(scale - self.scale).ok_or_else(overflow)?).ok_or_else(overflow)?,
+            Ordering::Less => match pow10(self.scale - scale) {
+                Some(p) => div_round(self.mantissa, p),
+                None => 0,
+            },
+        };
+        Ok(Decimal::new(mantissa, scale))
+    }
+
+    // Digits before the decimal point, not counting leading zeros (0.05 has none).
+    pub fn integer_digits(&self) -> u32 {
+        let digits = self.mantissa.unsigned_abs().checked_ilog10().map_or(0, |d| d + 1);
+        digits.saturating_sub(self.scale)
+    }
+
+    pub fn round_to_integer(self) -> Result<i64, String> {
+        i64::try_from(self.rescale(0)?.mantissa).map_err(|_| "Integer overflow".to_string())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    // Cannot overflow: arithmetic never yields an i128::MIN mantissa
+    pub fn neg(self) -> Self {
+        Decimal::new(-self.mantissa, self.scale)
+    }
+
+    pub fn abs(self) -> Self {
+        Decimal::new(self.mantissa.abs(), self.scale)
+    }
+
+    // Both operands with the same scale, or None if aligning them overflows.
+    fn aligned(self, other: Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        let a = self.rescale(scale).ok()?;
+        let b = other.rescale(scale).ok()?;
+        Some((a.mantissa, b.mantissa, scale))
+    }
+
+    pub fn add(self, other: Self) -> Result<Self, String> {
+        let (a, b, scale) = self.aligned(other).ok_or_else(overflow)?;
+        Decimal::checked(a.checked_add(b), scale)
+    }
+
+    pub fn sub(self, other: Self) -> Result<Self, String> {
+        let (a, b, scale) = self.aligned(other).ok_or_else(overflow)?;
+        Decimal::checked(a.checked_sub(b), scale)
+    }
+
+    pub fn mul(self, other: Self) -> Result<Self, String> {
+        let d = Decimal::checked(self.mantissa.checked_mul(other.mantissa), self.scale + other.scale)?;
+        if d.scale > MAX_DIGITS { d.rescale(MAX_DIGITS) } else { Ok(d) }
+    }
+
+    pub fn div(self, other: Self) -> Result<Self, String> {
+        if other.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        // Widest result scale whose numerator still fits, trimmed back to the operands' scale
+        let min_scale = self.scale.max(other.scale);
+        let mut scale = min_scale.max(DIV_SCALE);
+        loop {
+            // scale >= self.scale, so the shift is never negative
+            let shift = scale + other.scale - self.scale;
+            match pow10(shift).and_then(|p| self.mantissa.checked_mul(p)) {
+                Some(n) => return Ok(Decimal::new(div_round(n, other.mantissa), scale).trim(min_scale)),
+                None if scale > min_scale => scale -= 1,
+                None => return Err(overflow()),
+            }
+        }
+    }
+
+    pub fn rem(self, other: Self) -> Result<Self, String> {
+        if other.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        let (a, b, scale) = self.aligned(other).ok_or_else(overflow)?;
+        Ok(Decimal::new(a % b, scale))
+    }
+
+    // Drops trailing fractional zeros, keeping at least `min_scale` digits.
+    fn trim(mut self, min_scale: u32) -> Self {
+        while self.scale > min_scale && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        self
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(i: i64) -> Self {
+        Decimal::new(i as i128, 0)
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.aligned(*other) {
+            Some((a, b, _)) => a.cmp(&b),
+            // Aligning only overflows for huge magnitudes; floats order those correctly
+            None => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// 1.5 and 1.50 are the same number
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (int_part, frac_part) = padded.split_at(padded.len() - scale);
+        write!(f, "{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+impl fmt::Debug for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Persisted as a string so no digit is lost to a JSON float.
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        Decimal::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn arithmetic_is_exact() {
+        assert_eq!(dec("0.1").add(dec("0.2")).unwrap().to_string(), "0.3");
+        assert_eq!(dec("19.99").mul(dec("3")).unwrap().to_string(), "59.97");
+        assert_eq!(dec("1").div(dec("3")).unwrap().to_string(), "0.33333333333333333333");
+        assert_eq!(dec("10.50").rem(dec("3")).unwrap().to_string(), "1.50");
+        assert_eq!(dec("1").div(dec("0")).err(), Some("Division by zero".to_string()));
+    }
+
+    #[test]
+    fn rescale_rounds_half_away_from_zero() {
+        assert_eq!(dec("2.345").rescale(2).unwrap().to_string(), "2.35");
+        assert_eq!(dec("-2.345").rescale(2).unwrap().to_string(), "-2.35");
+        assert_eq!(dec("2.5").rescale(4).unwrap().to_string(), "2.5000");
+        assert_eq!(dec("2.5").round_to_integer(), Ok(3));
+    }
+
+    #[test]
+    fn integer_digits_ignore_the_fraction() {
+        assert_eq!(dec("0").integer_digits(), 0);
+        assert_eq!(dec("0.05").integer_digits(), 0);
+        assert_eq!(dec("0.999").integer_digits(), 0);
+        assert_eq!(dec("1.5").integer_digits(), 1);
+        assert_eq!(dec("-123.45").integer_digits(), 3);
+        assert_eq!(dec("100").integer_digits(), 3);
+    }
+
+    #[test]
+    fn out_of_range_values_are_errors_not_panics() {
+        assert_eq!(Decimal::parse("1e-2147483648").err(), Some(overflow()));
+        assert_eq!(Decimal::parse("1e2147483647").err(), Some(overflow()));
+        // The smallest i128 would have no negation, so arithmetic stops one short of it
+        let lowest = dec("-170141183460469231731687303715884105727");
+        assert_eq!(lowest.sub(dec("1")).err(), Some(overflow()));
+        assert_eq!(lowest.add(dec("-1")).err(), Some(overflow()));
+        assert_eq!(dec("-9223372036854775808").mul(dec("18446744073709551616")).err(), Some(overflow()));
+        assert_eq!(lowest.neg().to_string(), "170141183460469231731687303715884105727");
+        assert_eq!(lowest.abs(), lowest.neg());
+        assert_eq!(Decimal::parse(&lowest.to_string()), Ok(lowest));
+    }
+
+    #[test]
+    fn negating_an_overflowed_difference_reports_the_overflow() {
+        use crate::tests::run;
+        let mut db = crate::Database::new();
+        let sql = "SELECT -(CAST('-170141183460469231731687303715884105727' AS NUMERIC) - 1)";
+        assert_eq!(run(&mut db, sql), Err(overflow()));
+        assert_eq!(run(&mut db, "SELECT abs(CAST('-170141183460469231731687303715884105727' AS NUMERIC) - 1)"), Err(overflow()));
+        assert_eq!(run(&mut db, "SELECT CAST('1e-2147483648' AS NUMERIC)"), Err(overflow()));
+    }
+}
@@ -0,0 +1,682 @@
+// --- EXPRESSION EVALUATOR ---
+// Evaluates sqlparser expressions against a single row. Used by INSERT/UPDATE values,
+// WHERE clauses and column constraints (CHECK, DEFAULT).
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use sqlparser::ast::{BinaryOperator, Expr, FunctionArg, FunctionArgExpr, JsonOperator, UnaryOperator};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+
+use crate::Value;
+use crate::array;
+use crate::blob::Blob;
+use crate::datetime::{self, Interval, Timestamp};
+use crate::decimal::Decimal;
+use crate::json;
+use crate::types::ColumnType;
+use crate::uuid::Uuid;
+
+// Parses a stored SQL expression (e.g. a CHECK or DEFAULT saved in the catalog).
+pub fn parse_expr(sql: &str) -> Result<Expr, String> {
+    Parser::new(&GenericDialect {})
+        .try_with_sql(sql)
+        .and_then(|mut p| p.parse_expr())
+        .map_err(|e| format!("Invalid expression '{}': {}", sql, e))
+}
+
+// Converts a literal AST value into our Value.
+fn literal_to_value(v: &sqlparser::ast::Value) -> Result<Value, String> {
+    match v {
+        sqlparser::ast::Value::Number(n, _) => {
+            // 1e3 is approximate, 19.99 is an exact NUMERIC literal (as in PostgreSQL)
+            if n.contains('e') || n.contains('E') {
+                n.parse().map(Value::Float).map_err(|_| format!("Invalid number '{}'", n))
+            } else if n.contains('.') {
+                Decimal::parse(n).map(Value::Decimal)
+            } else {
+                n.parse().map(Value::Integer).map_err(|_| format!("Invalid number '{}'", n))
+            }
+        }
+        sqlparser::ast::Value::SingleQuotedString(s) => Ok(Value::Text(s.clone())),
+        sqlparser::ast::Value::HexStringLiteral(hex) => Blob::from_hex(hex).map(Value::Blob),
+        sqlparser::ast::Value::Boolean(b) => Ok(Value::Bool(*b)),
+        sqlparser::ast::Value::Null => Ok(Value::Null),
+        _ => Err("Unsupported value format".to_string()),
+    }
+}
+
+// SQL truthiness: NULL is "unknown", which is neither true nor false.
+pub fn truth(value: &Value) -> Result<Option<bool>, String> {
+    match value {
+        Value::Bool(b) => Ok(Some(*b)),
+        Value::Null => Ok(None),
+        Value::Integer(i) => Ok(Some(*i != 0)),
+        Value::Float(f) => Ok(Some(*f != 0.0)),
+        other => Err(format!("Expected a boolean, got {:?}", other)),
+    }
+}
+
+// Orders two non-NULL values. Integers, decimals and floats compare numerically.
+pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Integer(x), Value::Integer(y)) => Some(x.cmp(y)),
+        (Value::Decimal(x), Value::Decimal(y)) => Some(x.cmp(y)),
+        (Value::Decimal(x), Value::Integer(y)) => Some(x.cmp(&Decimal::from(*y))),
+        (Value::Integer(x), Value::Decimal(y)) => Some(Decimal::from(*x).cmp(y)),
+        (Value::Decimal(_), Value::Float(_)) | (Value::Float(_), Value::Decimal(_)) => as_f64(a)?.partial_cmp(&as_f64(b)?),
+        (Value::Integer(x), Value::Float(y)) => (*x as f64).partial_cmp(y),
+        (Value::Float(x), Value::Integer(y)) => x.partial_cmp(&(*y as f64)),
+        (Value::Float(x), Value::Float(y)) => x.partial_cmp(y),
+        (Value::Text(x), Value::Text(y)) => Some(x.cmp(y)),
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        (Value::Blob(x), Value::Blob(y)) => Some(x.cmp(y)),
+        (Value::Uuid(x), Value::Uuid(y)) => Some(x.cmp(y)),
+        (Value::Array(x), Value::Array(y)) => array::compare(x, y),
+        (Value::Uuid(_), Value::Text(s)) => compare_values(a, &Value::Uuid(Uuid::parse(s).ok()?)),
+        (Value::Text(s), Value::Uuid(_)) => compare_values(&Value::Uuid(Uuid::parse(s).ok()?), b),
+        // JSON documents have no natural order; unequal ones order by their serialized text,
+        // which keeps ORDER BY and indexes on a JSON column deterministic
+        (Value::Json(x), Value::Json(y)) if x == y => Some(Ordering::Equal),
+        (Value::Json(x), Value::Json(y)) => Some(x.to_string().cmp(&y.to_string())),
+        _ => datetime::compare(a, b),
+    }
+}
+
+fn column_value(row: &BTreeMap<String, Value>, name: &str) -> Result<Value, String> {
+    row.get(name).cloned().ok_or(format!("Column '{}' not found", name))
+}
+
+pub fn eval_expr(expr: &Expr, row: &BTreeMap<String, Value>) -> Result<Value, String> {
+    match expr {
+        Expr::Value(v) => literal_to_value(v),
+        Expr::Identifier(ident) => column_value(row, &ident.value),
+        Expr::CompoundIdentifier(idents) => {
+            // Try the qualified name first ("products.id" in joined rows), then the bare column.
+            let full = idents.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(".");
+            match row.get(&full) {
+                Some(v) => Ok(v.clone()),
+                None => column_value(row, &idents.last().ok_or("Empty identifier")?.value),
+            }
+        }
+        Expr::Nested(inner) => eval_expr(inner, row),
+        Expr::JsonAccess { left, operator, right } => match reassociate_json(left, operator, right) {
+            Some(fixed) => eval_expr(&fixed, row),
+            None => match (eval_expr(left, row)?, operator, as_array_operand(eval_expr(right, row)?, left, row)?) {
+                // @> and <@ share their syntax between JSON documents and arrays
+                (Value::Array(a), JsonOperator::AtArrow, Value::Array(b)) => Ok(Value::Bool(array::contains(&a, &b))),
+                (Value::Array(a), JsonOperator::ArrowAt, Value::Array(b)) => Ok(Value::Bool(array::contains(&b, &a))),
+                (l, op, r) => json::access(&l, op, &r),
+            },
+        },
+        // ARRAY[1, 2, 3]
+        Expr::Array(arr) => arr.elem.iter().map(|e| eval_expr(e, row)).collect::<Result<_, _>>().map(Value::Array),
+        Expr::ArrayIndex { obj, indexes } => {
+            let mut v = eval_expr(obj, row)?;
+            for index in indexes {
+                v = match v {
+                    Value::Null => Value::Null,
+                    Value::Array(items) => array::subscript(&items, &eval_expr(index, row)?)?,
+                    other => return Err(format!("Cannot subscript {:?}", other)),
+                };
+            }
+            Ok(v)
+        }
+        // x = ANY(arr), x > ALL(arr)
+        Expr::AnyOp { left, compare_op, right } | Expr::AllOp { left, compare_op, right } => {
+            let all = matches!(expr, Expr::AllOp { .. });
+            match (eval_expr(left, row)?, eval_expr(right, row)?) {
+                (_, Value::Null) => Ok(Value::Null),
+                (v, Value::Text(s)) => array::any_all(&v, compare_op, &array::from_text(&s)?, all),
+                (v, Value::Array(items)) => array::any_all(&v, compare_op, &items, all),
+                (_, other) => Err(format!("{} expects an array, got {:?}", if all { "ALL" } else { "ANY" }, other)),
+            }
+        }
+        Expr::UnaryOp { op, expr } => {
+            let v = eval_expr(expr, row)?;
+            match (op, v) {
+                (_, Value::Null) => Ok(Value::Null),
+                (UnaryOperator::Minus, Value::Integer(i)) => i.checked_neg().map(Value::Integer).ok_or("Integer overflow".to_string()),
+                (UnaryOperator::Minus, Value::Float(f)) => Ok(Value::Float(-f)),
+                (UnaryOperator::Minus, Value::Decimal(d)) => Ok(Value::Decimal(d.neg())),
+                (UnaryOperator::Plus, v @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_))) => Ok(v),
+                (UnaryOperator::Not, v) => Ok(truth(&v)?.map(|b| Value::Bool(!b)).unwrap_or(Value::Null)),
+                (op, v) => Err(format!("Cannot apply {} to {:?}", op, v)),
+            }
+        }
+        Expr::BinaryOp { left, op, right } => {
+            let l = eval_expr(left, row)?;
+            // AND/OR use three-valued logic, so they must see NULLs.
+            match op {
+                BinaryOperator::And => {
+                    let r = eval_expr(right, row)?;
+                    return Ok(match (truth(&l)?, truth(&r)?) {
+                        (Some(false), _) | (_, Some(false)) => Value::Bool(false),
+                        (Some(true), Some(true)) => Value::Bool(true),
+                        _ => Value::Null,
+                    });
+                }
+                BinaryOperator::Or => {
+                    let r = eval_expr(right, row)?;
+                    return Ok(match (truth(&l)?, truth(&r)?) {
+                        (Some(true), _) | (_, Some(true)) => Value::Bool(true),
+                        (Some(false), Some(false)) => Value::Bool(false),
+                        _ => Value::Null,
+                    });
+                }
+                _ => {}
+            }
+            let r = eval_expr(right, row)?;
+            binary_op(&l, op, &r)
+        }
+        Expr::IsNull(e) => Ok(Value::Bool(eval_expr(e, row)? == Value::Null)),
+        Expr::IsNotNull(e) => Ok(Value::Bool(eval_expr(e, row)? != Value::Null)),
+        Expr::IsTrue(e) => Ok(Value::Bool(truth(&eval_expr(e, row)?)? == Some(true))),
+        Expr::IsFalse(e) => Ok(Value::Bool(truth(&eval_expr(e, row)?)? == Some(false))),
+        Expr::Between { expr, negated, low, high } => {
+            let v = eval_expr(expr, row)?;
+            let lo = binary_op(&v, &BinaryOperator::GtEq, &eval_expr(low, row)?)?;
+            let hi = binary_op(&v, &BinaryOperator::LtEq, &eval_expr(high, row)?)?;
+            let both = match (truth(&lo)?, truth(&hi)?) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+            Ok(both.map(|b| Value::Bool(b != *negated)).unwrap_or(Value::Null))
+        }
+        Expr::InList { expr, list, negated } => {
+            let v = eval_expr(expr, row)?;
+            if v == Value::Null {
+                return Ok(Value::Null);
+            }
+            let mut saw_null = false;
+            for item in list {
+                let candidate = eval_expr(item, row)?;
+                if candidate == Value::Null {
+                    saw_null = true;
+                } else if compare_values(&v, &candidate) == Some(Ordering::Equal) {
+                    return Ok(Value::Bool(!*negated));
+                }
+            }
+            Ok(if saw_null { Value::Null } else { Value::Bool(*negated) })
+        }
+        Expr::Like { negated, expr, pattern, .. } => like(eval_expr(expr, row)?, eval_expr(pattern, row)?, *negated, false),
+        Expr::ILike { negated, expr, pattern, .. } => like(eval_expr(expr, row)?, eval_expr(pattern, row)?, *negated, true),
+        // DATE '2024-01-31', TIMESTAMP '2024-01-31 12:00:00', ...
+        Expr::TypedString { data_type, value } => ColumnType::from_ast(data_type)?.coerce("literal", Value::Text(value.clone())),
+        // INTERVAL '1 day' or INTERVAL '3' HOUR
+        Expr::Interval(iv) => {
+            let text = display_value(&eval_expr(&iv.value, row)?);
+            let text = match &iv.leading_field {
+                Some(field) => format!("{} {}", text, field),
+                None => text,
+            };
+            Interval::parse(&text).map(Value::Interval)
+        }
+        Expr::Extract { field, expr } => match eval_expr(expr, row)? {
+            Value::Null => Ok(Value::Null),
+            v => datetime::extract(field, &v),
+        },
+        Expr::Cast { expr, data_type, .. } => cast(eval_expr(expr, row)?, &ColumnType::from_ast(data_type)?),
+        Expr::Function(func) if func.name.to_string().eq_ignore_ascii_case("raise") => raise(&func.args, row),
+        Expr::Function(func) => {
+            let name = func.name.to_string().to_lowercase();
+            let mut args = Vec::new();
+            for arg in &func.args {
+                match arg {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => args.push(eval_expr(e, row)?),
+                    _ => return Err(format!("Unsupported argument to {}()", name)),
+                }
+            }
+            call_function(&name, &args)
+        }
+        // Scored for the whole query beforehand by fulltext::bind
+        Expr::MatchAgainst { .. } => row.get(&expr.to_string()).cloned()
+            .ok_or_else(|| "MATCH ... AGAINST is only supported in a SELECT from one table".to_string()),
+        Expr::InSubquery { .. } | Expr::Subquery(_) | Expr::Exists { .. } => {
+            Err("Subqueries are only supported as x [NOT] IN (SELECT ...) terms of WHERE".to_string())
+        }
+        _ => Err(format!("Unsupported expression: {}", expr)),
+    }
+}
+
+// `tags @> '{a,b}'`: a text right operand of an array is read as an array literal.
+fn as_array_operand(right: Value, left: &Expr, row: &BTreeMap<String, Value>) -> Result<Value, String> {
+    match &right {
+        Value::Text(s) if s.trim_start().starts_with('{') && matches!(eval_expr(left, row)?, Value::Array(_)) => {
+            array::from_text(s).map(Value::Array)
+        }
+        _ => Ok(right),
+    }
+}
+
+// sqlparser 0.39 parses everything after a JSON operator as its right operand, so
+// `doc ->> 'k' = 'v'` arrives as `doc ->> ('k' = 'v')`. JSON operators bind tighter than
+// any other operator, so pull the key back next to the operator.
+fn reassociate_json(left: &Expr, operator: &JsonOperator, right: &Expr) -> Option<Expr> {
+    let access = |key: &Expr| Box::new(Expr::JsonAccess {
+        left: Box::new(left.clone()),
+        operator: *operator,
+        right: Box::new(key.clone()),
+    });
+    Some(match right {
+        Expr::BinaryOp { left: key, op, right: rest } => Expr::BinaryOp { left: access(key), op: op.clone(), right: rest.clone() },
+        // a -> 'b' ->> 'c' chains left to right
+        Expr::JsonAccess { left: key, operator: next, right: rest } => {
+            Expr::JsonAccess { left: access(key), operator: *next, right: rest.clone() }
+        }
+        Expr::IsNull(key) => Expr::IsNull(access(key)),
+        Expr::IsNotNull(key) => Expr::IsNotNull(access(key)),
+        Expr::InList { expr: key, list, negated } => Expr::InList { expr: access(key), list: list.clone(), negated: *negated },
+        Expr::Between { expr: key, negated, low, high } => {
+            Expr::Between { expr: access(key), negated: *negated, low: low.clone(), high: high.clone() }
+        }
+        Expr::Like { negated, expr: key, pattern, escape_char } => {
+            Expr::Like { negated: *negated, expr: access(key), pattern: pattern.clone(), escape_char: *escape_char }
+        }
+        Expr::ILike { negated, expr: key, pattern, escape_char } => {
+            Expr::ILike { negated: *negated, expr: access(key), pattern: pattern.clone(), escape_char: *escape_char }
+        }
+        _ => return None,
+    })
+}
+
+pub fn binary_op(l: &Value, op: &BinaryOperator, r: &Value) -> Result<Value, String> {
+    if *l == Value::Null || *r == Value::Null {
+        return Ok(Value::Null);
+    }
+    let cmp = |pred: fn(Ordering) -> bool| -> Result<Value, String> {
+        compare_values(l, r)
+            .map(|o| Value::Bool(pred(o)))
+            .ok_or(format!("Cannot compare {:?} with {:?}", l, r))
+    };
+    match op {
+        BinaryOperator::Eq => cmp(|o| o == Ordering::Equal),
+        BinaryOperator::NotEq => cmp(|o| o != Ordering::Equal),
+        BinaryOperator::Lt => cmp(|o| o == Ordering::Less),
+        BinaryOperator::LtEq => cmp(|o| o != Ordering::Greater),
+        BinaryOperator::Gt => cmp(|o| o == Ordering::Greater),
+        BinaryOperator::GtEq => cmp(|o| o != Ordering::Less),
+        BinaryOperator::PGOverlap => match (l, r) {
+            (Value::Array(a), Value::Array(b)) => Ok(Value::Bool(array::overlaps(a, b))),
+            (Value::Array(a), Value::Text(s)) => Ok(Value::Bool(array::overlaps(a, &array::from_text(s)?))),
+            _ => Err(format!("&& expects arrays, got {:?} and {:?}", l, r)),
+        },
+        BinaryOperator::StringConcat => match (l, r) {
+            (Value::Array(a), Value::Array(b)) => Ok(Value::Array([a.as_slice(), b.as_slice()].concat())),
+            (Value::Blob(a), Value::Blob(b)) => Ok(Value::Blob(Blob([a.0.as_slice(), b.0.as_slice()].concat()))),
+            _ => Ok(Value::Text(format!("{}{}", display_value(l), display_value(r)))),
+        },
+        BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Multiply
+        | BinaryOperator::Divide | BinaryOperator::Modulo => match datetime::arithmetic(l, op, r) {
+            Some(result) => result,
+            None => arithmetic(l, op, r),
+        },
+        _ => Err(format!("Unsupported operator: {}", op)),
+    }
+}
+
+fn arithmetic(l: &Value, op: &BinaryOperator, r: &Value) -> Result<Value, String> {
+    match (l, r) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            let result = match op {
+                BinaryOperator::Plus => a.checked_add(b),
+                BinaryOperator::Minus => a.checked_sub(b),
+                BinaryOperator::Multiply => a.checked_mul(b),
+                BinaryOperator::Divide if b == 0 => return Err("Division by zero".to_string()),
+                BinaryOperator::Divide => a.checked_div(b),
+                BinaryOperator::Modulo if b == 0 => return Err("Division by zero".to_string()),
+                _ => a.checked_rem(b),
+            };
+            result.map(Value::Integer).ok_or("Integer overflow".to_string())
+        }
+        // Exact unless a float is involved
+        (Value::Integer(_) | Value::Decimal(_), Value::Integer(_) | Value::Decimal(_)) => {
+            let a = as_decimal(l).unwrap_or(Decimal::from(0));
+            let b = as_decimal(r).unwrap_or(Decimal::from(0));
+            Ok(Value::Decimal(match op {
+                BinaryOperator::Plus => a.add(b)?,
+                BinaryOperator::Minus => a.sub(b)?,
+                BinaryOperator::Multiply => a.mul(b)?,
+                BinaryOperator::Divide => a.div(b)?,
+                _ => a.rem(b)?,
+            }))
+        }
+        (Value::Integer(_) | Value::Float(_) | Value::Decimal(_), Value::Integer(_) | Value::Float(_) | Value::Decimal(_)) => {
+            let a = as_f64(l).unwrap_or(0.0);
+            let b = as_f64(r).unwrap_or(0.0);
+            Ok(Value::Float(match op {
+                BinaryOperator::Plus => a + b,
+                BinaryOperator::Minus => a - b,
+                BinaryOperator::Multiply => a * b,
+                BinaryOperator::Divide => a / b,
+                _ => a % b,
+            }))
+        }
+        _ => Err(format!("Cannot apply {} to {:?} and {:?}", op, l, r)),
+    }
+}
+
+pub fn as_f64(v: &Value) -> Option<f64> {
+    match v {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        Value::Decimal(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+fn as_decimal(v: &Value) -> Option<Decimal> {
+    match v {
+        Value::Integer(i) => Some(Decimal::from(*i)),
+        Value::Decimal(d) => Some(*d),
+        _ => None,
+    }
+}
+
+// Plain text rendering used by SELECT output and string concatenation.
+pub fn display_value(v: &Value) -> String {
+    match v {
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Decimal(d) => d.to_string(),
+        Value::Text(t) => t.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Blob(b) => b.to_string(),
+        Value::Json(j) => j.to_string(),
+        Value::Uuid(u) => u.to_string(),
+        Value::Array(items) => array::display(items),
+        Value::Date(d) => d.to_string(),
+        Value::Time(t) => t.to_string(),
+        Value::Timestamp(ts) => ts.to_string(),
+        Value::TimestampTz(ts) => format!("{}+00", ts),
+        Value::Interval(iv) => iv.to_string(),
+        Value::Null => "NULL".to_string(),
+    }
+}
+
+// The value as an SQL literal that evaluates back to it; used to bind NEW/OLD in trigger bodies.
+pub fn sql_literal(v: &Value) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let typed = |ty: &str| format!("CAST({} AS {})", quote(&display_value(v)), ty);
+    match v {
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) if f.is_finite() => format!("{:e}", f),
+        Value::Float(_) => typed("DOUBLE PRECISION"),
+        Value::Decimal(d) => d.to_string(),
+        Value::Text(t) => quote(t),
+        Value::Bool(b) => b.to_string().to_uppercase(),
+        Value::Blob(b) => format!("X'{}'", b.to_hex()),
+        Value::Json(_) => typed("JSON"),
+        Value::Uuid(_) => typed("UUID"),
+        Value::Array(items) if items.is_empty() => quote("{}"),
+        Value::Array(items) => format!("ARRAY[{}]", items.iter().map(sql_literal).collect::<Vec<_>>().join(", ")),
+        Value::Date(_) => typed("DATE"),
+        Value::Time(_) => typed("TIME"),
+        Value::Timestamp(_) => typed("TIMESTAMP"),
+        Value::TimestampTz(_) => typed("TIMESTAMP WITH TIME ZONE"),
+        Value::Interval(_) => typed("INTERVAL"),
+        Value::Null => "NULL".to_string(),
+    }
+}
+
+// The error RAISE(IGNORE) produces; the trigger that raised it skips the current row.
+pub const RAISE_IGNORE: &str = "RAISE(IGNORE)";
+
+// RAISE(ABORT | FAIL | ROLLBACK, 'message') fails the statement with the message.
+fn raise(args: &[FunctionArg], row: &BTreeMap<String, Value>) -> Result<Value, String> {
+    let exprs: Vec<&Expr> = args.iter().filter_map(|a| match a {
+        FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => Some(e),
+        _ => None,
+    }).collect();
+    match exprs.as_slice() {
+        [Expr::Identifier(kind)] if kind.value.eq_ignore_ascii_case("ignore") => Err(RAISE_IGNORE.to_string()),
+        [Expr::Identifier(kind), message] if ["abort", "fail", "rollback"].iter().any(|k| kind.value.eq_ignore_ascii_case(k)) => {
+            Err(display_value(&eval_expr(message, row)?))
+        }
+        _ => Err("RAISE expects IGNORE, or ABORT, FAIL or ROLLBACK with a message".to_string()),
+    }
+}
+
+// CAST(value AS type): explicit conversions on top of the implicit ones in `ColumnType::coerce`.
+pub fn cast(value: Value, target: &ColumnType) -> Result<Value, String> {
+    let bad = |v: &Value| format!("Cannot cast {:?} to {}", v, target);
+    let converted = match (target, value) {
+        (_, Value::Null) => Value::Null,
+        (ColumnType::Text | ColumnType::Varchar(_) | ColumnType::Char(_), v) => Value::Text(display_value(&v)),
+        (ColumnType::SmallInt | ColumnType::Integer | ColumnType::BigInt, v) => match v {
+            Value::Float(f) => Value::Integer(f.round() as i64),
+            Value::Decimal(d) => Value::Integer(d.round_to_integer()?),
+            Value::Bool(b) => Value::Integer(b as i64),
+            Value::Text(ref s) => Value::Integer(s.trim().parse().map_err(|_| bad(&v))?),
+            v => v,
+        },
+        (ColumnType::Float, Value::Text(s)) => {
+            Value::Float(s.trim().parse().map_err(|_| format!("Cannot cast '{}' to {}", s, target))?)
+        }
+        (ColumnType::Numeric { .. }, Value::Text(s)) => Value::Decimal(Decimal::parse(&s)?),
+        (ColumnType::Bool, Value::Integer(i)) => Value::Bool(i != 0),
+        (ColumnType::Bool, Value::Text(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "1" | "on" => Value::Bool(true),
+            "false" | "f" | "no" | "n" | "0" | "off" => Value::Bool(false),
+            _ => return Err(format!("Cannot cast '{}' to {}", s, target)),
+        },
+        (_, v) => v,
+    };
+    target.coerce("value", converted)
+}
+
+fn like(v: Value, p: Value, negated: bool, case_insensitive: bool) -> Result<Value, String> {
+    match (v, p) {
+        (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+        (Value::Text(s), Value::Text(p)) if case_insensitive => {
+            Ok(Value::Bool(like_match(&s.to_lowercase(), &p.to_lowercase()) != negated))
+        }
+        (Value::Text(s), Value::Text(p)) => Ok(Value::Bool(like_match(&s, &p) != negated)),
+        _ => Err("LIKE requires text operands".to_string()),
+    }
+}
+
+// SQL LIKE: `%` matches any run of characters, `_` matches exactly one.
+fn like_match(s: &str, pattern: &str) -> bool {
+    let s: Vec<char> = s.chars().collect();
+    let p: Vec<char> = pattern.chars().collect();
+    let (mut si, mut pi) = (0, 0);
+    let (mut star, mut mark) = (None, 0);
+    while si < s.len() {
+        if pi < p.len() && (p[pi] == '_' || p[pi] == s[si]) {
+            si += 1;
+            pi += 1;
+        } else if pi < p.len() && p[pi] == '%' {
+            star = Some(pi);
+            mark = si;
+            pi += 1;
+        } else if let Some(st) = star {
+            pi = st + 1;
+            mark += 1;
+            si = mark;
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '%' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+fn call_function(name: &str, args: &[Value]) -> Result<Value, String> {
+    let arity = |n: usize| -> Result<(), String> {
+        if args.len() == n { Ok(()) } else { Err(format!("{}() expects {} argument(s)", name, n)) }
+    };
+    match name {
+        "now" | "current_timestamp" => Ok(Value::TimestampTz(Timestamp::now())),
+        "localtimestamp" => Ok(Value::Timestamp(Timestamp::now())),
+        "current_date" => Ok(Value::Date(Timestamp::now().date())),
+        "current_time" | "localtime" => Ok(Value::Time(Timestamp::now().time())),
+        "date_trunc" | "date_part" => {
+            arity(2)?;
+            let field = match &args[0] {
+                Value::Text(f) => datetime::field_from_name(f)?,
+                other => return Err(format!("{}() expects a field name, got {:?}", name, other)),
+            };
+            match (&args[1], name) {
+                (Value::Null, _) => Ok(Value::Null),
+                (v, "date_trunc") => datetime::date_trunc(&field, v),
+                (v, _) => datetime::extract(&field, v),
+            }
+        }
+        "strftime" => {
+            arity(2)?;
+            match (&args[0], &args[1]) {
+                (_, Value::Null) | (Value::Null, _) => Ok(Value::Null),
+                (Value::Text(fmt), v) => datetime::strftime(fmt, v),
+                (other, _) => Err(format!("strftime() expects a format string, got {:?}", other)),
+            }
+        }
+        "date" | "time" | "timestamp" => {
+            arity(1)?;
+            let target = match name {
+                "date" => ColumnType::Date,
+                "time" => ColumnType::Time,
+                _ => ColumnType::Timestamp,
+            };
+            cast(args[0].clone(), &target)
+        }
+        "round" => {
+            let places = match args {
+                [_] => 0,
+                [_, Value::Integer(n)] => *n,
+                _ => return Err("round() expects a number and optional decimal places".to_string()),
+            };
+            match &args[0] {
+                Value::Null => Ok(Value::Null),
+                Value::Integer(i) => Ok(Value::Integer(*i)),
+                Value::Decimal(d) => Ok(Value::Decimal(d.rescale(places.clamp(0, crate::decimal::MAX_DIGITS as i64) as u32)?)),
+                Value::Float(f) => {
+                    let factor = 10f64.powi(places as i32);
+                    Ok(Value::Float((f * factor).round() / factor))
+                }
+                v => Err(format!("round() does not accept {:?}", v)),
+            }
+        }
+        "octet_length" | "hex" => {
+            arity(1)?;
+            let bytes = match &args[0] {
+                Value::Null => return Ok(Value::Null),
+                Value::Blob(b) => b.0.as_slice(),
+                Value::Text(s) => s.as_bytes(),
+                v => return Err(format!("{}() does not accept {:?}", name, v)),
+            };
+            Ok(match name {
+                "octet_length" => Value::Integer(bytes.len() as i64),
+                _ => Value::Text(Blob(bytes.to_vec()).to_hex().to_uppercase()),
+            })
+        }
+        // encode(bytes, 'hex' | 'base64') -> text and decode(text, 'hex' | 'base64') -> bytes
+        "encode" | "decode" => {
+            arity(2)?;
+            match (name, &args[0], &args[1]) {
+                (_, Value::Null, _) | (_, _, Value::Null) => Ok(Value::Null),
+                ("encode", Value::Blob(b), Value::Text(f)) if f.eq_ignore_ascii_case("hex") => Ok(Value::Text(b.to_hex())),
+                ("encode", Value::Blob(b), Value::Text(f)) if f.eq_ignore_ascii_case("base64") => Ok(Value::Text(b.to_base64())),
+                ("decode", Value::Text(s), Value::Text(f)) if f.eq_ignore_ascii_case("hex") => Blob::from_hex(s).map(Value::Blob),
+                ("decode", Value::Text(s), Value::Text(f)) if f.eq_ignore_ascii_case("base64") => Blob::from_base64(s).map(Value::Blob),
+                _ => Err(format!("{}() expects {} and a format of 'hex' or 'base64'", name, if name == "encode" { "bytes" } else { "text" })),
+            }
+        }
+        "json_extract" => {
+            arity(2)?;
+            match (&args[0], &args[1]) {
+                (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                (doc, Value::Text(path)) => json::extract(doc, path),
+                (_, other) => Err(format!("json_extract() expects a path, got {:?}", other)),
+            }
+        }
+        "json_array_length" => match args {
+            [Value::Null] | [Value::Null, _] | [_, Value::Null] => Ok(Value::Null),
+            [doc] => json::array_length(doc, None),
+            [doc, Value::Text(path)] => json::array_length(doc, Some(path)),
+            _ => Err("json_array_length() expects a document and an optional path".to_string()),
+        },
+        "json" | "json_valid" => {
+            arity(1)?;
+            match (name, &args[0]) {
+                (_, Value::Null) => Ok(Value::Null),
+                ("json", v) => json::as_json(v).map(|j| Value::Json(json::Json(j))),
+                (_, v) => Ok(Value::Bool(json::as_json(v).is_ok())),
+            }
+        }
+        "gen_random_uuid" | "uuid_generate_v4" | "uuidv4" => {
+            arity(0)?;
+            Ok(Value::Uuid(Uuid::new_v4()))
+        }
+        "uuidv7" | "uuid_generate_v7" => {
+            arity(0)?;
+            Ok(Value::Uuid(Uuid::new_v7()))
+        }
+        "cardinality" | "array_length" => {
+            match (args, name) {
+                ([Value::Null], _) | ([Value::Null, _], _) => Ok(Value::Null),
+                ([Value::Array(items)], "cardinality") | ([Value::Array(items), Value::Integer(1)], "array_length") => {
+                    // Like PostgreSQL, the length of an empty array is NULL for array_length
+                    Ok(if items.is_empty() && name == "array_length" { Value::Null } else { Value::Integer(items.len() as i64) })
+                }
+                ([Value::Array(_), _], "array_length") => Ok(Value::Null),
+                _ => Err(format!("{}() expects an array{}", name, if name == "array_length" { " and a dimension" } else { "" })),
+            }
+        }
+        "array_append" => {
+            arity(2)?;
+            match &args[0] {
+                Value::Null => Ok(Value::Array(vec![args[1].clone()])),
+                Value::Array(items) => Ok(Value::Array([items.as_slice(), &args[1..]].concat())),
+                other => Err(format!("array_append() expects an array, got {:?}", other)),
+            }
+        }
+        // Bound to values by `sequence::bind` wherever a statement may advance a sequence
+        "nextval" | "currval" | "setval" => Err(format!("{}() cannot be used here", name)),
+        "coalesce" => Ok(args.iter().find(|v| **v != Value::Null).cloned().unwrap_or(Value::Null)),
+        "lower" | "upper" | "length" | "trim" | "abs" => {
+            arity(1)?;
+            match (name, &args[0]) {
+                (_, Value::Null) => Ok(Value::Null),
+                ("lower", Value::Text(s)) => Ok(Value::Text(s.to_lowercase())),
+                ("upper", Value::Text(s)) => Ok(Value::Text(s.to_uppercase())),
+                ("trim", Value::Text(s)) => Ok(Value::Text(s.trim().to_string())),
+                ("length", Value::Text(s)) => Ok(Value::Integer(s.chars().count() as i64)),
+                ("length", Value::Blob(b)) => Ok(Value::Integer(b.0.len() as i64)),
+                ("abs", Value::Integer(i)) => i.checked_abs().map(Value::Integer).ok_or("Integer overflow".to_string()),
+                ("abs", Value::Float(f)) => Ok(Value::Float(f.abs())),
+                ("abs", Value::Decimal(d)) => Ok(Value::Decimal(d.abs())),
+                (_, v) => Err(format!("{}() does not accept {:?}", name, v)),
+            }
+        }
+        _ => Err(format!("Unknown function: {}()", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(sql: &str, x: Value) -> Result<Value, String> {
+        eval_expr(&parse_expr(sql)?, &BTreeMap::from([("x".to_string(), x)]))
+    }
+
+    #[test]
+    fn negating_the_smallest_integer_overflows() {
+        assert_eq!(eval("-x", Value::Integer(i64::MIN)), Err("Integer overflow".to_string()));
+        assert_eq!(eval("abs(x)", Value::Integer(i64::MIN)), Err("Integer overflow".to_string()));
+        assert_eq!(eval("-x", Value::Integer(i64::MAX)), Ok(Value::Integer(-i64::MAX)));
+        assert_eq!(eval("abs(x)", Value::Integer(-3)), Ok(Value::Integer(3)));
+    }
+
+    #[test]
+    fn json_documents_order_by_their_text() {
+        let doc = |s: &str| Value::Json(crate::json::Json::parse(s).unwrap());
+        assert_eq!(compare_values(&doc(r#"{"a": 1}"#), &doc(r#"{"a":1}"#)), Some(Ordering::Equal));
+        assert_eq!(compare_values(&doc("[1, 2]"), &doc("[1, 3]")), Some(Ordering::Less));
+    }
+}
@@ -0,0 +1,329 @@
+// --- FOREIGN KEYS ---
+// Referential integrity between tables: child-side checks on INSERT/UPDATE and
+// parent-side actions (CASCADE, SET NULL, ...) on DELETE/UPDATE.
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::ReferentialAction;
+
+use crate::{Database, Row, Table, Value};
+
+// An updated row as (before, after).
+pub type RowChange = (BTreeMap<String, Value>, BTreeMap<String, Value>);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RefAction {
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+impl RefAction {
+    pub fn from_ast(action: &Option<ReferentialAction>) -> Self {
+        match action {
+            None | Some(ReferentialAction::NoAction) => RefAction::NoAction,
+            Some(ReferentialAction::Restrict) => RefAction::Restrict,
+            Some(ReferentialAction::Cascade) => RefAction::Cascade,
+            Some(ReferentialAction::SetNull) => RefAction::SetNull,
+            Some(ReferentialAction::SetDefault) => RefAction::SetDefault,
+        }
+    }
+
+    pub fn sql(self) -> &'static str {
+        match self {
+            RefAction::NoAction => "NO ACTION",
+            RefAction::Restrict => "RESTRICT",
+            RefAction::Cascade => "CASCADE",
+            RefAction::SetNull => "SET NULL",
+            RefAction::SetDefault => "SET DEFAULT",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub ref_table: String,
+    pub ref_columns: Vec<String>,
+    pub on_delete: RefAction,
+    pub on_update: RefAction,
+}
+
+impl ForeignKey {
+    // The referencing values of a child row, or None if any of them is NULL
+    // (MATCH SIMPLE: a partially NULL key is not checked).
+    fn child_key(&self, row: &BTreeMap<String, Value>) -> Option<Vec<Value>> {
+        key_of(&self.columns, row)
+    }
+}
+
+fn key_of(cols: &[String], row: &BTreeMap<String, Value>) -> Option<Vec<Value>> {
+    let mut key = Vec::new();
+    for col in cols {
+        match row.get(col) {
+            None | Some(Value::Null) => return None,
+            Some(v) => key.push(v.clone()),
+        }
+    }
+    Some(key)
+}
+
+fn describe(key: &[Value]) -> String {
+    key.iter().map(|v| format!("{:?}", v)).collect::<Vec<_>>().join(", ")
+}
+
+// Does the parent table hold a row whose referenced columns equal `key`?
+fn parent_has_key(db: &Database, fk: &ForeignKey, key: &[Value]) -> Result<bool, String> {
+    let parent = db.tables.get(&fk.ref_table).ok_or(format!("Table '{}' not found", fk.ref_table))?;
+    let matches = |row: &Row| key_of(&fk.ref_columns, &row.data).as_deref() == Some(key);
+    // The referenced columns are a PRIMARY KEY or UNIQUE key, so an index usually holds them
+    Ok(match parent.indexes.iter().find(|i| i.predicate.is_none() && i.columns == fk.ref_columns) {
+        Some(index) => index.get(key).filter_map(|id| parent.data.get(&id)).any(matches),
+        None => parent.data.values().any(matches),
+    })
+}
+
+// Child side: every foreign key of `table_name` must point at an existing parent row.
+// Skipped while checks are deferred; COMMIT runs `check_all` instead.
+pub fn check_references(db: &Database, table_name: &str, row: &BTreeMap<String, Value>) -> Result<(), String> {
+    if db.foreign_keys_deferred() {
+        return Ok(());
+    }
+    let table = db.tables.get(table_name).ok_or(format!("Table '{}' not found", table_name))?;
+    for fk in &table.foreign_keys {
+        check_one(db, table_name, fk, row)?;
+    }
+    Ok(())
+}
+
+fn check_one(db: &Database, table_name: &str, fk: &ForeignKey, row: &BTreeMap<String, Value>) -> Result<(), String> {
+    if let Some(key) = fk.child_key(row)
+        && !parent_has_key(db, fk, &key)?
+    {
+        return Err(format!("FOREIGN KEY constraint '{}' failed: {}({}) = ({}) has no match in {}({})",
+            fk.name, table_name, fk.columns.join(", "), describe(&key), fk.ref_table, fk.ref_columns.join(", ")));
+    }
+    Ok(())
+}
+
+// Verifies every foreign key in the database. Used at COMMIT for deferred checks.
+pub fn check_all(db: &Database) -> Result<(), String> {
+    for (table_name, table) in &db.tables {
+        for fk in &table.foreign_keys {
+            for row in table.data.values() {
+                check_one(db, table_name, fk, &row.data)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// All (child table, foreign key) pairs that reference `parent`.
+fn referencing(db: &Database, parent: &str) -> Vec<(String, ForeignKey)> {
+    let mut out = Vec::new();
+    for (name, table) in &db.tables {
+        for fk in &table.foreign_keys {
+            if fk.ref_table == parent {
+                out.push((name.clone(), fk.clone()));
+            }
+        }
+    }
+    out
+}
+
+// Parent side of DELETE: apply each referencing key's ON DELETE action to the
+// children of the rows that were just removed from `parent`.
+pub fn on_parent_delete(db: &mut Database, parent: &str, deleted: &[BTreeMap<String, Value>]) -> Result<(), String> {
+    for (child_name, fk) in referencing(db, parent) {
+        for parent_row in deleted {
+            let Some(key) = key_of(&fk.ref_columns, parent_row) else { continue };
+            apply_action(db, &child_name, &fk, fk.on_delete, &key, None)?;
+        }
+    }
+    Ok(())
+}
+
+// Parent side of UPDATE: children of rows whose referenced key changed follow ON UPDATE.
+pub fn on_parent_update(db: &mut Database, parent: &str, changes: &[RowChange]) -> Result<(), String> {
+    for (child_name, fk) in referencing(db, parent) {
+        for (old_row, new_row) in changes {
+            let Some(old_key) = key_of(&fk.ref_columns, old_row) else { continue };
+            let new_key: Vec<Value> = fk.ref_columns.iter().map(|c| new_row.get(c).cloned().unwrap_or(Value::Null)).collect();
+            if old_key == new_key {
+                continue;
+            }
+            apply_action(db, &child_name, &fk, fk.on_update, &old_key, Some(&new_key))?;
+        }
+    }
+    Ok(())
+}
+
+// The rows of `child` whose foreign key columns hold `key`. An index leading with those columns
+// (in any order, and without a WHERE) answers this with a lookup; without one every child row
+// is read, so deleting or rekeying n parent rows costs n full scans of the child table.
+fn child_rows(child: &Table, fk: &ForeignKey, key: &[Value]) -> Vec<u64> {
+    let n = fk.columns.len();
+    let index = child.indexes.iter().find(|i| {
+        i.predicate.is_none() && i.columns.len() >= n && i.columns[..n].iter().all(|c| fk.columns.contains(c))
+    });
+    let candidates: Vec<u64> = match index {
+        Some(index) => {
+            let prefix: Vec<Value> = index.columns[..n].iter()
+                .map(|c| key[fk.columns.iter().position(|f| f == c).unwrap()].clone())
+                .collect();
+            index.scan(&prefix, Bound::Unbounded, Bound::Unbounded).into_iter().collect()
+        }
+        None => child.data.keys().copied().collect(),
+    };
+    // The index compares keys loosely (1 = 1.0); the foreign key matches exact values
+    candidates.into_iter()
+        .filter(|id| child.data.get(id).is_some_and(|row| fk.child_key(&row.data).as_deref() == Some(key)))
+        .collect()
+}
+
+// Applies a referential action to the child rows currently pointing at `old_key`.
+// `new_key` is Some for ON UPDATE CASCADE, where children follow the parent's new key.
+fn apply_action(db: &mut Database, child_name: &str, fk: &ForeignKey, action: RefAction, old_key: &[Value], new_key: Option<&[Value]>) -> Result<(), String> {
+    let child = db.tables.get(child_name).ok_or(format!("Table '{}' not found", child_name))?;
+    let matches = child_rows(child, fk, old_key);
+    if matches.is_empty() {
+        return Ok(());
+    }
+
+    match action {
+        // NO ACTION may be deferred to COMMIT; RESTRICT never is.
+        RefAction::NoAction if db.foreign_keys_deferred() => Ok(()),
+        RefAction::NoAction | RefAction::Restrict => Err(format!(
+            "FOREIGN KEY constraint '{}' failed: {} row(s) in '{}' still reference {}({}) = ({})",
+            fk.name, matches.len(), child_name, fk.ref_table, fk.ref_columns.join(", "), describe(old_key))),
+        RefAction::Cascade if new_key.is_none() => {
+            let child = db.tables.get_mut(child_name).ok_or(format!("Table '{}' not found", child_name))?;
+            let mut removed = Vec::new();
+            for id in &matches {
+                if let Some(row) = child.take_row(*id)? {
+                    removed.push(row.data);
+                }
+            }
+            on_parent_delete(db, child_name, &removed)
+        }
+        RefAction::Cascade | RefAction::SetNull | RefAction::SetDefault => {
+            let child = db.tables.get(child_name).ok_or(format!("Table '{}' not found", child_name))?;
+            let mut changes = Vec::new();
+            for id in &matches {
+                let old_row = child.data[id].data.clone();
+                let mut new_row = old_row.clone();
+                for (i, col) in fk.columns.iter().enumerate() {
+                    let value = match (action, new_key) {
+                        (RefAction::Cascade, Some(key)) => key[i].clone(),
+                        (RefAction::SetDefault, _) => child.default_value(col)?,
+                        _ => Value::Null,
+                    };
+                    new_row.insert(col.clone(), value);
+                }
+                child.check_constraints(&new_row)?;
+                child.check_primary_key(&new_row, Some(*id))?;
+                child.check_unique(&new_row, Some(*id))?;
+                changes.push((*id, old_row, new_row));
+            }
+            // SET DEFAULT must still land on an existing parent row
+            if action == RefAction::SetDefault {
+                for (_, _, new_row) in &changes {
+                    check_one(db, child_name, fk, new_row)?;
+                }
+            }
+            let child = db.tables.get_mut(child_name).ok_or(format!("Table '{}' not found", child_name))?;
+            for (id, _, new_row) in &changes {
+                if child.data.contains_key(id) {
+                    child.put_row(*id, new_row.clone())?;
+                }
+            }
+            let changed: Vec<_> = changes.into_iter().map(|(_, old, new)| (old, new)).collect();
+            on_parent_update(db, child_name, &changed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{run, setup};
+
+    #[test]
+    fn children_must_reference_an_existing_parent() {
+        let mut db = setup("
+            CREATE TABLE cat (id INT PRIMARY KEY);
+            CREATE TABLE prod (id INT PRIMARY KEY, cat_id INT REFERENCES cat(id));
+            INSERT INTO cat VALUES (1);
+        ");
+        assert_eq!(
+            run(&mut db, "INSERT INTO prod VALUES (10, 9)"),
+            Err("FOREIGN KEY constraint 'prod_cat_id_fkey' failed: prod(cat_id) = (Integer(9)) has no match in cat(id)".to_string())
+        );
+        // NULL references nothing
+        run(&mut db, "INSERT INTO prod VALUES (10, NULL)").unwrap();
+        assert!(run(&mut db, "UPDATE prod SET cat_id = 9 WHERE id = 10").is_err());
+        run(&mut db, "UPDATE prod SET cat_id = 1 WHERE id = 10").unwrap();
+    }
+
+    #[test]
+    fn parent_changes_run_the_referential_actions() {
+        let mut db = setup("
+            CREATE TABLE cat (id INT PRIMARY KEY);
+            CREATE TABLE prod (id INT PRIMARY KEY, cat_id INT REFERENCES cat(id) ON DELETE CASCADE ON UPDATE CASCADE);
+            CREATE TABLE tag (id INT PRIMARY KEY, cat_id INT, FOREIGN KEY (cat_id) REFERENCES cat(id) ON DELETE SET NULL);
+            CREATE TABLE pin (id INT PRIMARY KEY, cat_id INT REFERENCES cat(id));
+            INSERT INTO cat VALUES (1), (2), (3);
+            INSERT INTO prod VALUES (10, 1), (11, 2);
+            INSERT INTO tag VALUES (20, 1);
+            INSERT INTO pin VALUES (30, 3);
+        ");
+        run(&mut db, "UPDATE cat SET id = 5 WHERE id = 2").unwrap();
+        run(&mut db, "DELETE FROM cat WHERE id = 1").unwrap();
+        assert_eq!(run(&mut db, "SELECT id, cat_id FROM prod"), Ok("ID | id | cat_id\n2  | 11 | 5".to_string()));
+        assert_eq!(run(&mut db, "SELECT id, cat_id FROM tag"), Ok("ID | id | cat_id\n1  | 20 | NULL".to_string()));
+        // The default action refuses to orphan a child
+        assert_eq!(
+            run(&mut db, "DELETE FROM cat WHERE id = 3"),
+            Err("FOREIGN KEY constraint 'pin_cat_id_fkey' failed: 1 row(s) in 'pin' still reference cat(id) = (Integer(3))".to_string())
+        );
+    }
+
+    #[test]
+    fn deferred_checks_wait_for_commit() {
+        let mut db = setup("CREATE TABLE cat (id INT PRIMARY KEY); CREATE TABLE pin (id INT PRIMARY KEY, cat_id INT REFERENCES cat(id))");
+        run(&mut db, "BEGIN; PRAGMA defer_foreign_keys = 1; INSERT INTO pin VALUES (31, 4); INSERT INTO cat VALUES (4); COMMIT").unwrap();
+        assert_eq!(run(&mut db, "SELECT id FROM pin"), Ok("ID | id\n1  | 31".to_string()));
+        run(&mut db, "BEGIN; PRAGMA defer_foreign_keys = 1; INSERT INTO pin VALUES (32, 8)").unwrap();
+        assert!(run(&mut db, "COMMIT").is_err());
+        assert_eq!(run(&mut db, "SELECT id FROM pin"), Ok("ID | id\n1  | 31".to_string()));
+    }
+
+    #[test]
+    fn deleting_many_parents_finds_the_children_with_or_without_an_index() {
+        let mut db = setup("
+            CREATE TABLE cat (id INT PRIMARY KEY, shop INT, UNIQUE (id, shop));
+            CREATE TABLE prod (id INT PRIMARY KEY, cat_id INT REFERENCES cat(id) ON DELETE CASCADE);
+            CREATE TABLE item (id INT PRIMARY KEY, shop INT, cat_id INT, FOREIGN KEY (cat_id, shop) REFERENCES cat(id, shop) ON DELETE CASCADE);
+            CREATE INDEX ON item (shop, cat_id);
+        ");
+        for i in 1..=300 {
+            run(&mut db, &format!("INSERT INTO cat VALUES ({i}, {}); INSERT INTO prod VALUES ({i}, {i}), ({}, {i}); INSERT INTO item VALUES ({i}, {}, {i})", i % 3, 1000 + i, i % 3)).unwrap();
+        }
+        // item's index leads with its foreign key columns, in another order; prod has none
+        let fk = db.tables["item"].foreign_keys[0].clone();
+        assert_eq!(child_rows(&db.tables["item"], &fk, &[Value::Integer(7), Value::Integer(1)]), vec![7]);
+        run(&mut db, "DELETE FROM cat WHERE shop = 1").unwrap();
+        assert_eq!(run(&mut db, "SELECT COUNT(*) FROM prod"), Ok("COUNT(*)\n400".to_string()));
+        assert_eq!(run(&mut db, "SELECT COUNT(*) FROM item"), Ok("COUNT(*)\n200".to_string()));
+        assert_eq!(run(&mut db, "SELECT id FROM item WHERE cat_id = 7"), Ok("ID | id".to_string()));
+        assert_eq!(child_rows(&db.tables["item"], &fk, &[Value::Integer(8), Value::Integer(2)]), vec![8]);
+        // The lookup reads only the index: emptied, it finds no children
+        let item = db.tables.get_mut("item").unwrap();
+        item.indexes.iter_mut().find(|i| i.columns == ["shop", "cat_id"]).unwrap().entries.clear();
+        assert!(child_rows(&db.tables["item"], &fk, &[Value::Integer(8), Value::Integer(2)]).is_empty());
+    }
+}
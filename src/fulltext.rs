@@ -0,0 +1,510 @@
+// --- FULL-TEXT SEARCH ---
+// CREATE INDEX [name] ON docs USING fulltext (title, body) keeps an inverted index: each word of
+// the indexed columns maps to the rows holding it and the word's positions there. Only its
+// definition is saved: the postings are rebuilt from the rows on load (Table::rebuild_indexes)
+// and kept current by Table::put_row and Table::take_row.
+// MATCH (title, body) AGAINST ('query') is a row's BM25 relevance for the query, 0 when the row
+// does not match, so the same expression filters in WHERE and ranks in ORDER BY.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{Expr, Ident, SearchModifier};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::tokenizer::{Token, Tokenizer};
+
+use crate::expr::{display_value, parse_expr};
+use crate::index::{taken, CreateIndex};
+use crate::sequence::{closing_paren, token_sql};
+use crate::{object_name, Database, Row, Table, Value};
+
+// BM25 parameters: term frequency saturation and document length normalisation
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FullTextIndex {
+    pub name: String,
+    pub columns: Vec<String>,
+    // Word -> rowid -> positions of the word in that row's text. Not saved, like Index::entries.
+    #[serde(skip)]
+    postings: BTreeMap<String, BTreeMap<u64, Vec<u32>>>,
+    // Rowid -> number of words, for BM25's length normalisation. Rows without words are left out.
+    #[serde(skip)]
+    lengths: BTreeMap<u64, u32>,
+}
+
+// Lowercased runs of letters and digits; everything else separates words.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+impl FullTextIndex {
+    // The words of a row's indexed columns with their positions, and how many there are.
+    // A gap between columns keeps a phrase from matching across two of them.
+    fn terms(&self, row: &BTreeMap<String, Value>) -> (BTreeMap<String, Vec<u32>>, u32) {
+        let mut terms: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        let mut position = 0;
+        let mut length = 0;
+        for col in &self.columns {
+            let text = match row.get(col) {
+                None | Some(Value::Null) => continue,
+                Some(Value::Text(s)) => Cow::Borrowed(s.as_str()),
+                Some(other) => Cow::Owned(display_value(other)),
+            };
+            for word in words(&text) {
+                terms.entry(word).or_default().push(position);
+                position += 1;
+                length += 1;
+            }
+            position += 1;
+        }
+        (terms, length)
+    }
+
+    pub fn fill(&mut self, table: &Table) -> Result<(), String> {
+        for row in table.data.values() {
+            let row = table.read_row(row)?;
+            self.add(row.id, &row.data);
+        }
+        Ok(())
+    }
+
+    // `row` is the row as readers see it, VIRTUAL columns included.
+    pub fn add(&mut self, id: u64, row: &BTreeMap<String, Value>) {
+        let (terms, length) = self.terms(row);
+        if length == 0 {
+            return;
+        }
+        for (word, positions) in terms {
+            self.postings.entry(word).or_default().insert(id, positions);
+        }
+        self.lengths.insert(id, length);
+    }
+
+    pub fn remove(&mut self, id: u64, row: &BTreeMap<String, Value>) {
+        if self.lengths.remove(&id).is_none() {
+            return;
+        }
+        for word in self.terms(row).0.into_keys() {
+            if let Some(rows) = self.postings.get_mut(&word) {
+                rows.remove(&id);
+                if rows.is_empty() {
+                    self.postings.remove(&word);
+                }
+            }
+        }
+    }
+
+    // Rowids matching a search.
+    fn rows(&self, search: &Search) -> BTreeSet<u64> {
+        match search {
+            Search::Word(word) => self.postings.get(word).map(|rows| rows.keys().copied().collect()).unwrap_or_default(),
+            Search::Prefix(prefix) => self.postings.range(prefix.clone()..)
+                .take_while(|(word, _)| word.starts_with(prefix.as_str()))
+                .flat_map(|(_, rows)| rows.keys().copied())
+                .collect(),
+            Search::Phrase(phrase) => {
+                let Some(first) = self.postings.get(&phrase[0]) else { return BTreeSet::new() };
+                // Rows where each following word sits right after the one before it
+                let follows = |id: u64, start: u32| phrase[1..].iter().zip(1..).all(|(word, offset)| {
+                    self.postings.get(word).and_then(|rows| rows.get(&id)).is_some_and(|p| p.binary_search(&(start + offset)).is_ok())
+                });
+                first.iter()
+                    .filter(|(id, starts)| starts.iter().any(|start| follows(**id, *start)))
+                    .map(|(id, _)| *id)
+                    .collect()
+            }
+            Search::And(a, b) => match (&**a, &**b) {
+                (x, Search::Not(y)) | (Search::Not(y), x) => self.rows(x).difference(&self.rows(y)).copied().collect(),
+                (x, y) => self.rows(x).intersection(&self.rows(y)).copied().collect(),
+            },
+            Search::Or(a, b) => self.rows(a).union(&self.rows(b)).copied().collect(),
+            Search::Not(x) => {
+                let excluded = self.rows(x);
+                self.lengths.keys().filter(|id| !excluded.contains(id)).copied().collect()
+            }
+        }
+    }
+
+    // The indexed words a search scores by: those it asks for, not those it excludes.
+    fn scored_words(&self, search: &Search, out: &mut BTreeSet<String>) {
+        match search {
+            Search::Word(word) => {
+                out.insert(word.clone());
+            }
+            Search::Prefix(prefix) => out.extend(self.postings.range(prefix.clone()..)
+                .take_while(|(word, _)| word.starts_with(prefix.as_str()))
+                .map(|(word, _)| word.clone())),
+            Search::Phrase(phrase) => out.extend(phrase.iter().cloned()),
+            Search::And(a, b) | Search::Or(a, b) => {
+                self.scored_words(a, out);
+                self.scored_words(b, out);
+            }
+            Search::Not(_) => {}
+        }
+    }
+
+    // BM25 relevance of every matching row. A matching row always scores above 0.
+    fn scores(&self, search: &Search) -> BTreeMap<u64, f64> {
+        let mut scored = BTreeSet::new();
+        self.scored_words(search, &mut scored);
+        let n = self.lengths.len() as f64;
+        let average = self.lengths.values().map(|&l| l as f64).sum::<f64>() / n.max(1.0);
+        self.rows(search).into_iter().map(|id| {
+            let length = self.lengths.get(&id).copied().unwrap_or(0) as f64;
+            let score = scored.iter().filter_map(|word| {
+                let rows = self.postings.get(word)?;
+                let tf = rows.get(&id)?.len() as f64;
+                let df = rows.len() as f64;
+                let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                Some(idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length / average)))
+            }).sum();
+            (id, score)
+        }).collect()
+    }
+
+    pub fn sql(&self, table: &str) -> String {
+        format!("CREATE INDEX {} ON {} USING fulltext ({})", self.name, table, self.columns.join(", "))
+    }
+}
+
+// CREATE INDEX [IF NOT EXISTS] [name] ON table USING fulltext (col, ...)
+pub fn create(db: &mut Database, def: CreateIndex) -> Result<String, String> {
+    let table_name = object_name(def.table_name);
+    let table = db.tables.get(&table_name).ok_or(format!("Table '{}' not found", table_name))?;
+    if def.unique || def.predicate.is_some() || def.extras {
+        return Err("A full-text index cannot be UNIQUE, partial, or have INCLUDE or NULLS [NOT] DISTINCT".to_string());
+    }
+    let mut columns: Vec<String> = Vec::new();
+    for item in def.columns {
+        let Expr::Identifier(ident) = &item.expr else {
+            return Err(format!("A full-text index covers columns, not expressions like '{}'", item.expr));
+        };
+        if item.asc.is_some() || item.nulls_first.is_some() {
+            return Err("ASC, DESC and NULLS FIRST / LAST do not apply to a full-text index".to_string());
+        }
+        if !table.columns.iter().any(|(c, _)| *c == ident.value) {
+            return Err(format!("Column '{}' not found in table '{}'", ident.value, table_name));
+        }
+        if columns.contains(&ident.value) {
+            return Err(format!("'{}' is listed more than once", ident.value));
+        }
+        columns.push(ident.value.clone());
+    }
+    let name = match def.name {
+        Some(name) => name.to_string(),
+        None => {
+            let base = format!("{}_{}_fts", crate::schema::split(&table_name).1, columns.join("_"));
+            (0..).map(|i| if i == 0 { base.clone() } else { format!("{}{}", base, i) })
+                .find(|name| !taken(db, name))
+                .unwrap_or(base)
+        }
+    };
+    if taken(db, &name) {
+        return if def.if_not_exists {
+            Ok(format!("Index '{}' already exists, skipping", name))
+        } else {
+            Err(format!("Index '{}' already exists", name))
+        };
+    }
+
+    let mut index = FullTextIndex { name: name.clone(), columns, postings: BTreeMap::new(), lengths: BTreeMap::new() };
+    index.fill(table)?;
+    let count = index.lengths.len();
+    db.tables.get_mut(&table_name).ok_or(format!("Table '{}' not found", table_name))?.fulltext.push(index);
+    Ok(format!("Full-text index '{}' created on '{}' ({} rows)", name, table_name, count))
+}
+
+// --- SEARCH QUERIES ---
+// `rust search` (both words), `rust OR go`, `rust NOT java` or `rust -java`, `"search engine"`
+// (a phrase), `optim*` (a prefix) and parentheses. Words are matched case-insensitively;
+// AND, OR and NOT are operators only in capitals. NOT binds tightest, then AND, then OR.
+#[derive(Debug)]
+enum Search {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+    And(Box<Search>, Box<Search>),
+    Or(Box<Search>, Box<Search>),
+    Not(Box<Search>),
+}
+
+#[derive(Debug)]
+enum Lexeme {
+    Term(Search),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn lex(query: &str) -> Vec<Lexeme> {
+    let mut out = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                match words(&phrase) {
+                    words if words.is_empty() => {}
+                    mut words if words.len() == 1 => out.push(Lexeme::Term(Search::Word(words.remove(0)))),
+                    words => out.push(Lexeme::Term(Search::Phrase(words))),
+                }
+            }
+            '(' => out.push(Lexeme::Open),
+            ')' => out.push(Lexeme::Close),
+            c if c.is_whitespace() => {}
+            // A leading '-' excludes the term after it; inside a word (e-mail) it separates words
+            '-' => out.push(Lexeme::Not),
+            c => {
+                let mut run = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '"' | '(' | ')') {
+                        break;
+                    }
+                    run.push(next);
+                    chars.next();
+                }
+                match run.as_str() {
+                    "AND" => out.push(Lexeme::And),
+                    "OR" => out.push(Lexeme::Or),
+                    "NOT" => out.push(Lexeme::Not),
+                    _ => {
+                        let prefix = run.ends_with('*');
+                        match words(&run) {
+                            words if words.is_empty() => {}
+                            mut words if words.len() == 1 && prefix => out.push(Lexeme::Term(Search::Prefix(words.remove(0)))),
+                            mut words if words.len() == 1 => out.push(Lexeme::Term(Search::Word(words.remove(0)))),
+                            words => out.push(Lexeme::Term(Search::Phrase(words))),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+struct SearchParser {
+    lexemes: std::iter::Peekable<std::vec::IntoIter<Lexeme>>,
+}
+
+impl SearchParser {
+    fn or(&mut self) -> Result<Search, String> {
+        let mut search = self.and()?;
+        while self.lexemes.next_if(|l| matches!(l, Lexeme::Or)).is_some() {
+            search = Search::Or(Box::new(search), Box::new(self.and()?));
+        }
+        Ok(search)
+    }
+
+    // Terms next to each other must all match, as if joined by AND
+    fn and(&mut self) -> Result<Search, String> {
+        let mut search = self.not()?;
+        while !matches!(self.lexemes.peek(), None | Some(Lexeme::Or | Lexeme::Close)) {
+            let _and = self.lexemes.next_if(|l| matches!(l, Lexeme::And));
+            search = Search::And(Box::new(search), Box::new(self.not()?));
+        }
+        Ok(search)
+    }
+
+    fn not(&mut self) -> Result<Search, String> {
+        if self.lexemes.next_if(|l| matches!(l, Lexeme::Not)).is_some() {
+            return Ok(Search::Not(Box::new(self.not()?)));
+        }
+        match self.lexemes.next() {
+            Some(Lexeme::Term(term)) => Ok(term),
+            Some(Lexeme::Open) => {
+                let search = self.or()?;
+                match self.lexemes.next() {
+                    Some(Lexeme::Close) => Ok(search),
+                    _ => Err("Full-text query: missing ')'".to_string()),
+                }
+            }
+            Some(other) => Err(format!("Full-text query: expected a word, found {:?}", other)),
+            None => Err("Full-text query: expected a word".to_string()),
+        }
+    }
+}
+
+// Can the search match a row by what it contains, not only by what it lacks?
+fn positive(search: &Search) -> bool {
+    match search {
+        Search::Word(_) | Search::Prefix(_) | Search::Phrase(_) => true,
+        Search::And(a, b) => positive(a) || positive(b),
+        Search::Or(a, b) => positive(a) && positive(b),
+        Search::Not(_) => false,
+    }
+}
+
+fn parse_search(query: &str) -> Result<Search, String> {
+    let mut parser = SearchParser { lexemes: lex(query).into_iter().peekable() };
+    if parser.lexemes.peek().is_none() {
+        return Err(format!("Full-text query '{}' has no words to search for", query));
+    }
+    let search = parser.or()?;
+    if let Some(extra) = parser.lexemes.next() {
+        return Err(format!("Full-text query: unexpected {:?}", extra));
+    }
+    if !positive(&search) {
+        return Err(format!("Full-text query '{}' only excludes words; name at least one to look for", query));
+    }
+    Ok(search)
+}
+
+// The parts of a MATCH (cols) AGAINST ('query' [IN BOOLEAN MODE | IN NATURAL LANGUAGE MODE]).
+// Both modes take the query syntax above.
+fn match_parts(expr: &Expr) -> Option<Result<(&[Ident], Search), String>> {
+    let Expr::MatchAgainst { columns, match_value, opt_search_modifier } = expr else { return None };
+    if matches!(opt_search_modifier, Some(SearchModifier::WithQueryExpansion | SearchModifier::InNaturalLanguageModeWithQueryExpansion)) {
+        return Some(Err("WITH QUERY EXPANSION is not supported".to_string()));
+    }
+    let sqlparser::ast::Value::SingleQuotedString(query) = match_value else {
+        return Some(Err(format!("AGAINST takes a string, not {}", match_value)));
+    };
+    Some(parse_search(query).map(|search| (columns.as_slice(), search)))
+}
+
+// The full-text index on exactly the columns MATCH names, in any order.
+fn index_for<'a>(table: &'a Table, columns: &[Ident]) -> Option<&'a FullTextIndex> {
+    table.fulltext.iter().find(|i| i.columns.len() == columns.len() && columns.iter().all(|c| i.columns.contains(&c.value)))
+}
+
+// Rowids a WHERE term MATCH ... AGAINST ... can hold for, if a full-text index answers it.
+pub fn probe(table: &Table, term: &Expr) -> Option<BTreeSet<u64>> {
+    let (columns, search) = match_parts(term)?.ok()?;
+    Some(index_for(table, columns)?.rows(&search))
+}
+
+// The index a probe would read, e.g. `docs_title_body_fts ('rust search')`, for EXPLAIN.
+pub fn describe(table: &Table, term: &Expr) -> Option<String> {
+    let Expr::MatchAgainst { columns, match_value, .. } = term else { return None };
+    index_for(table, columns).map(|index| format!("{} ({})", index.name, match_value))
+}
+
+// The MATCH ... AGAINST calls in `sql`. Like sequence::bind, this reads the SQL text
+// rather than walking the AST.
+pub fn calls(sql: &str) -> Result<Vec<Expr>, String> {
+    if !sql.to_lowercase().contains("against") {
+        return Ok(Vec::new());
+    }
+    let tokens: Vec<Token> = Tokenizer::new(&GenericDialect {}, sql).tokenize().map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|t| !matches!(t, Token::Whitespace(_)))
+        .collect();
+    let is_word = |i: usize, word: &str| matches!(tokens.get(i), Some(Token::Word(w)) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word));
+    let mut found = Vec::new();
+    for i in 0..tokens.len() {
+        if is_word(i, "MATCH")
+            && let Some(close) = closing_paren(&tokens, i + 1)
+            && is_word(close + 1, "AGAINST")
+            && let Some(end) = closing_paren(&tokens, close + 2)
+        {
+            let text = tokens[i..=end].iter().map(token_sql).collect::<Vec<_>>().join(" ");
+            let call = parse_expr(&text)?;
+            if !found.contains(&call) {
+                found.push(call);
+            }
+        }
+    }
+    Ok(found)
+}
+
+// Scores each MATCH ... AGAINST call for the rows of `table` and stores the score in every row
+// under the call's text, where eval_expr looks it up.
+pub fn bind(db: &Database, table_name: &str, calls: &[Expr], rows: &mut [Cow<Row>]) -> Result<(), String> {
+    let table = db.tables.get(table_name);
+    for call in calls {
+        let Some(parts) = match_parts(call) else { continue };
+        let (columns, search) = parts?;
+        let Some(index) = table.and_then(|t| index_for(t, columns)) else {
+            let columns = columns.iter().map(|c| c.value.as_str()).collect::<Vec<_>>().join(", ");
+            return Err(format!("MATCH ({}) needs a full-text index on ({}): CREATE INDEX ON {} USING fulltext ({})",
+                columns, columns, table_name, columns));
+        };
+        let scores = index.scores(&search);
+        let key = call.to_string();
+        for row in rows.iter_mut() {
+            let score = scores.get(&row.id).copied().unwrap_or(0.0);
+            row.to_mut().data.insert(key.clone(), Value::Float(score));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{rows, run, setup};
+
+    // The ids of the rows of `docs` that match `query`, best first
+    fn matching(db: &mut Database, query: &str) -> Vec<Value> {
+        let sql = format!("SELECT id FROM docs WHERE MATCH (body) AGAINST ('{}') ORDER BY MATCH (body) AGAINST ('{}') DESC", query, query);
+        rows(db, &sql).into_iter().map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    fn words_are_lowercased_runs_of_letters_and_digits() {
+        assert_eq!(words("Full-text, v2 SEARCH!"), vec!["full", "text", "v2", "search"]);
+    }
+
+    #[test]
+    fn bm25_ranks_frequent_terms_in_short_documents_first() {
+        let mut db = setup("
+            CREATE TABLE docs (id INTEGER PRIMARY KEY, title TEXT, body TEXT);
+            INSERT INTO docs VALUES
+                (1, 'Go', 'go is simple, rust is safe and the compiler is long long long long text here'),
+                (2, 'Rust', 'rust rust rust is fast'),
+                (3, 'Java', 'java');
+            CREATE INDEX ON docs USING fulltext (title, body);
+        ");
+        let ranked = rows(&mut db, "SELECT id, MATCH (title, body) AGAINST ('rust') FROM docs ORDER BY MATCH (title, body) AGAINST ('rust') DESC");
+        assert_eq!(ranked.iter().map(|row| row[0].clone()).collect::<Vec<_>>(), vec![Value::Integer(2), Value::Integer(1), Value::Integer(3)]);
+        let [Value::Float(first), Value::Float(second)] = [&ranked[0][1], &ranked[1][1]] else { panic!("scores are not floats") };
+        assert!(first > second && *second > 0.0);
+        assert_eq!(ranked[2][1], Value::Float(0.0));
+    }
+
+    #[test]
+    fn queries_combine_words_phrases_and_prefixes() {
+        let mut db = setup("
+            CREATE TABLE docs (id INTEGER PRIMARY KEY, body TEXT);
+            INSERT INTO docs VALUES (1, 'go search engine'), (2, 'java search engine'), (3, 'engine search'), (4, 'optimizing optimization');
+            CREATE INDEX ON docs USING fulltext (body);
+        ");
+        assert_eq!(matching(&mut db, "\"search engine\" -java"), vec![Value::Integer(1)]);
+        assert_eq!(matching(&mut db, "java OR go").len(), 2);
+        assert_eq!(matching(&mut db, "optim*"), vec![Value::Integer(4)]);
+        assert_eq!(
+            run(&mut db, "SELECT id FROM docs WHERE MATCH (body) AGAINST ('-java')"),
+            Err("Full-text query '-java' only excludes words; name at least one to look for".to_string())
+        );
+        // The index follows writes
+        run(&mut db, "UPDATE docs SET body = 'nothing' WHERE id = 4").unwrap();
+        assert!(matching(&mut db, "optim*").is_empty());
+    }
+
+    #[test]
+    fn postings_are_rebuilt_on_load() {
+        let db = setup("
+            CREATE TABLE docs (id INTEGER PRIMARY KEY, body TEXT);
+            INSERT INTO docs VALUES (1, 'rust search engine'), (2, 'rust rust'), (3, 'go');
+            CREATE INDEX ON docs USING fulltext (body);
+        ");
+        let path = std::env::temp_dir().join(format!("rust_sqlite_fulltext_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        db.write_to(path).unwrap();
+        let saved = std::fs::read_to_string(path);
+        let loaded = Database::load_from(path);
+        std::fs::remove_file(path).unwrap();
+        assert!(!saved.unwrap().contains("postings"));
+        let mut db = loaded.unwrap();
+        assert_eq!(matching(&mut db, "rust"), vec![Value::Integer(2), Value::Integer(1)]);
+        assert_eq!(matching(&mut db, "\"search engine\""), vec![Value::Integer(1)]);
+    }
+}
@@ -0,0 +1,765 @@
+// --- SECONDARY INDEXES ---
+// CREATE [UNIQUE] INDEX name ON t (a, b DESC, lower(c)) [WHERE cond] keeps an ordered map from
+// key values to the rowids holding them. Only its definition is saved; the entries are rebuilt
+// from the rows on load (Table::rebuild_indexes) and kept current by every write
+// (Table::put_row and Table::take_row). The planner matches WHERE terms against an
+// index's key parts (equality on the leading ones, then a range) so SELECT, UPDATE/DELETE
+// lookups and JOIN ... ON read only the rows it names. Every PRIMARY KEY and UNIQUE constraint
+// has one too, so checking a new key is a lookup rather than a scan.
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{BinaryOperator, Expr, Ident, ObjectName, OrderByExpr};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::tokenizer::{Token, Tokenizer};
+
+use crate::expr::{compare_values, eval_expr, parse_expr, sql_literal, truth};
+use crate::fulltext;
+use crate::schema::split;
+use crate::stats;
+use crate::{object_name, Database, Table, Value};
+
+// The indexed values of one row. NULL sorts first; the values of one column share a type and
+// otherwise compare the way SQL compares them.
+#[derive(Debug, Clone)]
+pub struct Key(pub Vec<Value>);
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for (a, b) in self.0.iter().zip(&other.0) {
+            let ord = compare_key_values(a, b);
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        self.0.len().cmp(&other.0.len())
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+// Orders values of different types by type, so the map stays totally ordered.
+fn type_rank(v: &Value) -> u8 {
+    match v {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => 2,
+        Value::Text(_) => 3,
+        Value::Blob(_) => 4,
+        Value::Uuid(_) => 5,
+        Value::Json(_) => 6,
+        Value::Array(_) => 7,
+        Value::Date(_) => 8,
+        Value::Time(_) => 9,
+        Value::Timestamp(_) => 10,
+        Value::TimestampTz(_) => 11,
+        Value::Interval(_) => 12,
+    }
+}
+
+fn compare_key_values(a: &Value, b: &Value) -> Ordering {
+    type_rank(a).cmp(&type_rank(b)).then_with(|| match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        _ => compare_values(a, b).unwrap_or_else(|| format!("{:?}", a).cmp(&format!("{:?}", b))),
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Index {
+    pub name: String,
+    // Key parts: column names, or expressions such as `lower(email)` as SQL text
+    pub columns: Vec<String>,
+    // DESC per key part. Lookups read an index in either direction, so this is only recorded.
+    #[serde(default)]
+    pub descending: Vec<bool>,
+    pub unique: bool,
+    // Built for a PRIMARY KEY or UNIQUE constraint rather than by CREATE INDEX
+    #[serde(default)]
+    pub constraint: bool,
+    // Partial index: only rows for which this condition holds are in it
+    #[serde(default)]
+    pub predicate: Option<String>,
+    // Key -> rowids with that key. Not saved: the rows are, and the entries follow from them.
+    #[serde(skip)]
+    pub entries: BTreeMap<Key, BTreeSet<u64>>,
+}
+
+impl Index {
+    pub fn new(name: String, columns: Vec<String>, unique: bool, constraint: bool) -> Self {
+        Index { name, columns, descending: Vec::new(), unique, constraint, predicate: None, entries: BTreeMap::new() }
+    }
+
+    // Adds every row of `table`. For a UNIQUE index, returns the first key found twice.
+    pub fn fill(&mut self, table: &Table) -> Result<Option<Key>, String> {
+        let mut duplicate = None;
+        for row in table.data.values() {
+            let row = table.read_row(row)?;
+            if duplicate.is_none() {
+                duplicate = self.conflict(&row.data, None)?;
+            }
+            self.add(row.id, &row.data)?;
+        }
+        Ok(duplicate)
+    }
+
+    // The error for a unique index that `fill` found a duplicate `key` in, when loading `table`.
+    pub fn duplicated(&self, table: &str, key: &Key) -> String {
+        format!("Unique index '{}' of '{}' does not hold: ({}) = ({}) is duplicated",
+            self.name, table, self.columns.join(", "), describe(key))
+    }
+
+    // Does the index hold this row? Every row, unless it is a partial index.
+    fn covers(&self, row: &BTreeMap<String, Value>) -> Result<bool, String> {
+        match &self.predicate {
+            Some(sql) => Ok(truth(&eval_expr(&parse_expr(sql)?, row)?)? == Some(true)),
+            None => Ok(true),
+        }
+    }
+
+    // `row` is the row as readers see it, VIRTUAL columns included.
+    pub fn key(&self, row: &BTreeMap<String, Value>) -> Result<Key, String> {
+        self.columns.iter()
+            .map(|part| match row.get(part) {
+                Some(value) => Ok(value.clone()),
+                None => eval_expr(&parse_expr(part)?, row),
+            })
+            .collect::<Result<_, _>>()
+            .map(Key)
+    }
+
+    pub fn add(&mut self, id: u64, row: &BTreeMap<String, Value>) -> Result<(), String> {
+        if self.covers(row)? {
+            self.entries.entry(self.key(row)?).or_default().insert(id);
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: u64, row: &BTreeMap<String, Value>) -> Result<(), String> {
+        if !self.covers(row)? {
+            return Ok(());
+        }
+        let key = self.key(row)?;
+        if let Some(ids) = self.entries.get_mut(&key) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.entries.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    // For a UNIQUE index: another row that already has this row's key. Keys containing NULL
+    // never collide, as with UNIQUE constraints, and a partial index only compares the rows it holds.
+    pub fn conflict(&self, row: &BTreeMap<String, Value>, skip_id: Option<u64>) -> Result<Option<Key>, String> {
+        if !self.unique || !self.covers(row)? {
+            return Ok(None);
+        }
+        let key = self.key(row)?;
+        if key.0.contains(&Value::Null) {
+            return Ok(None);
+        }
+        let taken = self.entries.get(&key).is_some_and(|ids| ids.iter().any(|id| Some(*id) != skip_id));
+        Ok(taken.then_some(key))
+    }
+
+    // Rowids holding exactly `key`.
+    pub fn get(&self, key: &[Value]) -> impl Iterator<Item = u64> + '_ {
+        self.entries.get(&Key(key.to_vec())).into_iter().flatten().copied()
+    }
+
+    // Rowids whose key starts with `prefix` and whose next part lies within the bounds.
+    // A part with a bound is never NULL, as no comparison with NULL holds.
+    pub fn scan(&self, prefix: &[Value], lower: Bound<&Value>, upper: Bound<&Value>) -> BTreeSet<u64> {
+        let n = prefix.len();
+        let mut start = prefix.to_vec();
+        if let Bound::Included(v) | Bound::Excluded(v) = lower {
+            start.push(v.clone());
+        }
+        let bounded = !matches!((lower, upper), (Bound::Unbounded, Bound::Unbounded));
+        self.entries.range(Key(start)..)
+            .take_while(|(key, _)| key.0.iter().zip(prefix).all(|(a, b)| compare_key_values(a, b) == Ordering::Equal))
+            .filter(|(key, _)| !bounded || key.0.get(n).is_some_and(|v| *v != Value::Null))
+            .filter(|(key, _)| !matches!(lower, Bound::Excluded(v) if compare_key_values(&key.0[n], v) == Ordering::Equal))
+            .take_while(|(key, _)| match upper {
+                Bound::Included(v) => compare_key_values(&key.0[n], v) != Ordering::Greater,
+                Bound::Excluded(v) => compare_key_values(&key.0[n], v) == Ordering::Less,
+                Bound::Unbounded => true,
+            })
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    pub fn sql(&self, table: &str) -> String {
+        let unique = if self.unique { "UNIQUE " } else { "" };
+        let parts: Vec<String> = self.columns.iter().enumerate()
+            .map(|(i, part)| if self.descending.get(i) == Some(&true) { format!("{} DESC", part) } else { part.clone() })
+            .collect();
+        let predicate = self.predicate.as_ref().map(|p| format!(" WHERE {}", p)).unwrap_or_default();
+        format!("CREATE {}INDEX {} ON {} ({}){}", unique, self.name, table, parts.join(", "), predicate)
+    }
+}
+
+pub struct CreateIndex<'a> {
+    pub name: Option<&'a ObjectName>,
+    pub table_name: &'a ObjectName,
+    pub using: Option<&'a Ident>,
+    pub columns: &'a [OrderByExpr],
+    pub unique: bool,
+    pub if_not_exists: bool,
+    pub predicate: Option<&'a Expr>,
+    // INCLUDE or NULLS [NOT] DISTINCT was given
+    pub extras: bool,
+}
+
+fn find<'a>(db: &'a Database, name: &str) -> Option<(&'a Table, usize)> {
+    db.tables.values().find_map(|t| t.indexes.iter().position(|i| i.name == name).map(|pos| (t, pos)))
+}
+
+// Is `name` used by an index of either kind?
+pub fn taken(db: &Database, name: &str) -> bool {
+    find(db, name).is_some() || db.tables.values().any(|t| t.fulltext.iter().any(|i| i.name == name))
+}
+
+fn unwrap_nested(mut expr: &Expr) -> &Expr {
+    while let Expr::Nested(inner) = expr {
+        expr = inner;
+    }
+    expr
+}
+
+// CREATE [UNIQUE] INDEX [IF NOT EXISTS] [name] ON table (col | (expr) [ASC | DESC], ...) [WHERE cond]
+pub fn create(db: &mut Database, def: CreateIndex) -> Result<String, String> {
+    let table_name = object_name(def.table_name);
+    if db.views.contains_key(&table_name) {
+        return Err(format!("Cannot create an index on view '{}'", table_name));
+    }
+    if def.using.is_some_and(|u| u.value.eq_ignore_ascii_case("fulltext")) {
+        return fulltext::create(db, def);
+    }
+    let table = db.tables.get(&table_name).ok_or(format!("Table '{}' not found", table_name))?;
+    if let Some(using) = def.using.filter(|u| !u.value.eq_ignore_ascii_case("btree")) {
+        return Err(format!("Index method '{}' is not supported; use btree (the default) or fulltext", using));
+    }
+    if def.extras {
+        return Err("INCLUDE and NULLS [NOT] DISTINCT are not supported in CREATE INDEX".to_string());
+    }
+    // Expressions must be computable from the row alone; try them on a row of NULLs
+    let null_row: BTreeMap<String, Value> = table.columns.iter().map(|(c, _)| (c.clone(), Value::Null)).collect();
+    let mut columns = Vec::new();
+    let mut descending = Vec::new();
+    for item in def.columns {
+        if item.nulls_first.is_some() {
+            return Err("NULLS FIRST / NULLS LAST is not supported in CREATE INDEX".to_string());
+        }
+        let part = match unwrap_nested(&item.expr) {
+            Expr::Identifier(ident) => {
+                if !table.columns.iter().any(|(c, _)| *c == ident.value) {
+                    return Err(format!("Column '{}' not found in table '{}'", ident.value, table_name));
+                }
+                ident.value.clone()
+            }
+            expr => {
+                eval_expr(expr, &null_row).map_err(|e| format!("Cannot index '{}': {}", expr, e))?;
+                expr.to_string()
+            }
+        };
+        if columns.contains(&part) {
+            return Err(format!("'{}' is listed more than once", part));
+        }
+        columns.push(part);
+        descending.push(item.asc == Some(false));
+    }
+    let predicate = match def.predicate {
+        Some(expr) => {
+            eval_expr(expr, &null_row).map_err(|e| format!("Invalid index condition '{}': {}", expr, e))?;
+            Some(expr.to_string())
+        }
+        None => None,
+    };
+    let name = match def.name {
+        Some(name) => name.to_string(),
+        // <table>_<cols>_idx, numbered like PostgreSQL if that is taken
+        None => {
+            let parts: Vec<&str> = columns.iter()
+                .map(|part| if table.columns.iter().any(|(c, _)| c == part) { part.as_str() } else { "expr" })
+                .collect();
+            let base = format!("{}_{}_idx", split(&table_name).1, parts.join("_"));
+            (0..).map(|i| if i == 0 { base.clone() } else { format!("{}{}", base, i) })
+                .find(|name| !taken(db, name))
+                .unwrap_or(base)
+        }
+    };
+    if taken(db, &name) {
+        return if def.if_not_exists {
+            Ok(format!("Index '{}' already exists, skipping", name))
+        } else {
+            Err(format!("Index '{}' already exists", name))
+        };
+    }
+
+    let mut index = Index::new(name.clone(), columns, def.unique, false);
+    index.descending = descending;
+    index.predicate = predicate;
+    if let Some(key) = index.fill(table)? {
+        return Err(format!("Cannot create unique index '{}': ({}) = ({}) is duplicated",
+            name, index.columns.join(", "), describe(&key)));
+    }
+    let count = index.entries.values().map(|ids| ids.len()).sum::<usize>();
+    db.tables.get_mut(&table_name).ok_or(format!("Table '{}' not found", table_name))?.indexes.push(index);
+    Ok(format!("Index '{}' created on '{}' ({} rows)", name, table_name, count))
+}
+
+// DROP INDEX [IF EXISTS] name, ...
+pub fn drop(db: &mut Database, names: &[ObjectName], if_exists: bool) -> Result<String, String> {
+    let mut dropped = Vec::new();
+    for name in names.iter().map(|n| n.to_string()) {
+        let Some((table, pos)) = find(db, &name) else {
+            if let Some(table) = db.tables.values_mut().find(|t| t.fulltext.iter().any(|i| i.name == name)) {
+                table.fulltext.retain(|i| i.name != name);
+                dropped.push(name);
+                continue;
+            }
+            if if_exists {
+                continue;
+            }
+            return Err(format!("Index '{}' not found", name));
+        };
+        if table.indexes[pos].constraint {
+            return Err(format!("Cannot drop index '{}': it enforces a PRIMARY KEY or UNIQUE constraint of '{}'", name, table.name));
+        }
+        let table_name = table.name.clone();
+        if let Some(table) = db.tables.get_mut(&table_name) {
+            table.indexes.remove(pos);
+        }
+        dropped.push(name);
+    }
+    if dropped.is_empty() {
+        return Ok("No indexes dropped".to_string());
+    }
+    Ok(format!("Dropped {}", dropped.iter().map(|n| format!("'{}'", n)).collect::<Vec<_>>().join(", ")))
+}
+
+pub fn describe(key: &Key) -> String {
+    key.0.iter().map(|v| format!("{:?}", v)).collect::<Vec<_>>().join(", ")
+}
+
+// Rowids whose `col` equals `value`, if a (non-partial) index leads with `col`.
+pub fn lookup(table: &Table, col: &str, value: &Value) -> Option<BTreeSet<u64>> {
+    let index = table.index_on(col)?;
+    Some(index.scan(&[], Bound::Included(value), Bound::Included(value)))
+}
+
+// Rowids that may satisfy `selection`, narrowed through the B-tree index named `index` and any
+// full-text index a MATCH term can use, or None when neither applies. This may return too many
+// rows but never too few: callers still evaluate the WHERE on every row they read.
+pub fn candidates(table: &Table, selection: &Expr, index: Option<&str>) -> Option<BTreeSet<u64>> {
+    let terms = conjuncts(selection);
+    let mut found: Option<BTreeSet<u64>> = None;
+    let probes = accesses(table, selection).into_iter()
+        .filter(|access| Some(access.index.name.as_str()) == index)
+        .map(|access| access.rows())
+        .chain(terms.iter().filter_map(|term| fulltext::probe(table, term)));
+    for ids in probes {
+        found = Some(match found {
+            Some(found) => found.intersection(&ids).copied().collect(),
+            None => ids,
+        });
+    }
+    found
+}
+
+// The B-tree indexes that can narrow a scan for `selection`, and how.
+pub fn accesses<'a>(table: &'a Table, selection: &Expr) -> Vec<Access<'a>> {
+    let terms = conjuncts(selection);
+    table.indexes.iter().filter_map(|index| probe(table, index, &terms)).collect()
+}
+
+// The terms of `a AND b AND ...`.
+pub fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            terms
+        }
+        Expr::Nested(inner) => conjuncts(inner),
+        _ => vec![expr],
+    }
+}
+
+// How one index narrows a scan: equality on its first key parts, then possibly a range or an
+// IN list on the next one.
+pub struct Access<'a> {
+    pub index: &'a Index,
+    prefix: Vec<Value>,
+    next: Option<Step>,
+}
+
+enum Step {
+    Range(Bound<Value>, Bound<Value>),
+    In(Vec<Value>),
+}
+
+impl Access<'_> {
+    pub fn rows(&self) -> BTreeSet<u64> {
+        match &self.next {
+            None => self.index.scan(&self.prefix, Bound::Unbounded, Bound::Unbounded),
+            Some(Step::Range(lower, upper)) => self.index.scan(&self.prefix, lower.as_ref(), upper.as_ref()),
+            Some(Step::In(values)) => values.iter().flat_map(|value| {
+                let mut key = self.prefix.clone();
+                key.push(value.clone());
+                self.index.scan(&key, Bound::Unbounded, Bound::Unbounded)
+            }).collect(),
+        }
+    }
+
+    // Estimated share of the table's rows the access reads, from the statistics of its key
+    // parts; a partial index holds a known share of the rows to begin with.
+    pub fn selectivity(&self, table: &Table) -> f64 {
+        let parts = &self.index.columns;
+        let mut fraction: f64 = parts.iter().zip(&self.prefix).map(|(part, value)| stats::eq_fraction(table, part, value)).product();
+        let part = parts.get(self.prefix.len()).map(String::as_str).unwrap_or_default();
+        fraction *= match &self.next {
+            None => 1.0,
+            Some(Step::Range(lower, upper)) => stats::range_fraction(table, part, lower.as_ref(), upper.as_ref()),
+            Some(Step::In(values)) => values.iter().map(|value| stats::eq_fraction(table, part, value)).sum::<f64>().min(1.0),
+        };
+        if self.index.predicate.is_some() && !table.data.is_empty() {
+            let held: usize = self.index.entries.values().map(BTreeSet::len).sum();
+            fraction *= held as f64 / table.data.len() as f64;
+        }
+        fraction
+    }
+
+    // The index and the key condition it is read with, e.g. `u_a_b_idx (a = 1, b > 5)`
+    pub fn describe(&self) -> String {
+        let parts = &self.index.columns;
+        let mut conditions: Vec<String> = parts.iter().zip(&self.prefix)
+            .map(|(part, value)| format!("{} = {}", part, sql_literal(value)))
+            .collect();
+        let part = parts.get(self.prefix.len()).map(String::as_str).unwrap_or_default();
+        match &self.next {
+            Some(Step::Range(lower, upper)) => {
+                if let Bound::Included(v) | Bound::Excluded(v) = lower {
+                    let op = if matches!(lower, Bound::Included(_)) { ">=" } else { ">" };
+                    conditions.push(format!("{} {} {}", part, op, sql_literal(v)));
+                }
+                if let Bound::Included(v) | Bound::Excluded(v) = upper {
+                    let op = if matches!(upper, Bound::Included(_)) { "<=" } else { "<" };
+                    conditions.push(format!("{} {} {}", part, op, sql_literal(v)));
+                }
+            }
+            Some(Step::In(values)) => {
+                let values: Vec<String> = values.iter().map(sql_literal).collect();
+                conditions.push(format!("{} IN ({})", part, values.join(", ")));
+            }
+            None => {}
+        }
+        if let Some(predicate) = &self.index.predicate {
+            conditions.push(format!("partial: {}", predicate));
+        }
+        format!("{} ({})", self.index.name, conditions.join(", "))
+    }
+}
+
+// What one index can answer for the WHERE terms: equality on its leading key parts, then a
+// range, BETWEEN or IN on the next one. A partial index is only used when the terms imply its
+// condition, and then holds every row that can match even if no key part is constrained.
+fn probe<'a>(table: &Table, index: &'a Index, terms: &[&Expr]) -> Option<Access<'a>> {
+    if let Some(predicate) = &index.predicate
+        && !implied(predicate, terms)
+    {
+        return None;
+    }
+    let mut prefix = Vec::new();
+    let mut next = None;
+    for part in &index.columns {
+        if let Some(value) = terms.iter().find_map(|t| equality(table, part, t)) {
+            prefix.push(value);
+            continue;
+        }
+        next = terms.iter().find_map(|t| bounded(table, part, t));
+        break;
+    }
+    if prefix.is_empty() && next.is_none() && index.predicate.is_none() {
+        return None;
+    }
+    Some(Access { index, prefix, next })
+}
+
+// `part = c`, `c = part`, or `part` itself for a boolean key part.
+fn equality(table: &Table, part: &str, term: &Expr) -> Option<Value> {
+    match unwrap_nested(term) {
+        Expr::BinaryOp { left, op: BinaryOperator::Eq, right } => {
+            if matches_part(table, part, left) {
+                constant(table, part, right)
+            } else if matches_part(table, part, right) {
+                constant(table, part, left)
+            } else {
+                None
+            }
+        }
+        term if matches_part(table, part, term) => Some(Value::Bool(true)),
+        _ => None,
+    }
+}
+
+// A range term on a key part: part < c (and <=, >, >=), part BETWEEN a AND b, part IN (a, b, ...).
+fn bounded(table: &Table, part: &str, term: &Expr) -> Option<Step> {
+    match unwrap_nested(term) {
+        Expr::BinaryOp { left, op, right } => {
+            let (op, other) = if matches_part(table, part, left) {
+                (op.clone(), right)
+            } else if matches_part(table, part, right) {
+                (flip(op)?, left)
+            } else {
+                return None;
+            };
+            let value = constant(table, part, other)?;
+            Some(match op {
+                BinaryOperator::Lt => Step::Range(Bound::Unbounded, Bound::Excluded(value)),
+                BinaryOperator::LtEq => Step::Range(Bound::Unbounded, Bound::Included(value)),
+                BinaryOperator::Gt => Step::Range(Bound::Excluded(value), Bound::Unbounded),
+                BinaryOperator::GtEq => Step::Range(Bound::Included(value), Bound::Unbounded),
+                _ => return None,
+            })
+        }
+        Expr::Between { expr, negated: false, low, high } if matches_part(table, part, expr) => {
+            Some(Step::Range(Bound::Included(constant(table, part, low)?), Bound::Included(constant(table, part, high)?)))
+        }
+        Expr::InList { expr, list, negated: false } if matches_part(table, part, expr) => {
+            list.iter().map(|item| constant(table, part, item)).collect::<Option<_>>().map(Step::In)
+        }
+        _ => None,
+    }
+}
+
+fn is_column(table: &Table, part: &str) -> bool {
+    table.columns.iter().any(|(c, _)| c == part)
+}
+
+// Is `expr` the key part `part`? A column matches by name (qualified or not); an expression
+// matches when it is written the same way, ignoring case and spacing.
+fn matches_part(table: &Table, part: &str, expr: &Expr) -> bool {
+    let expr = unwrap_nested(expr);
+    if is_column(table, part) {
+        return match expr {
+            Expr::Identifier(ident) => ident.value == part,
+            Expr::CompoundIdentifier(idents) => idents.last().is_some_and(|i| i.value == part),
+            _ => false,
+        };
+    }
+    canonical(&expr.to_string()) == canonical(part)
+}
+
+// SQL text with unquoted words lowercased and spacing dropped, so `LOWER(Email)` and
+// `lower(email)` compare equal but `'Email'` and `'email'` do not.
+fn canonical(sql: &str) -> String {
+    let Ok(tokens) = Tokenizer::new(&GenericDialect {}, sql).tokenize() else {
+        return sql.to_string();
+    };
+    tokens.iter()
+        .filter(|t| !matches!(t, Token::Whitespace(_)))
+        .map(|t| match t {
+            Token::Word(w) if w.quote_style.is_none() => w.value.to_lowercase(),
+            other => other.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Does the WHERE imply a partial index's condition? Each term of the condition must be one of
+// the WHERE's terms (`flag`, `flag = true` and `flag IS TRUE` count as the same term).
+fn implied(predicate: &str, terms: &[&Expr]) -> bool {
+    let Ok(predicate) = parse_expr(predicate) else { return false };
+    let have: Vec<String> = terms.iter().map(|t| term_form(t)).collect();
+    conjuncts(&predicate).iter().all(|t| have.contains(&term_form(t)))
+}
+
+fn term_form(expr: &Expr) -> String {
+    match unwrap_nested(expr) {
+        Expr::BinaryOp { left, op: BinaryOperator::Eq, right }
+            if matches!(**right, Expr::Value(sqlparser::ast::Value::Boolean(true))) => term_form(left),
+        Expr::IsTrue(inner) => term_form(inner),
+        other => canonical(&other.to_string()),
+    }
+}
+
+// `expr` as a value for key part `part`, if it does not depend on the row. For a column it must
+// convert to the column's type exactly (an index on an INTEGER column cannot answer `n < 1.5`
+// by looking up 1); an expression's value is used as it is.
+fn constant(table: &Table, part: &str, expr: &Expr) -> Option<Value> {
+    let value = eval_expr(expr, &BTreeMap::new()).ok()?;
+    if value == Value::Null {
+        return Some(value);
+    }
+    let Some((_, col_type)) = table.columns.iter().find(|(c, _)| c == part) else {
+        return Some(value);
+    };
+    let coerced = col_type.coerce(part, value.clone()).ok()?;
+    (compare_values(&coerced, &value) == Some(Ordering::Equal)).then_some(coerced)
+}
+
+// `a op b` read as `b op' a`.
+pub fn flip(op: &BinaryOperator) -> Option<BinaryOperator> {
+    Some(match op {
+        BinaryOperator::Eq => BinaryOperator::Eq,
+        BinaryOperator::NotEq => BinaryOperator::NotEq,
+        BinaryOperator::Lt => BinaryOperator::Gt,
+        BinaryOperator::LtEq => BinaryOperator::GtEq,
+        BinaryOperator::Gt => BinaryOperator::Lt,
+        BinaryOperator::GtEq => BinaryOperator::LtEq,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{rows, run, setup};
+
+    // The values of `col` in the rows the index on it lists for `value`
+    fn indexed(db: &Database, col: &str, value: Value) -> Vec<Value> {
+        let table = &db.tables["t"];
+        lookup(table, col, &value).unwrap().iter().map(|id| table.data[id].data[col].clone()).collect()
+    }
+
+    #[test]
+    fn indexes_follow_updates_and_deletes() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);
+            INSERT INTO t VALUES (1, 10), (2, 20), (3, 20);
+            CREATE INDEX t_n ON t (n);
+        ");
+        assert_eq!(indexed(&db, "n", Value::Integer(20)).len(), 2);
+        run(&mut db, "UPDATE t SET n = 30 WHERE id = 2").unwrap();
+        assert_eq!(indexed(&db, "n", Value::Integer(20)), vec![Value::Integer(20)]);
+        assert_eq!(indexed(&db, "n", Value::Integer(30)), vec![Value::Integer(30)]);
+        run(&mut db, "DELETE FROM t WHERE n = 30").unwrap();
+        assert!(indexed(&db, "n", Value::Integer(30)).is_empty());
+        assert_eq!(rows(&mut db, "SELECT id FROM t WHERE n >= 20"), vec![vec![Value::Integer(3)]]);
+        run(&mut db, "DROP INDEX t_n").unwrap();
+        assert!(lookup(&db.tables["t"], "n", &Value::Integer(10)).is_none());
+    }
+
+    #[test]
+    fn unique_indexes_reject_repeated_keys_but_not_nulls() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, email TEXT);
+            INSERT INTO t VALUES (1, 'a'), (2, 'a'), (3, NULL);
+        ");
+        assert_eq!(
+            run(&mut db, "CREATE UNIQUE INDEX t_email ON t (email)"),
+            Err("Cannot create unique index 't_email': (email) = (Text(\"a\")) is duplicated".to_string())
+        );
+        run(&mut db, "DELETE FROM t WHERE id = 2; CREATE UNIQUE INDEX t_email ON t (email)").unwrap();
+        assert_eq!(run(&mut db, "INSERT INTO t VALUES (4, 'a')"), Err("Unique index 't_email' violated: (email) = (Text(\"a\")) already exists".to_string()));
+        run(&mut db, "INSERT INTO t VALUES (4, NULL)").unwrap();
+        assert!(run(&mut db, "UPDATE t SET email = 'a' WHERE id = 3").is_err());
+        // Updating a row to the key it already has is not a conflict
+        run(&mut db, "UPDATE t SET email = 'a' WHERE id = 1").unwrap();
+        assert_eq!(
+            run(&mut db, "DROP INDEX t_pkey"),
+            Err("Cannot drop index 't_pkey': it enforces a PRIMARY KEY or UNIQUE constraint of 't'".to_string())
+        );
+    }
+
+    // The indexes of `t` that can narrow a scan for `selection`
+    fn usable(db: &Database, selection: &str) -> Vec<String> {
+        let table = &db.tables["t"];
+        let expr = parse_expr(selection).unwrap();
+        let terms = conjuncts(&expr);
+        table.indexes.iter().filter(|index| probe(table, index, &terms).is_some()).map(|index| index.name.clone()).collect()
+    }
+
+    #[test]
+    fn composite_partial_and_expression_indexes_answer_matching_queries() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, email TEXT, n INTEGER, active BOOL);
+            INSERT INTO t VALUES (1, 'A', 1, true), (2, 'b', 1, false), (3, 'c', 2, true);
+            CREATE INDEX ON t (lower(email));
+            CREATE INDEX ON t (n, email DESC);
+            CREATE UNIQUE INDEX t_act ON t (n) WHERE active;
+        ");
+        assert_eq!(usable(&db, "lower(email) = 'a'"), vec!["t_expr_idx"]);
+        assert_eq!(rows(&mut db, "SELECT id FROM t WHERE LOWER( email ) = 'a'"), vec![vec![Value::Integer(1)]]);
+        assert_eq!(usable(&db, "n = 1 AND email > 'a'"), vec!["t_n_email_idx"]);
+        assert_eq!(rows(&mut db, "SELECT id FROM t WHERE n = 1 AND email > 'a'"), vec![vec![Value::Integer(2)]]);
+        assert_eq!(usable(&db, "n = 1 AND active = true"), vec!["t_n_email_idx", "t_act"]);
+        // The partial index holds only active rows, so it cannot answer a query for all of them
+        assert_eq!(usable(&db, "n = 1"), vec!["t_n_email_idx"]);
+        // and only active rows collide
+        run(&mut db, "INSERT INTO t VALUES (4, 'd', 1, false)").unwrap();
+        assert!(run(&mut db, "INSERT INTO t VALUES (5, 'e', 2, true)").is_err());
+    }
+
+    #[test]
+    fn only_index_definitions_are_saved_and_entries_come_back_on_load() {
+        let db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);
+            INSERT INTO t VALUES (1, 10), (2, 20), (3, 20);
+            CREATE INDEX t_n ON t (n);
+        ");
+        let path = std::env::temp_dir().join(format!("rust_sqlite_index_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        db.write_to(path).unwrap();
+        let saved = std::fs::read_to_string(path);
+        let loaded = Database::load_from(path);
+        std::fs::remove_file(path).unwrap();
+        assert!(!saved.unwrap().contains("entries"));
+        let mut db = loaded.unwrap();
+        assert_eq!(indexed(&db, "n", Value::Integer(20)).len(), 2);
+        assert_eq!(db.tables["t"].indexes.iter().map(|index| index.entries.len()).collect::<Vec<_>>(), vec![3, 2]);
+        run(&mut db, "INSERT INTO t VALUES (4, 20)").unwrap();
+        assert!(run(&mut db, "INSERT INTO t VALUES (4, 30)").is_err());
+        assert_eq!(indexed(&db, "n", Value::Integer(20)).len(), 3);
+    }
+
+    #[test]
+    fn loading_rows_that_break_a_unique_index_is_an_error() {
+        let path = std::env::temp_dir().join(format!("rust_sqlite_unique_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let load = |db: Database| {
+            db.write_to(path).unwrap();
+            // Stand-in for a hand edit of mydb.json: give row 2 the key of row 1
+            let json = std::fs::read_to_string(path).unwrap().replace("\"b\"", "\"a\"");
+            std::fs::write(path, json).unwrap();
+            let loaded = Database::load_from(path);
+            std::fs::remove_file(path).unwrap();
+            loaded.map(|_| ()).map_err(|e| e.to_string())
+        };
+        let db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, email TEXT, active BOOL);
+            INSERT INTO t VALUES (1, 'a', true), (2, 'b', true);
+            CREATE UNIQUE INDEX t_email ON t (email);
+        ");
+        assert_eq!(load(db), Err("Unique index 't_email' of 't' does not hold: (email) = (Text(\"a\")) is duplicated".to_string()));
+        // A partial index only minds the rows it covers
+        let db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, email TEXT, active BOOL);
+            INSERT INTO t VALUES (1, 'a', true), (2, 'b', false);
+            CREATE UNIQUE INDEX t_email ON t (email) WHERE active;
+        ");
+        assert_eq!(load(db), Ok(()));
+        let db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, email TEXT, active BOOL);
+            INSERT INTO t VALUES (1, 'a', true), (2, 'b', true);
+            CREATE UNIQUE INDEX t_email ON t (email) WHERE active;
+        ");
+        assert!(load(db).unwrap_err().contains("is duplicated"));
+    }
+}
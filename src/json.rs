@@ -0,0 +1,273 @@
+// --- JSON ---
+// JSON/JSONB column values, the ->, ->>, #>, #>> and @> operators, json_extract-style
+// paths ('$.items[0].name') and the json_each table function.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sqlparser::ast::JsonOperator;
+
+use crate::Value;
+
+// Persisted as the JSON document itself, so mydb.json stays readable.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Json(pub JsonValue);
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        serde_json::from_str(s).map(Json).map_err(|e| format!("Invalid JSON: {}", e))
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// A JSON scalar as the matching SQL value; objects and arrays stay JSON.
+pub fn to_sql(v: &JsonValue) -> Value {
+    match v {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Bool(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        JsonValue::String(s) => Value::Text(s.clone()),
+        other => Value::Json(Json(other.clone())),
+    }
+}
+
+// The text ->> returns: strings unquoted, everything else as JSON text.
+fn to_text(v: &JsonValue) -> Value {
+    match v {
+        JsonValue::Null => Value::Null,
+        JsonValue::String(s) => Value::Text(s.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+// Reads a JSON operand: a JSON value, or text that parses as JSON.
+pub fn as_json(v: &Value) -> Result<JsonValue, String> {
+    match v {
+        Value::Json(j) => Ok(j.0.clone()),
+        Value::Text(s) => Json::parse(s).map(|j| j.0),
+        other => Err(format!("Expected JSON, got {:?}", other)),
+    }
+}
+
+// One step of a path: an object key or an array index (negative counts from the end).
+fn step<'a>(v: &'a JsonValue, key: &Value) -> Option<&'a JsonValue> {
+    match (v, key) {
+        (JsonValue::Object(map), Value::Text(k)) => map.get(k),
+        (JsonValue::Array(items), Value::Integer(i)) => {
+            let i = if *i < 0 { items.len() as i64 + i } else { *i };
+            items.get(usize::try_from(i).ok()?)
+        }
+        // '{items,0}' path elements arrive as text
+        (JsonValue::Array(items), Value::Text(k)) => items.get(k.parse::<usize>().ok()?),
+        _ => None,
+    }
+}
+
+// Splits a '$.a.b[0]' path into steps.
+fn parse_path(path: &str) -> Result<Vec<Value>, String> {
+    let invalid = || format!("Invalid JSON path: '{}'", path);
+    let rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut steps = Vec::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                let mut key = String::new();
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if c == '"' {
+                            break;
+                        }
+                        key.push(c);
+                    }
+                } else {
+                    while let Some(&c) = chars.peek() {
+                        if c == '.' || c == '[' {
+                            break;
+                        }
+                        key.push(c);
+                        chars.next();
+                    }
+                }
+                steps.push(Value::Text(key));
+            }
+            '[' => {
+                let mut index = String::new();
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    index.push(c);
+                }
+                let index = match index.trim().strip_prefix("#-") {
+                    Some(back) => -back.trim().parse::<i64>().map_err(|_| invalid())?,
+                    None => index.trim().parse::<i64>().map_err(|_| invalid())?,
+                };
+                steps.push(Value::Integer(index));
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(steps)
+}
+
+// '{a,b,0}' as used by #> and #>>.
+fn parse_brace_path(path: &str) -> Result<Vec<Value>, String> {
+    let inner = path.trim().strip_prefix('{').and_then(|p| p.strip_suffix('}'))
+        .ok_or(format!("Invalid JSON path: '{}' (expected '{{a,b}}')", path))?;
+    Ok(inner.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| Value::Text(s.to_string())).collect())
+}
+
+fn follow<'a>(mut v: &'a JsonValue, steps: &[Value]) -> Option<&'a JsonValue> {
+    for key in steps {
+        v = step(v, key)?;
+    }
+    Some(v)
+}
+
+// json_extract(doc, '$.path'): the value at the path as an SQL value, NULL if absent.
+pub fn extract(doc: &Value, path: &str) -> Result<Value, String> {
+    let doc = as_json(doc)?;
+    Ok(follow(&doc, &parse_path(path)?).map(to_sql).unwrap_or(Value::Null))
+}
+
+// json_array_length(doc [, '$.path'])
+pub fn array_length(doc: &Value, path: Option<&str>) -> Result<Value, String> {
+    let doc = as_json(doc)?;
+    let steps = match path {
+        Some(p) => parse_path(p)?,
+        None => Vec::new(),
+    };
+    match follow(&doc, &steps) {
+        Some(JsonValue::Array(items)) => Ok(Value::Integer(items.len() as i64)),
+        Some(_) => Err("json_array_length() expects an array".to_string()),
+        None => Ok(Value::Null),
+    }
+}
+
+// Does `a` contain `b`? Objects contain a subset of keys, arrays contain every element of b.
+fn contains(a: &JsonValue, b: &JsonValue) -> bool {
+    match (a, b) {
+        (JsonValue::Object(a), JsonValue::Object(b)) => {
+            b.iter().all(|(k, bv)| a.get(k).is_some_and(|av| contains(av, bv)))
+        }
+        (JsonValue::Array(a), JsonValue::Array(b)) => b.iter().all(|bv| a.iter().any(|av| contains(av, bv))),
+        (JsonValue::Array(a), scalar) => a.iter().any(|av| av == scalar),
+        (a, b) => a == b,
+    }
+}
+
+// doc -> key, doc ->> key, doc #> '{a,b}', doc #>> '{a,b}', a @> b, a <@ b
+pub fn access(left: &Value, op: &JsonOperator, right: &Value) -> Result<Value, String> {
+    if *left == Value::Null || *right == Value::Null {
+        return Ok(Value::Null);
+    }
+    let doc = as_json(left)?;
+    let path = |r: &Value| match r {
+        Value::Text(p) => parse_brace_path(p),
+        other => Err(format!("Expected a path like '{{a,b}}', got {:?}", other)),
+    };
+    let found = match op {
+        JsonOperator::Arrow | JsonOperator::LongArrow => step(&doc, right),
+        JsonOperator::HashArrow | JsonOperator::HashLongArrow => follow(&doc, &path(right)?),
+        JsonOperator::AtArrow => return Ok(Value::Bool(contains(&doc, &as_json(right)?))),
+        JsonOperator::ArrowAt => return Ok(Value::Bool(contains(&as_json(right)?, &doc))),
+        other => return Err(format!("Unsupported JSON operator: {}", other)),
+    };
+    Ok(match (found, op) {
+        (None, _) => Value::Null,
+        (Some(v), JsonOperator::LongArrow | JsonOperator::HashLongArrow) => to_text(v),
+        (Some(v), _) => Value::Json(Json(v.clone())),
+    })
+}
+
+fn type_name(v: &JsonValue) -> &'static str {
+    match v {
+        JsonValue::Null => "null",
+        JsonValue::Bool(true) => "true",
+        JsonValue::Bool(false) => "false",
+        JsonValue::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        JsonValue::Number(_) => "real",
+        JsonValue::String(_) => "text",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+pub const EACH_COLUMNS: [&str; 3] = ["key", "value", "type"];
+
+// json_each(doc [, '$.path']): one row per array element or object member.
+pub fn each(doc: &Value, path: Option<&str>) -> Result<Vec<BTreeMap<String, Value>>, String> {
+    if *doc == Value::Null {
+        return Ok(Vec::new());
+    }
+    let doc = as_json(doc)?;
+    let steps = match path {
+        Some(p) => parse_path(p)?,
+        None => Vec::new(),
+    };
+    let row = |key: Value, v: &JsonValue| {
+        BTreeMap::from([
+            ("key".to_string(), key),
+            ("value".to_string(), to_sql(v)),
+            ("type".to_string(), Value::Text(type_name(v).to_string())),
+        ])
+    };
+    Ok(match follow(&doc, &steps) {
+        Some(JsonValue::Array(items)) => items.iter().enumerate().map(|(i, v)| row(Value::Integer(i as i64), v)).collect(),
+        Some(JsonValue::Object(map)) => map.iter().map(|(k, v)| row(Value::Text(k.clone()), v)).collect(),
+        Some(scalar) => vec![row(Value::Null, scalar)],
+        None => Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{run, setup};
+
+    #[test]
+    fn paths_and_operators_extract_values() {
+        let mut db = setup(r#"
+            CREATE TABLE p (id INTEGER PRIMARY KEY, attrs JSON);
+            INSERT INTO p VALUES (1, '{"color": "red", "tags": ["a", "b"], "dim": {"w": 3}}');
+        "#);
+        assert_eq!(
+            run(&mut db, "SELECT attrs -> 'color', attrs ->> 'color', attrs #>> '{dim,w}', json_extract(attrs, '$.tags[1]'), json_array_length(attrs, '$.tags') FROM p"),
+            Ok("ID | attrs -> 'color' | attrs ->> 'color' | attrs #>> '{dim,w}' | json_extract(attrs, '$.tags[1]') | json_array_length(attrs, '$.tags')\n1  | \"red\" | red | 3 | b | 2".to_string())
+        );
+        // A missing key is NULL, not an error
+        assert_eq!(run(&mut db, "SELECT attrs ->> 'weight' AS w FROM p"), Ok("ID | w\n1  | NULL".to_string()));
+    }
+
+    #[test]
+    fn documents_are_validated_and_searchable() {
+        let mut db = setup(r#"
+            CREATE TABLE p (id INTEGER PRIMARY KEY, attrs JSON);
+            INSERT INTO p VALUES (1, '{"color": "red", "size": 2}'), (2, '{"color": "blue", "size": 1}');
+        "#);
+        assert_eq!(
+            run(&mut db, "INSERT INTO p VALUES (3, '{bad')"),
+            Err("Invalid JSON: key must be a string at line 1 column 2 (column 'attrs')".to_string())
+        );
+        assert_eq!(run(&mut db, r#"SELECT id FROM p WHERE attrs @> '{"color": "red"}'"#), Ok("ID | id\n1  | 1".to_string()));
+        assert_eq!(run(&mut db, "SELECT id FROM p ORDER BY attrs ->> 'size'"), Ok("ID | id\n2  | 2\n1  | 1".to_string()));
+        assert_eq!(run(&mut db, "SELECT key, value, type FROM json_each('[1, \"x\"]')"), Ok("ID | key | value | type\n1  | 0 | 1 | integer\n2  | 1 | x | text".to_string()));
+    }
+}
@@ -1,40 +1,135 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::web::Bytes;
 use actix_web::{post, web, App, HttpResponse, HttpServer, Responder};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::fs::{ File};
 use std::io::BufReader;
 use std::path::Path;
+use std::pin::Pin;
 use std::sync::Mutex; // NEW: Needed for locking the DB between web requests
+use std::task::{Context, Poll};
 
 // SQL Parser Imports
-use sqlparser::dialect::GenericDialect;
-use sqlparser::parser::Parser;
-use sqlparser::ast::{Statement, DataType, SetExpr, Values, ColumnOption, JoinOperator, JoinConstraint, TableFactor, Expr, BinaryOperator};
+mod aggregate;
+mod array;
+mod blob;
+mod catalog;
+mod datetime;
+mod decimal;
+mod expr;
+mod foreign_key;
+mod fulltext;
+mod index;
+mod json;
+mod parser;
+mod physical;
+mod plan;
+mod rewrite;
+mod schema;
+mod sequence;
+mod stats;
+mod trigger;
+mod types;
+mod uuid;
+mod view;
+use parser::{parse_commands, Command, CreateTable};
+use expr::{compare_values, eval_expr, parse_expr, truth};
+use foreign_key::{ForeignKey, RefAction};
+use trigger::{Event, Timing};
+use types::ColumnType;
+
+use sqlparser::ast::{Statement, SetExpr, Values, ColumnOption, TableConstraint, TableFactor, Expr, BinaryOperator, OrderByExpr, TableAlias, FunctionArg, Query, ObjectName, ObjectType, SchemaName, FunctionArgExpr, DataType, ArrayElemTypeDef, GeneratedAs};
 
 // --- DATA STRUCTURES (Same as before) ---
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum Value {
     Integer(i64),
     Float(f64),
+    Decimal(decimal::Decimal),
     Text(String),
     Bool(bool),
+    Blob(blob::Blob),
+    Json(json::Json),
+    Uuid(uuid::Uuid),
+    Array(Vec<Value>),
+    Date(datetime::Date),
+    Time(datetime::Time),
+    Timestamp(datetime::Timestamp),
+    TimestampTz(datetime::Timestamp),
+    Interval(datetime::Interval),
     Null,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Row {
-    pub id: u32,
+    pub id: u64,
     pub data: BTreeMap<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Table {
     pub name: String,
-    pub columns: Vec<(String, String)>,
-    pub unique_columns: Vec<String>,
-    pub data: BTreeMap<u32, Row>,
-    pub last_id: u32,
+    pub columns: Vec<(String, ColumnType)>,
+    #[serde(default)]
+    pub uniques: Vec<UniqueConstraint>,
+    // Files written before composite UNIQUE support list single unique columns here;
+    // `load_from_disk` migrates them into `uniques`.
+    #[serde(default, rename = "unique_columns", skip_serializing)]
+    legacy_unique_columns: Vec<String>,
+    // Declared PRIMARY KEY columns (empty if none). Rows are still keyed by
+    // the internal rowid in `data`; the primary key is just a constraint.
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    #[serde(default)]
+    pub not_null: Vec<String>,
+    // Column name -> DEFAULT expression, stored as SQL text and re-parsed on use.
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+    #[serde(default)]
+    pub checks: Vec<CheckConstraint>,
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    // Generated columns in declaration order. VIRTUAL values are never stored in `data`.
+    #[serde(default)]
+    pub generated: Vec<GeneratedColumn>,
+    // GENERATED ALWAYS AS IDENTITY columns: only their sequence may set them.
+    // (Identity and SERIAL columns keep their nextval() in `defaults`.)
+    #[serde(default)]
+    pub identity_always: Vec<String>,
+    // CREATE INDEX; kept current by put_row and take_row
+    #[serde(default)]
+    pub indexes: Vec<index::Index>,
+    // CREATE INDEX ... USING fulltext; kept current the same way
+    #[serde(default)]
+    pub fulltext: Vec<fulltext::FullTextIndex>,
+    // Column statistics from the last ANALYZE, if any; not updated by writes
+    #[serde(default)]
+    pub stats: Option<stats::TableStats>,
+    pub data: BTreeMap<u64, Row>,
+    pub last_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UniqueConstraint {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CheckConstraint {
+    pub name: String,
+    pub expr: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeneratedColumn {
+    pub column: String,
+    pub expr: String,
+    pub stored: bool,
 }
 
 impl Table {
@@ -42,147 +137,401 @@ impl Table {
         Table {
             name,
             columns: Vec::new(),
-            unique_columns: Vec::new(),
+            uniques: Vec::new(),
+            legacy_unique_columns: Vec::new(),
+            primary_key: Vec::new(),
+            not_null: Vec::new(),
+            defaults: BTreeMap::new(),
+            checks: Vec::new(),
+            foreign_keys: Vec::new(),
+            generated: Vec::new(),
+            identity_always: Vec::new(),
+            indexes: Vec::new(),
+            fulltext: Vec::new(),
+            stats: None,
             data: BTreeMap::new(),
             last_id: 0,
         }
     }
+
+    // Value a column takes when an INSERT does not provide one, coerced to the column type.
+    pub fn default_value(&self, col_name: &str) -> Result<Value, String> {
+        let value = match self.defaults.get(col_name) {
+            Some(sql) => eval_expr(&parse_expr(sql)?, &BTreeMap::new())?,
+            None => return Ok(Value::Null),
+        };
+        match self.columns.iter().find(|(c, _)| c == col_name) {
+            Some((_, col_type)) => col_type.coerce(col_name, value),
+            None => Ok(value),
+        }
+    }
+
+    pub fn generated_column(&self, col_name: &str) -> Option<&GeneratedColumn> {
+        self.generated.iter().find(|g| g.column == col_name)
+    }
+
+    fn is_virtual(&self, col_name: &str) -> bool {
+        self.generated_column(col_name).is_some_and(|g| !g.stored)
+    }
+
+    // Computes the generated columns of a row in declaration order: all of them when writing,
+    // only the VIRTUAL ones when reading a stored row.
+    pub fn fill_generated(&self, row_data: &mut BTreeMap<String, Value>, writing: bool) -> Result<(), String> {
+        for g in self.generated.iter().filter(|g| writing || !g.stored) {
+            let value = eval_expr(&parse_expr(&g.expr)?, row_data)?;
+            let col_type = self.columns.iter().find(|(c, _)| *c == g.column).map(|(_, t)| t)
+                .ok_or(format!("Column '{}' not found", g.column))?;
+            row_data.insert(g.column.clone(), col_type.coerce(&g.column, value)?);
+        }
+        Ok(())
+    }
+
+    // A stored row as readers see it, with VIRTUAL columns computed.
+    pub fn read_row<'a>(&self, row: &'a Row) -> Result<Cow<'a, Row>, String> {
+        if self.generated.iter().all(|g| g.stored) {
+            return Ok(Cow::Borrowed(row));
+        }
+        let mut data = row.data.clone();
+        self.fill_generated(&mut data, false)?;
+        Ok(Cow::Owned(Row { id: row.id, data }))
+    }
+
+    // A stored row with only `columns`, computing VIRTUAL columns only when one is wanted.
+    pub fn read_columns(&self, row: &Row, columns: &[String]) -> Result<Row, String> {
+        let full;
+        let data = if columns.iter().any(|c| self.is_virtual(c)) {
+            full = self.read_row(row)?;
+            &full.data
+        } else {
+            &row.data
+        };
+        let data = columns.iter().filter_map(|c| data.get(c).map(|v| (c.clone(), v.clone()))).collect();
+        Ok(Row { id: row.id, data })
+    }
+
+    // Stores a row, replacing any row with the same id, and updates the indexes to match.
+    pub fn put_row(&mut self, id: u64, row_data: BTreeMap<String, Value>) -> Result<(), String> {
+        self.take_row(id)?;
+        let row = Row { id, data: self.strip_virtual(row_data) };
+        if !self.indexes.is_empty() || !self.fulltext.is_empty() {
+            let full = self.read_row(&row)?.into_owned().data;
+            for index in &mut self.indexes {
+                index.add(id, &full)?;
+            }
+            for index in &mut self.fulltext {
+                index.add(id, &full);
+            }
+        }
+        self.data.insert(id, row);
+        Ok(())
+    }
+
+    // Removes a row and its index entries.
+    pub fn take_row(&mut self, id: u64) -> Result<Option<Row>, String> {
+        let Some(row) = self.data.remove(&id) else { return Ok(None) };
+        if !self.indexes.is_empty() || !self.fulltext.is_empty() {
+            let full = self.read_row(&row)?.into_owned().data;
+            for index in &mut self.indexes {
+                index.remove(id, &full)?;
+            }
+            for index in &mut self.fulltext {
+                index.remove(id, &full);
+            }
+        }
+        Ok(Some(row))
+    }
+
+    // A full (not partial) index whose first key part is the column `col`.
+    pub fn index_on(&self, col: &str) -> Option<&index::Index> {
+        self.indexes.iter().find(|i| i.predicate.is_none() && i.columns[0] == col)
+    }
+
+    // Drops VIRTUAL values before a row is stored.
+    pub fn strip_virtual(&self, mut row_data: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+        row_data.retain(|col, _| !self.is_virtual(col));
+        row_data
+    }
+
+    // Enforces NOT NULL and CHECK constraints on a complete candidate row.
+    // A CHECK passes unless its expression evaluates to false (NULL counts as a pass).
+    pub fn check_constraints(&self, row_data: &BTreeMap<String, Value>) -> Result<(), String> {
+        for col in &self.not_null {
+            if matches!(row_data.get(col), None | Some(Value::Null)) {
+                return Err(format!("NOT NULL constraint failed: {}.{}", self.name, col));
+            }
+        }
+        for check in &self.checks {
+            let result = eval_expr(&parse_expr(&check.expr)?, row_data)?;
+            if truth(&result)? == Some(false) {
+                return Err(format!("CHECK constraint '{}' failed: {}", check.name, check.expr));
+            }
+        }
+        Ok(())
+    }
+
+    // Enforces UNIQUE constraints and UNIQUE indexes. As in standard SQL, a key containing NULL never collides.
+    pub fn check_unique(&self, row_data: &BTreeMap<String, Value>, skip_id: Option<u64>) -> Result<(), String> {
+        for unique in &self.uniques {
+            if let Some(key) = self.key_index(&unique.columns)?.conflict(row_data, skip_id)? {
+                return Err(format!("Unique constraint '{}' violated: ({}) = ({}) already exists",
+                    unique.name, unique.columns.join(", "), index::describe(&key)));
+            }
+        }
+        for index in self.indexes.iter().filter(|i| !i.constraint) {
+            if let Some(key) = index.conflict(row_data, skip_id)? {
+                return Err(format!("Unique index '{}' violated: ({}) = ({}) already exists",
+                    index.name, index.columns.join(", "), index::describe(&key)));
+            }
+        }
+        Ok(())
+    }
+
+    // The index behind the PRIMARY KEY or UNIQUE constraint on `cols`.
+    fn key_index(&self, cols: &[String]) -> Result<&index::Index, String> {
+        self.indexes.iter().find(|i| i.constraint && i.columns == cols)
+            .ok_or(format!("Table '{}' has no index for its key ({})", self.name, cols.join(", ")))
+    }
+
+    // Builds the indexes behind the PRIMARY KEY and UNIQUE constraints that do not have one yet:
+    // those of a new table, or of a file saved before constraints were indexed.
+    pub fn index_constraints(&mut self) -> Result<(), String> {
+        let mut keys: Vec<(String, Vec<String>)> = self.uniques.iter().map(|u| (u.name.clone(), u.columns.clone())).collect();
+        if !self.primary_key.is_empty() {
+            keys.insert(0, (format!("{}_pkey", self.name), self.primary_key.clone()));
+        }
+        for (name, columns) in keys {
+            if self.key_index(&columns).is_ok() {
+                continue;
+            }
+            let mut index = index::Index::new(name, columns, true, true);
+            if let Some(key) = index.fill(self)? {
+                return Err(index.duplicated(&self.name, &key));
+            }
+            self.indexes.push(index);
+        }
+        Ok(())
+    }
+
+    // Fills the indexes of a table read from disk, where only their definitions are saved.
+    // A unique index whose rows now collide (say, after mydb.json was edited by hand) is an error.
+    fn rebuild_indexes(&mut self) -> Result<(), String> {
+        let mut indexes = std::mem::take(&mut self.indexes);
+        for index in &mut indexes {
+            if let Some(key) = index.fill(self)? {
+                return Err(index.duplicated(&self.name, &key));
+            }
+        }
+        self.indexes = indexes;
+        let mut fulltext = std::mem::take(&mut self.fulltext);
+        for index in &mut fulltext {
+            index.fill(self)?;
+        }
+        self.fulltext = fulltext;
+        Ok(())
+    }
+
+    // Can `cols` identify a single row? True for the primary key and any UNIQUE constraint.
+    pub fn is_unique_key(&self, cols: &[String]) -> bool {
+        !cols.is_empty() && (self.primary_key == cols || self.uniques.iter().any(|u| u.columns == cols))
+    }
+
+    // Adds a UNIQUE constraint, naming it `<table>_<cols>_key` unless a name was given.
+    fn add_unique(&mut self, name: Option<String>, columns: Vec<String>) -> Result<(), String> {
+        for col in &columns {
+            if !self.columns.iter().any(|(c, _)| c == col) {
+                return Err(format!("Unique column '{}' does not exist", col));
+            }
+        }
+        let name = name.unwrap_or_else(|| format!("{}_{}_key", self.name, columns.join("_")));
+        self.uniques.push(UniqueConstraint { name, columns });
+        Ok(())
+    }
+
+    // Enforces PRIMARY KEY semantics (NOT NULL + unique) for a candidate row.
+    // `skip_id` is the rowid being replaced on UPDATE, so a row never collides with itself.
+    pub fn check_primary_key(&self, row_data: &BTreeMap<String, Value>, skip_id: Option<u64>) -> Result<(), String> {
+        if self.primary_key.is_empty() {
+            return Ok(());
+        }
+        for col in &self.primary_key {
+            if matches!(row_data.get(col), None | Some(Value::Null)) {
+                return Err(format!("Primary key violation: Column '{}' cannot be NULL", col));
+            }
+        }
+        if let Some(key) = self.key_index(&self.primary_key)?.conflict(row_data, skip_id)? {
+            return Err(format!("Primary key violation: Duplicate key ({}) = ({}) in table '{}'",
+                self.primary_key.join(", "), index::describe(&key), self.name));
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Database {
     pub tables: HashMap<String, Table>,
+    // CREATE TYPE ... AS ENUM: type name -> labels in declaration order
+    #[serde(default)]
+    pub enums: BTreeMap<String, Vec<String>>,
+    // CREATE [MATERIALIZED] VIEW, by name
+    #[serde(default)]
+    pub views: BTreeMap<String, view::View>,
+    #[serde(default)]
+    pub triggers: BTreeMap<String, trigger::Trigger>,
+    #[serde(default)]
+    pub sequences: BTreeMap<String, sequence::Sequence>,
+    // CREATE SCHEMA names; their tables are stored as "schema.table"
+    #[serde(default)]
+    pub schemas: BTreeSet<String>,
+    // ATTACH DATABASE alias -> file. Attachments last for the session only.
+    #[serde(skip)]
+    pub attached: BTreeMap<String, schema::Attached>,
+    // Copy of the whole database taken at BEGIN; restored on ROLLBACK. Never persisted.
+    #[serde(skip)]
+    pub transaction: Option<Box<Database>>,
+    // Set by `PRAGMA defer_foreign_keys = 1`: inside a transaction, foreign keys are only checked at COMMIT.
+    // Like SQLite, it switches itself off again when the transaction ends.
+    #[serde(skip)]
+    pub defer_foreign_keys: bool,
+    // How deeply trigger bodies are currently nested.
+    #[serde(skip)]
+    pub trigger_depth: u32,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Database {
     pub fn new() -> Self {
-        Database { tables: HashMap::new() }
+        Database { tables: HashMap::new(), enums: BTreeMap::new(), views: BTreeMap::new(), triggers: BTreeMap::new(), sequences: BTreeMap::new(), schemas: BTreeSet::new(), attached: BTreeMap::new(), transaction: None, defer_foreign_keys: false, trigger_depth: 0 }
+    }
+
+    pub fn foreign_keys_deferred(&self) -> bool {
+        self.transaction.is_some() && self.defer_foreign_keys
+    }
+
+    // A column's DEFAULT for a row being written. Unlike Table::default_value, a nextval() in it
+    // advances the sequence, so SERIAL columns number their rows.
+    pub fn default_value(&mut self, table: &str, col_name: &str) -> Result<Value, String> {
+        let table = self.tables.get(table).ok_or(format!("Table '{}' not found", table))?;
+        let Some(sql) = table.defaults.get(col_name).filter(|sql| sequence::mentioned(sql)) else {
+            return table.default_value(col_name);
+        };
+        let col_type = table.columns.iter().find(|(c, _)| c == col_name).map(|(_, t)| t.clone());
+        let value = eval_expr(&parse_expr(&sequence::bind(self, &sql.clone())?)?, &BTreeMap::new())?;
+        match col_type {
+            Some(col_type) => col_type.coerce(col_name, value),
+            None => Ok(value),
+        }
     }
 
+    // Uncommitted changes stay in memory; they are written out by COMMIT.
+    // Tables of attached databases go back to their own files.
     pub fn save_to_disk(&self) -> Result<(), Box<dyn Error>> {
-        let file = File::create("mydb.json")?;
+        if self.transaction.is_some() {
+            return Ok(());
+        }
+        if self.attached.is_empty() {
+            return self.write_to(DB_FILE);
+        }
+        let (main, files) = schema::split_attached(self);
+        main.write_to(DB_FILE)?;
+        for (path, file) in files {
+            file.write_to(&path)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_to(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let file = File::create(path)?;
         serde_json::to_writer_pretty(file, &self)?;
         Ok(())
     }
 
     pub fn load_from_disk() -> Result<Self, Box<dyn Error>> {
-        if Path::new("mydb.json").exists() {
-            let file = File::open("mydb.json")?;
+        Self::load_from(DB_FILE)
+    }
+
+    pub fn load_from(path: &str) -> Result<Self, Box<dyn Error>> {
+        if Path::new(path).exists() {
+            let file = File::open(path)?;
             let reader = BufReader::new(file);
-            let db: Database = serde_json::from_reader(reader)?;
+            let mut db: Database = serde_json::from_reader(reader)?;
+            // Older files may hold rows saved with fewer values than columns; make the NULLs explicit
+            for table in db.tables.values_mut() {
+                for col in std::mem::take(&mut table.legacy_unique_columns) {
+                    table.add_unique(None, vec![col])?;
+                }
+                for row in table.data.values_mut() {
+                    for (col, col_type) in table.columns.iter().filter(|(c, _)| !table.generated.iter().any(|g| g.column == *c && !g.stored)) {
+                        let value = row.data.entry(col.clone()).or_insert(Value::Null);
+                        // NUMERIC values were stored as floats before the exact decimal type existed
+                        if let (ColumnType::Numeric { .. }, Value::Float(_)) = (col_type, &value) {
+                            *value = col_type.coerce(col, value.clone())?;
+                        }
+                    }
+                }
+                table.rebuild_indexes()?;
+                table.index_constraints()?;
+            }
             return Ok(db);
         }
         Ok(Database::new())
     }
 }
 
+const DB_FILE: &str = "mydb.json";
+
 // --- LOGIC: The Brain ---
 // This handles the SQL logic. It returns a String (success message) or String (error).
 fn process_command(db: &mut Database, stmt: &Statement) -> Result<String, String> {
     match stmt {
-        // CREATE TABLE
-        Statement::CreateTable { name, columns, .. } => {
-            let table_name = name.to_string();
-            if db.tables.contains_key(&table_name) {
-                return Err(format!("Table '{}' already exists", table_name));
-            }
-            let mut table = Table::new(table_name.clone());
-            
-            for col in columns {
-                let col_name = col.name.to_string();
-                let col_type = match col.data_type {
-                    DataType::Int(_) => "Integer",
-                    DataType::Float(_) => "Float",
-                    DataType::Text => "Text",
-                    DataType::Boolean => "Bool",
-                    DataType::Bool => "Bool",
-                    _ => return Err(format!("Unsupported type: {:?}", col.data_type)),
-                };
-                table.columns.push((col_name.clone(), col_type.to_string()));
-
-                // Unique Constraint Check
-                for option in &col.options {
-                    if let ColumnOption::Unique { .. } = &option.option {
-                        table.unique_columns.push(col_name.clone());
+        // INSERT
+        Statement::Insert { table_name, columns, source, .. } => {
+            let name = object_name(table_name);
+            view::reject_write(db, &name)?;
+            let table = db.tables.get(&name).ok_or(format!("Table '{}' not found", name))?;
+
+            // Target columns: the explicit list if given, otherwise the table's column order
+            let mut target_cols = Vec::new();
+            // (generated columns are left out of the implicit list and cannot be named)
+            if columns.is_empty() {
+                target_cols = table.columns.iter().filter(|(c, _)| table.generated_column(c).is_none()).cloned().collect();
+            } else {
+                for ident in columns {
+                    let col = table.columns.iter().find(|(c, _)| *c == ident.value)
+                        .ok_or(format!("Column '{}' not found", ident.value))?;
+                    if table.generated_column(&col.0).is_some() {
+                        return Err(format!("Cannot insert into generated column '{}'", col.0));
                     }
+                    target_cols.push(col.clone());
                 }
             }
-            db.tables.insert(table_name.clone(), table);
-            Ok(format!("Table '{}' created", table_name))
-        }
 
-        // INSERT
-        Statement::Insert { table_name, source, .. } => {
-            let name = table_name.to_string();
-            let table = db.tables.get_mut(&name).ok_or(format!("Table '{}' not found", name))?;
-            
             match &*source.body {
                 SetExpr::Values(Values { rows, .. }) => {
-                    let mut count = 0;
+                    // All rows go in or none do: a failing row removes the rows inserted before it.
+                    // Without triggers that is all the statement wrote; with them, run_statement
+                    // also undoes what the triggers did.
+                    let last_id = db.tables[&name].last_id;
+                    let mut inserted = Vec::new();
                     for row_expr in rows {
-                        let mut row_data = BTreeMap::new();
-                        let mut cols_iter = table.columns.iter(); 
-
-                        for expr in row_expr {
-                            let (col_name, col_type) = cols_iter.next().ok_or("Too many values for table columns")?;
-                            
-                            // 1. Convert AST to our Value
-                            let value = match expr {
-                                sqlparser::ast::Expr::Value(v) => match v {
-                                    sqlparser::ast::Value::Number(n, _) => {
-                                        if n.contains('.') {
-                                            Value::Float(n.parse().unwrap_or(0.0))
-                                        } else {
-                                            Value::Integer(n.parse().unwrap_or(0))
-                                        }
-                                    },
-                                    sqlparser::ast::Value::SingleQuotedString(s) => Value::Text(s.clone()),
-                                    sqlparser::ast::Value::Boolean(b) => Value::Bool(*b),
-                                    sqlparser::ast::Value::Null => Value::Null,
-                                    _ => return Err("Unsupported value format".to_string()),
-                                },
-                                _ => return Err("Unsupported expression type".to_string()),
-                            };
-
-                            // 2. TYPE CHECK
-                            match (col_type.as_str(), &value) {
-                                ("Integer", Value::Integer(_)) => {},
-                                ("Float", Value::Float(_)) => {},
-                                ("Text", Value::Text(_)) => {},
-                                ("Bool", Value::Bool(_)) => {},
-                                (_, Value::Null) => {}, 
-                                (expected, actual) => {
-                                    return Err(format!("Type Mismatch! Column '{}' expects {}, but got {:?}", col_name, expected, actual));
-                                }
-                            }
-                            row_data.insert(col_name.clone(), value);
-                        }
-                        
-                        // 3. UNIQUE CHECK
-                        for unique_col in &table.unique_columns {
-                            if let Some(new_val) = row_data.get(unique_col) {
-                                for existing_row in table.data.values() {
-                                    if let Some(existing_val) = existing_row.data.get(unique_col) {
-                                        if existing_val == new_val {
-                                            return Err(format!("Unique constraint violation: Column '{}' already has value {:?}", unique_col, new_val));
-                                        }
-                                    }
+                        match insert_row(db, &name, &target_cols, row_expr) {
+                            Ok(Some(row_id)) => inserted.push(row_id),
+                            Ok(None) => {}
+                            Err(e) => {
+                                let table = db.tables.get_mut(&name).ok_or(format!("Table '{}' not found", name))?;
+                                for row_id in inserted {
+                                    table.take_row(row_id)?;
                                 }
+                                table.last_id = last_id;
+                                return Err(e);
                             }
                         }
-
-                        let row_id = if let Some(Value::Integer(provided_id)) = row_data.get("id") {
-                            *provided_id as u32 // Use user's ID (e.g. 96600)
-                        } else {
-                            table.last_id + 1 // Auto-increment if no ID provided
-                        };
-                        if row_id > table.last_id {
-                            table.last_id = row_id;
-                        }
-                       table.data.insert(row_id, Row { id: row_id, data: row_data });
-                       count += 1;
                     }
-                    Ok(format!("Inserted {} rows", count))
+                    Ok(format!("Inserted {} rows", inserted.len()))
                 }
                 _ => Err("Only INSERT VALUES is supported".to_string()),
             }
@@ -190,120 +539,18 @@ fn process_command(db: &mut Database, stmt: &Statement) -> Result<String, String
 
         // SELECT (With JOIN Support)
         Statement::Query(query) => {
-            if let SetExpr::Select(select) = &*query.body {
-                let left_table_name = match &select.from[0].relation {
-                    TableFactor::Table { name, .. } => name.to_string(),
-                    _ => return Err("Only simple table names supported".to_string()),
-                };
-                let left_table = db.tables.get(&left_table_name).ok_or(format!("Table '{}' not found", left_table_name))?;
-
-                if !select.from[0].joins.is_empty() {
-                    // --- JOIN LOGIC ---
-                    let join = &select.from[0].joins[0]; 
-                    let right_table_name = match &join.relation {
-                        TableFactor::Table { name, .. } => name.to_string(),
-                        _ => return Err("Only simple table joins supported".to_string()),
-                    };
-                    let right_table = db.tables.get(&right_table_name).ok_or(format!("Table '{}' not found", right_table_name))?;
-
-                    let (left_col_name, right_col_name) = match &join.join_operator {
-                        JoinOperator::Inner(JoinConstraint::On(Expr::BinaryOp { left, op: BinaryOperator::Eq, right })) => {
-                            fn extract_col(expr: &Expr) -> Option<String> {
-                                match expr {
-                                    Expr::Identifier(ident) => Some(ident.value.clone()),
-                                    Expr::CompoundIdentifier(idents) => Some(idents.last()?.value.clone()),
-                                    _ => None
-                                }
-                            }
-                            match (extract_col(left), extract_col(right)) {
-                                (Some(l), Some(r)) => (l, r),
-                                _ => return Err("Unsupported ON condition".to_string()),
-                            }
-                        },
-                        _ => return Err("Only INNER JOIN ... ON supported".to_string()),
-                    };
-
-                    // Headers
-                    let mut headers = vec![];
-                    for (col, _) in &left_table.columns { headers.push(format!("{}.{}", left_table_name, col)); }
-                    for (col, _) in &right_table.columns { headers.push(format!("{}.{}", right_table_name, col)); }
-                    
-                    let mut output_lines = Vec::new();
-                    output_lines.push(headers.join(" | ")); // Header row
-
-                    // Loop
-                    for left_row in left_table.data.values() {
-                        for right_row in right_table.data.values() {
-                            let l_val = left_row.data.get(&left_col_name).unwrap_or(&Value::Null);
-                            let r_val = right_row.data.get(&right_col_name).unwrap_or(&Value::Null);
-
-                            if l_val != &Value::Null && l_val == r_val {
-                                let mut row_strs = vec![];
-                                for (col, _) in &left_table.columns { row_strs.push(format!("{:?}", left_row.data.get(col).unwrap_or(&Value::Null))); }
-                                for (col, _) in &right_table.columns { row_strs.push(format!("{:?}", right_row.data.get(col).unwrap_or(&Value::Null))); }
-                                output_lines.push(row_strs.join(" | "));
-                            }
-                        }
-                    }
-                    Ok(output_lines.join("\n"))
-
-              } else {
-                    // --- STANDARD SELECT (No Join) ---
-                    
-                    // 1. Determine which columns to show
-                    let all_columns: Vec<String> = left_table.columns.iter().map(|(n, _)| n.clone()).collect();
-                    let mut target_columns = Vec::new();
-
-                    for item in &select.projection {
-                        match item {
-                            // If "SELECT *", take everything
-                            sqlparser::ast::SelectItem::Wildcard(_) => {
-                                target_columns = all_columns.clone();
-                                break;
-                            },
-                            // If "SELECT name", take just that column
-                            sqlparser::ast::SelectItem::UnnamedExpr(Expr::Identifier(ident)) => {
-                                let col_name = ident.value.clone();
-                                if all_columns.contains(&col_name) {
-                                    target_columns.push(col_name); 
-                                } else {
-                                     return Err(format!("Column '{}' not found", col_name));
-                                }
-                            },
-                            _ => return Err("Only SELECT * or SELECT col supported".to_string()),
-                        }
-                    }
+            let query = sequence::bind_query(db, query)?;
+            Ok(render(&run_query(db, &query)?))
+        }
 
-                    // 2. Print Headers
-                    let header_display: Vec<&str> = target_columns.iter().map(|s| s.as_str()).collect();
-                    
-
-                    // 3. Print Rows (Only the requested columns)
-                    let mut output_lines = Vec::new();
-                    // Note: We reconstruct the output string for the Server response too
-                    output_lines.push(format!("ID | {}", header_display.join(" | ")));
-
-                    for row in left_table.data.values() {
-                        let mut values = vec![];
-                        for col in &target_columns {
-                            let val = row.data.get(col).unwrap_or(&Value::Null);
-                            let v_str = match val {
-                                Value::Integer(i) => i.to_string(),
-                                Value::Float(f) => f.to_string(),
-                                Value::Text(t) => t.clone(),
-                                Value::Bool(b) => b.to_string(),
-                                Value::Null => "NULL".to_string(),
-                            };
-                            values.push(v_str);
-                        }
-                        
-                        output_lines.push(format!("{}  | {}", row.id, values.join(" | "))); // Save for Server
-                    }
-                    Ok(output_lines.join("\n"))
-                }
-            } else {
-                Err("Only SELECT statements supported".to_string())
-            }
+        // EXPLAIN [ANALYZE] [VERBOSE] SELECT ...
+        Statement::Explain { analyze, verbose, statement, .. } => {
+            let Statement::Query(query) = &**statement else {
+                return Err("EXPLAIN supports SELECT statements only".to_string());
+            };
+            // Only ANALYZE runs the query, and with it any nextval() calls
+            let query = if *analyze { sequence::bind_query(db, query)? } else { Cow::Borrowed(&**query) };
+            Ok(render(&physical::explain(db, &query, *analyze, *verbose)?))
         }
 
       // DELETE (Fixed for standard 'DELETE FROM table')
@@ -313,114 +560,958 @@ fn process_command(db: &mut Database, stmt: &Statement) -> Result<String, String
             // Non-standard "DELETE table FROM..." uses the 'tables' field.
             let table_name = if !from.is_empty() {
                 match &from[0].relation {
-                    TableFactor::Table { name, .. } => name.to_string(),
+                    TableFactor::Table { name, .. } => object_name(name),
                     _ => return Err("Only simple table names supported".to_string()),
                 }
             } else if !tables.is_empty() {
-                tables[0].to_string()
+                object_name(&tables[0])
             } else {
                 return Err("No table specified".to_string());
             };
 
-            let table = db.tables.get_mut(&table_name).ok_or(format!("Table '{}' not found", table_name))?;
+            view::reject_write(db, &table_name)?;
 
-            // 2. Extract ID from "WHERE id = X"
-            if let Some(Expr::BinaryOp { left, op: BinaryOperator::Eq, right }) = selection {
-                let col_name = match &**left { 
-                    Expr::Identifier(i) => i.value.clone(), 
-                    _ => return Err("Left side must be column name".to_string()) 
-                };
-                
-                if col_name.to_lowercase() != "id" {
-                    return Err("For this demo, you can only DELETE by 'id' (e.g. WHERE id = 1)".to_string());
-                }
+            let table = db.tables.get(&table_name).ok_or(format!("Table '{}' not found", table_name))?;
 
-                let id_val = match &**right { 
-                    Expr::Value(sqlparser::ast::Value::Number(n, _)) => n.parse::<u32>().unwrap_or(0), 
-                    _ => return Err("ID must be a number".to_string()) 
-                };
+            // 2. Resolve "WHERE col = X" to the matching rows
+            if selection.is_none() {
+                return Err("DELETE must have a WHERE col = X clause".to_string());
+            }
+            let (col_name, target, row_ids) = find_rows(table, selection)?;
+            if row_ids.is_empty() {
+                // Inside a trigger body matching nothing is normal (a parent with no children)
+                if db.trigger_depth > 0 {
+                    return Ok("Deleted 0 rows".to_string());
+                }
+                return Err(format!("No row found where {} = {:?}", col_name, target));
+            }
 
-                if table.data.remove(&id_val).is_some() {
-                    Ok(format!("Deleted row with id {}", id_val))
-                } else {
-                    Err(format!("ID {} not found", id_val))
+            // BEFORE DELETE triggers may skip rows
+            let mut doomed = Vec::new();
+            for row_id in row_ids {
+                let table = &db.tables[&table_name];
+                let Some(old) = table.data.get(&row_id).map(|row| table.read_row(row)).transpose()?.map(|row| row.data.clone()) else { continue };
+                if trigger::fire(db, Timing::Before, Event::Delete, &table_name, Some(&old), None, &[])? {
+                    doomed.push((row_id, old));
                 }
-            } else {
-                Err("DELETE must have a WHERE id = X clause".to_string())
             }
+            let table = db.tables.get_mut(&table_name).ok_or(format!("Table '{}' not found", table_name))?;
+            let mut deleted = Vec::new();
+            for (row_id, _) in &doomed {
+                if let Some(row) = table.take_row(*row_id)? {
+                    deleted.push(row.data);
+                }
+            }
+
+            // 3. Referencing rows follow ON DELETE
+            foreign_key::on_parent_delete(db, &table_name, &deleted)?;
+            for (_, old) in &doomed {
+                trigger::fire(db, Timing::After, Event::Delete, &table_name, Some(old), None, &[])?;
+            }
+            Ok(format!("Deleted {} rows", deleted.len()))
         }
 
         // UPDATE (Simple: UPDATE table SET col = val WHERE id = X)
         Statement::Update { table, assignments, selection, .. } => {
             let name = match &table.relation {
-                TableFactor::Table { name, .. } => name.to_string(),
+                TableFactor::Table { name, .. } => object_name(name),
                 _ => return Err("Only simple table names supported".to_string()),
             };
+            view::reject_write(db, &name)?;
+            let db_table = db.tables.get(&name).ok_or(format!("Table '{}' not found", name))?;
+
+            // 1. Resolve "WHERE col = X" to the matching rows
+            if selection.is_none() {
+                return Err("Missing WHERE col = X clause".to_string());
+            }
+            let (col_name, target, row_ids) = find_rows(db_table, selection)?;
+            if row_ids.is_empty() {
+                if db.trigger_depth > 0 {
+                    return Ok("Updated 0 rows".to_string());
+                }
+                return Err(format!("No row found where {} = {:?}", col_name, target));
+            }
+
+            // 2. Apply Assignments to a copy of each row and validate it before writing anything
+            let assigned: Vec<String> = assignments.iter().map(|a| a.id[0].value.clone()).collect();
+            let mut changes = Vec::new();
+            for row_id in &row_ids {
+                let db_table = &db.tables[&name];
+                let old_data = db_table.read_row(&db_table.data[row_id])?.data.clone();
+                let mut new_data = old_data.clone();
+                for assignment in assignments {
+                    let col_name = assignment.id[0].value.clone();
+                    let db_table = &db.tables[&name];
+                    let col_type = db_table.columns.iter().find(|(c, _)| *c == col_name)
+                        .map(|(_, t)| t.clone())
+                        .ok_or(format!("Column '{}' not found", col_name))?;
+                    if db_table.generated_column(&col_name).is_some() {
+                        return Err(format!("Cannot update generated column '{}'", col_name));
+                    }
+                    // Right-hand sides see the row as it was before the UPDATE
+                    let new_val = if is_default_keyword(&assignment.value) {
+                        db.default_value(&name, &col_name)?
+                    } else if db_table.identity_always.contains(&col_name) {
+                        return Err(format!("Column '{}' is GENERATED ALWAYS AS IDENTITY and can only be updated to DEFAULT", col_name));
+                    } else {
+                        eval_expr(&*sequence::bind_expr(db, &assignment.value)?, &old_data)?
+                    };
+                    let new_val = col_type.coerce(&col_name, new_val)?;
+                    new_data.insert(col_name, new_val);
+                }
+
+                // BEFORE UPDATE triggers may change the new row or skip it
+                if !trigger::fire(db, Timing::Before, Event::Update, &name, Some(&old_data), Some(&mut new_data), &assigned)? {
+                    continue;
+                }
+                let db_table = &db.tables[&name];
+                db_table.fill_generated(&mut new_data, true)?;
+                db_table.check_constraints(&new_data)?;
+                foreign_key::check_references(db, &name, &new_data)?;
+                changes.push((*row_id, old_data, new_data));
+            }
+
+            // 3. Write the rows, then let referencing rows follow ON UPDATE
             let db_table = db.tables.get_mut(&name).ok_or(format!("Table '{}' not found", name))?;
+            for (row_id, _, new_data) in &changes {
+                if db_table.data.contains_key(row_id) {
+                    db_table.put_row(*row_id, new_data.clone())?;
+                }
+            }
+            // Keys are checked once every row is written, so rows can swap keys but two rows
+            // cannot both take the same one
+            let key_error = changes.iter()
+                .map(|(row_id, _, new_data)| db_table.check_primary_key(new_data, Some(*row_id)).and(db_table.check_unique(new_data, Some(*row_id))))
+                .find_map(Result::err);
+            if let Some(e) = key_error {
+                for (row_id, old_data, _) in &changes {
+                    if db_table.data.contains_key(row_id) {
+                        db_table.put_row(*row_id, old_data.clone())?;
+                    }
+                }
+                return Err(e);
+            }
+            let changed: Vec<_> = changes.into_iter().map(|(_, old, new)| (old, new)).collect();
+            foreign_key::on_parent_update(db, &name, &changed)?;
+            for (old, new) in &changed {
+                trigger::fire(db, Timing::After, Event::Update, &name, Some(old), Some(&mut new.clone()), &assigned)?;
+            }
+            Ok(format!("Updated {} rows", changed.len()))
+        }
 
-            // 1. Get ID from WHERE clause
-            let id_val = if let Some(Expr::BinaryOp { left, op: BinaryOperator::Eq, right }) = selection {
-                 let col = match &**left { Expr::Identifier(i) => i.value.clone(), _ => return Err("Left side must be col".to_string()) };
-                 if col.to_lowercase() != "id" { return Err("Only UPDATE WHERE id = ... supported".to_string()); }
-                 match &**right { Expr::Value(sqlparser::ast::Value::Number(n, _)) => n.parse::<u32>().unwrap_or(0), _ => return Err("ID must be number".to_string()) }
-            } else {
-                return Err("Missing WHERE id = clause".to_string());
-            };
+        // SHOW TABLES, SHOW COLUMNS FROM t, DESCRIBE t and SHOW CREATE TABLE t read the catalog
+        Statement::ShowTables { .. } | Statement::ShowColumns { .. } | Statement::ExplainTable { .. } | Statement::ShowCreate { .. } => {
+            Ok(render(&run_query(db, &catalog::show_query(db, stmt)?)?))
+        }
 
-            // 2. Find Row
-            let row = db_table.data.get_mut(&id_val).ok_or(format!("ID {} not found", id_val))?;
+        // CREATE [OR REPLACE] [MATERIALIZED] VIEW name [(a, b)] AS SELECT ...
+        Statement::CreateView { or_replace, materialized, name, columns, query, if_not_exists, .. } => {
+            view::create(db, view::CreateView {
+                name: object_name(name),
+                column_names: columns.iter().map(|c| c.value.clone()).collect(),
+                query,
+                materialized: *materialized,
+                or_replace: *or_replace,
+                if_not_exists: *if_not_exists,
+            })
+        }
+        Statement::Drop { object_type: ObjectType::View, if_exists, names, cascade, .. } => {
+            view::drop(db, names, *if_exists, *cascade, false)
+        }
 
-            // 3. Apply Assignments
-            for assignment in assignments {
-                let col_name = assignment.id[0].value.clone();
-                let new_val = match &assignment.value {
-                    Expr::Value(v) => match v {
-                        sqlparser::ast::Value::Number(n, _) => if n.contains('.') { Value::Float(n.parse().unwrap_or(0.0)) } else { Value::Integer(n.parse().unwrap_or(0)) },
-                        sqlparser::ast::Value::SingleQuotedString(s) => Value::Text(s.clone()),
-                        sqlparser::ast::Value::Boolean(b) => Value::Bool(*b),
-                        _ => Value::Null,
-                    },
-                    _ => return Err("Unsupported value".to_string()),
-                };
-                
-                // (Optional: You should add Type Checking here similar to INSERT)
-                row.data.insert(col_name, new_val);
+        Statement::Drop { object_type: ObjectType::Sequence, if_exists, names, cascade, .. } => {
+            sequence::drop(db, names, *if_exists, *cascade)
+        }
+
+        // CREATE [UNIQUE] INDEX [name] ON t (a, b DESC, lower(c)) [WHERE cond], CREATE INDEX ON t USING fulltext (a, b),
+        // DROP INDEX name
+        Statement::CreateIndex { name, table_name, using, columns, unique, if_not_exists, include, nulls_distinct, predicate, .. } => {
+            index::create(db, index::CreateIndex {
+                name: name.as_ref(),
+                table_name,
+                using: using.as_ref(),
+                columns,
+                unique: *unique,
+                if_not_exists: *if_not_exists,
+                predicate: predicate.as_ref(),
+                extras: !include.is_empty() || nulls_distinct.is_some(),
+            })
+        }
+        Statement::Drop { object_type: ObjectType::Index, if_exists, names, .. } => index::drop(db, names, *if_exists),
+
+        // SCHEMAS: CREATE SCHEMA sales, then sales.orders; ATTACH 'file.json' AS archive
+        Statement::CreateSchema { schema_name: SchemaName::Simple(name), if_not_exists } => {
+            schema::create_schema(db, &name.to_string(), *if_not_exists)
+        }
+        Statement::CreateSchema { .. } => Err("Only CREATE SCHEMA name is supported".to_string()),
+        Statement::Drop { object_type: ObjectType::Schema, if_exists, names, cascade, .. } => {
+            schema::drop_schema(db, names, *if_exists, *cascade)
+        }
+        Statement::AttachDatabase { schema_name, database_file_name, .. } => {
+            match eval_expr(database_file_name, &BTreeMap::new())? {
+                Value::Text(path) => schema::attach(db, &path, &schema_name.value),
+                other => Err(format!("ATTACH expects a file name, not {:?}", other)),
             }
-            Ok(format!("Updated row {}", id_val))
         }
 
+        // TRANSACTIONS: BEGIN snapshots the tables, ROLLBACK restores them, COMMIT keeps them
+        Statement::StartTransaction { .. } => {
+            if db.transaction.is_some() {
+                return Err("A transaction is already in progress".to_string());
+            }
+            db.transaction = Some(Box::new(db.clone()));
+            Ok("Transaction started".to_string())
+        }
+        Statement::Commit { .. } => {
+            let snapshot = db.transaction.take().ok_or("No transaction in progress")?;
+            // Deferred foreign keys are verified now; a violation aborts the transaction
+            let deferred = std::mem::take(&mut db.defer_foreign_keys);
+            if deferred
+                && let Err(e) = foreign_key::check_all(db)
+            {
+                *db = *snapshot;
+                return Err(format!("COMMIT failed, transaction rolled back: {}", e));
+            }
+            Ok("Transaction committed".to_string())
+        }
+        Statement::Rollback { .. } => {
+            let snapshot = db.transaction.take().ok_or("No transaction in progress")?;
+            *db = *snapshot;
+            db.defer_foreign_keys = false;
+            Ok("Transaction rolled back".to_string())
+        }
+
+        // PRAGMA defer_foreign_keys = 1 | 0
+        Statement::Pragma { name, value, .. } => match name.to_string().to_lowercase().as_str() {
+            "defer_foreign_keys" => {
+                if let Some(v) = value {
+                    db.defer_foreign_keys = match v.to_string().trim_matches('\'').to_lowercase().as_str() {
+                        "1" | "on" | "true" => true,
+                        "0" | "off" | "false" => false,
+                        other => return Err(format!("Invalid value for defer_foreign_keys: {}", other)),
+                    };
+                }
+                Ok(format!("defer_foreign_keys = {}", db.defer_foreign_keys as u8))
+            }
+            other => Err(format!("Unknown pragma: {}", other)),
+        },
+
         _ => Err("SQL command not supported yet".to_string()),
     }
 }
 
+// One row of INSERT ... VALUES: fills defaults, runs BEFORE triggers, checks constraints and
+// stores it. Returns the new rowid, or None if a BEFORE trigger skipped the row.
+fn insert_row(db: &mut Database, name: &str, target_cols: &[(String, ColumnType)], row_expr: &[Expr]) -> Result<Option<u64>, String> {
+    if row_expr.len() > target_cols.len() {
+        return Err("Too many values for table columns".to_string());
+    }
+    let mut row_data = BTreeMap::new();
+
+    for ((col_name, col_type), expr) in target_cols.iter().zip(row_expr) {
+        // 1. Convert AST to our Value (the DEFAULT keyword uses the column default)
+        let value = if is_default_keyword(expr) {
+            db.default_value(name, col_name)?
+        } else if db.tables[name].identity_always.contains(col_name) {
+            return Err(format!("Cannot insert into column '{}': it is GENERATED ALWAYS AS IDENTITY", col_name));
+        } else {
+            eval_expr(&*sequence::bind_expr(db, expr)?, &BTreeMap::new())?
+        };
+
+        // 2. TYPE CHECK
+        row_data.insert(col_name.clone(), col_type.coerce(col_name, value)?);
+    }
+
+    // Columns not given a value take their DEFAULT (or NULL)
+    let missing: Vec<String> = db.tables[name].columns.iter()
+        .map(|(c, _)| c.clone())
+        .filter(|c| !row_data.contains_key(c))
+        .collect();
+    for col_name in missing {
+        let value = db.default_value(name, &col_name)?;
+        row_data.insert(col_name, value);
+    }
+
+    // BEFORE INSERT triggers may change the row or skip it
+    if !trigger::fire(db, Timing::Before, Event::Insert, name, None, Some(&mut row_data), &[])? {
+        return Ok(None);
+    }
+    let table = &db.tables[name];
+    table.fill_generated(&mut row_data, true)?;
+    table.check_constraints(&row_data)?;
+
+    // 3. PRIMARY KEY + UNIQUE CHECK
+    table.check_primary_key(&row_data, None)?;
+    table.check_unique(&row_data, None)?;
+
+    // 4. FOREIGN KEY CHECK
+    foreign_key::check_references(db, name, &row_data)?;
+
+    // The rowid is internal and always auto-assigned; user columns
+    // (including one named `id`) never address the storage map.
+    let table = db.tables.get_mut(name).ok_or(format!("Table '{}' not found", name))?;
+    let row_id = table.last_id + 1;
+    table.last_id = row_id;
+    table.put_row(row_id, row_data.clone())?;
+    trigger::fire(db, Timing::After, Event::Insert, name, None, Some(&mut row_data), &[])?;
+    Ok(Some(row_id))
+}
+
+// CREATE TABLE
+fn create_table(db: &mut Database, def: &CreateTable) -> Result<String, String> {
+    let table_name = def.name.clone();
+    if db.tables.contains_key(&table_name) || db.views.contains_key(&table_name) {
+        if def.if_not_exists {
+            return Ok(format!("Table '{}' already exists, skipping", table_name));
+        }
+        return Err(format!("Table '{}' already exists", table_name));
+    }
+    schema::check_schema(db, &table_name)?;
+    let mut table = Table::new(table_name.clone());
+    // SERIAL and IDENTITY columns, with the options for their sequence
+    let mut numbered = Vec::new();
+
+    for col in &def.columns {
+        let col_name = col.name.to_string();
+        let serial = ColumnType::serial(&col.data_type);
+        let col_type = match &serial {
+            Some(col_type) => col_type.clone(),
+            None => resolve_type(db, &col.data_type)?,
+        };
+        if serial.is_some() {
+            numbered.push((col_name.clone(), col_type.clone(), Vec::new()));
+        }
+        table.columns.push((col_name.clone(), col_type.clone()));
+
+        // Column Constraints
+        for option in &col.options {
+            match &option.option {
+                ColumnOption::Unique { is_primary: true } => {
+                    if !table.primary_key.is_empty() {
+                        return Err(format!("Table '{}' has more than one primary key", table_name));
+                    }
+                    table.primary_key.push(col_name.clone());
+                }
+                ColumnOption::Unique { is_primary: false } => {
+                    table.add_unique(option.name.as_ref().map(|n| n.value.clone()), vec![col_name.clone()])?;
+                }
+                ColumnOption::NotNull => table.not_null.push(col_name.clone()),
+                ColumnOption::Null => {}
+                ColumnOption::Default(expr) => {
+                    table.defaults.insert(col_name.clone(), expr.to_string());
+                }
+                ColumnOption::Check(expr) => {
+                    let name = option.name.as_ref().map(|n| n.value.clone())
+                        .unwrap_or_else(|| format!("{}_{}_check", table_name, col_name));
+                    table.checks.push(CheckConstraint { name, expr: expr.to_string() });
+                }
+                // GENERATED { ALWAYS | BY DEFAULT } AS IDENTITY [(sequence options)]
+                ColumnOption::Generated { generated_as: generated_as @ (GeneratedAs::Always | GeneratedAs::ByDefault), sequence_options, .. } => {
+                    if serial.is_some() || numbered.iter().any(|(c, _, _)| *c == col_name) {
+                        return Err(format!("Column '{}' is already numbered by a sequence", col_name));
+                    }
+                    if !matches!(col_type, ColumnType::SmallInt | ColumnType::Integer | ColumnType::BigInt) {
+                        return Err(format!("Identity column '{}' must be SMALLINT, INT or BIGINT", col_name));
+                    }
+                    if *generated_as == GeneratedAs::Always {
+                        table.identity_always.push(col_name.clone());
+                    }
+                    numbered.push((col_name.clone(), col_type.clone(), sequence_options.clone().unwrap_or_default()));
+                }
+                ColumnOption::ForeignKey { foreign_table, referred_columns, on_delete, on_update } => {
+                    let name = option.name.as_ref().map(|n| n.value.clone())
+                        .unwrap_or_else(|| format!("{}_{}_fkey", table_name, col_name));
+                    table.foreign_keys.push(ForeignKey {
+                        name,
+                        columns: vec![col_name.clone()],
+                        ref_table: object_name(foreign_table),
+                        ref_columns: referred_columns.iter().map(|i| i.value.clone()).collect(),
+                        on_delete: RefAction::from_ast(on_delete),
+                        on_update: RefAction::from_ast(on_update),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Table-level PRIMARY KEY (a, b) and CHECK (expr)
+    let mut unnamed_checks = 0;
+    for constraint in &def.constraints {
+        if let TableConstraint::Check { name, expr } = constraint {
+            let name = match name {
+                Some(n) => n.value.clone(),
+                None => {
+                    unnamed_checks += 1;
+                    if unnamed_checks == 1 { format!("{}_check", table_name) } else { format!("{}_check{}", table_name, unnamed_checks) }
+                }
+            };
+            table.checks.push(CheckConstraint { name, expr: expr.to_string() });
+        }
+        if let TableConstraint::ForeignKey { name, columns: fk_cols, foreign_table, referred_columns, on_delete, on_update } = constraint {
+            let columns: Vec<String> = fk_cols.iter().map(|i| i.value.clone()).collect();
+            let name = name.as_ref().map(|n| n.value.clone())
+                .unwrap_or_else(|| format!("{}_{}_fkey", table_name, columns.join("_")));
+            table.foreign_keys.push(ForeignKey {
+                name,
+                columns,
+                ref_table: object_name(foreign_table),
+                ref_columns: referred_columns.iter().map(|i| i.value.clone()).collect(),
+                on_delete: RefAction::from_ast(on_delete),
+                on_update: RefAction::from_ast(on_update),
+            });
+        }
+        if let TableConstraint::Unique { name, columns: key_cols, is_primary: false } = constraint {
+            table.add_unique(name.as_ref().map(|n| n.value.clone()), key_cols.iter().map(|i| i.value.clone()).collect())?;
+        }
+        if let TableConstraint::Unique { columns: key_cols, is_primary: true, .. } = constraint {
+            if !table.primary_key.is_empty() {
+                return Err(format!("Table '{}' has more than one primary key", table_name));
+            }
+            for ident in key_cols {
+                let col_name = ident.value.clone();
+                if !table.columns.iter().any(|(c, _)| *c == col_name) {
+                    return Err(format!("Primary key column '{}' does not exist", col_name));
+                }
+                table.primary_key.push(col_name);
+            }
+        }
+    }
+
+    // SERIAL and IDENTITY columns are NOT NULL and take nextval() of their own sequence as their DEFAULT
+    let mut sequences = Vec::new();
+    for (col_name, col_type, options) in numbered {
+        if table.defaults.contains_key(&col_name) {
+            return Err(format!("Column '{}' cannot have both a DEFAULT and SERIAL or IDENTITY", col_name));
+        }
+        let seq_name = sequence::owned_name(&table_name, &col_name);
+        if db.sequences.contains_key(&seq_name) {
+            return Err(format!("Sequence '{}' already exists", seq_name));
+        }
+        let mut seq = sequence::Sequence::new(&seq_name, Some(&col_type), &options)?;
+        seq.owned_by = Some(format!("{}.{}", table_name, col_name));
+        table.defaults.insert(col_name.clone(), format!("nextval('{}')", seq_name.replace('\'', "''")));
+        if !table.not_null.contains(&col_name) {
+            table.not_null.push(col_name);
+        }
+        sequences.push(seq);
+    }
+
+    // Generated columns: no DEFAULT, and VIRTUAL values (which are never stored) cannot be keys
+    for g in &def.generated {
+        if table.defaults.contains_key(&g.column) {
+            return Err(format!("Generated column '{}' cannot have a DEFAULT", g.column));
+        }
+        let keyed = table.primary_key.contains(&g.column)
+            || table.uniques.iter().any(|u| u.columns.contains(&g.column))
+            || table.foreign_keys.iter().any(|fk| fk.columns.contains(&g.column));
+        if keyed && !g.stored {
+            return Err(format!("VIRTUAL generated column '{}' cannot be part of a key; make it STORED", g.column));
+        }
+        table.generated.push(GeneratedColumn { column: g.column.clone(), expr: g.expr.to_string(), stored: g.stored });
+    }
+    // Each expression may use the ordinary columns and generated columns declared before it
+    let mut visible: BTreeMap<String, Value> = table.columns.iter()
+        .filter(|(c, _)| table.generated_column(c).is_none())
+        .map(|(c, _)| (c.clone(), Value::Null))
+        .collect();
+    for g in &table.generated {
+        eval_expr(&parse_expr(&g.expr)?, &visible).map_err(|e| format!("{} (in generated column '{}')", e, g.column))?;
+        visible.insert(g.column.clone(), Value::Null);
+    }
+
+    // Validate DEFAULTs and CHECKs now, so a typo fails at CREATE time instead of on every insert
+    // (a DEFAULT calling nextval() runs against a copy of the sequences, so none of them advances)
+    let null_row: BTreeMap<String, Value> = table.columns.iter().map(|(c, _)| (c.clone(), Value::Null)).collect();
+    let mut scratch = Database::new();
+    scratch.sequences = db.sequences.clone();
+    scratch.sequences.extend(sequences.iter().map(|seq| (seq.name.clone(), seq.clone())));
+    for (col_name, _) in &table.columns {
+        match table.defaults.get(col_name) {
+            Some(sql) if sequence::mentioned(sql) => {
+                eval_expr(&parse_expr(&sequence::bind(&mut scratch, sql)?)?, &BTreeMap::new())?;
+            }
+            _ => {
+                table.default_value(col_name)?;
+            }
+        }
+    }
+    for check in &table.checks {
+        eval_expr(&parse_expr(&check.expr)?, &null_row)?;
+    }
+
+    // Resolve foreign keys: the referenced columns default to the parent's primary key,
+    // and must be its primary key or a UNIQUE key so each child matches at most one parent.
+    let mut foreign_keys = std::mem::take(&mut table.foreign_keys);
+    for fk in &mut foreign_keys {
+        schema::check_same_database(db, &table_name, &fk.ref_table)?;
+        let parent = if fk.ref_table == table_name {
+            &table
+        } else {
+            db.tables.get(&fk.ref_table).ok_or(format!("Referenced table '{}' not found", fk.ref_table))?
+        };
+        if fk.ref_columns.is_empty() {
+            fk.ref_columns = parent.primary_key.clone();
+        }
+        if !parent.is_unique_key(&fk.ref_columns) {
+            return Err(format!("Foreign key '{}' must reference a PRIMARY KEY or UNIQUE key of '{}'", fk.name, fk.ref_table));
+        }
+        if fk.ref_columns.len() != fk.columns.len() {
+            return Err(format!("Foreign key '{}' has {} column(s) but references {}", fk.name, fk.columns.len(), fk.ref_columns.len()));
+        }
+        for col in &fk.columns {
+            if !table.columns.iter().any(|(c, _)| c == col) {
+                return Err(format!("Foreign key column '{}' does not exist", col));
+            }
+        }
+    }
+    table.foreign_keys = foreign_keys;
+    table.index_constraints()?;
+    db.sequences.extend(sequences.into_iter().map(|seq| (seq.name.clone(), seq)));
+    db.tables.insert(table_name.clone(), table);
+    Ok(format!("Table '{}' created", table_name))
+}
+
+// The name a table, view or sequence is stored under: `schema.name`, or the bare name in the
+// default schema, so `main.orders` and `orders` are the same table.
+pub fn object_name(name: &ObjectName) -> String {
+    match name.0.as_slice() {
+        [schema, rest @ ..] if !rest.is_empty() && schema.value.eq_ignore_ascii_case(schema::MAIN) => ObjectName(rest.to_vec()).to_string(),
+        _ => name.to_string(),
+    }
+}
+
+// `DEFAULT` in a VALUES list or SET clause parses as a bare identifier.
+fn is_default_keyword(expr: &Expr) -> bool {
+    matches!(expr, Expr::Identifier(ident) if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default"))
+}
+
+// --- QUERIES ---
+// A SELECT is run into a ResultStream, which the REPL and HTTP handler render line by line as
+// its rows are read; views and EXPLAIN collect one into a ResultSet.
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    // (rowid, values); the rowid is only shown by the table layout
+    pub rows: Vec<(u64, Vec<Value>)>,
+    layout: Layout,
+}
+
+// (rowid, values) rows, each computed when it is pulled
+pub type ResultRows<'a> = Box<dyn Iterator<Item = Result<(u64, Vec<Value>), String>> + 'a>;
+
+// A SELECT's result as it is read
+pub struct ResultStream<'a> {
+    pub columns: Vec<String>,
+    pub rows: ResultRows<'a>,
+    layout: Layout,
+}
+
+impl ResultStream<'_> {
+    pub fn collect(self) -> Result<ResultSet, String> {
+        Ok(ResultSet { columns: self.columns, rows: self.rows.collect::<Result<_, _>>()?, layout: self.layout })
+    }
+}
+
+enum Layout {
+    // "ID | a | b", each row led by its rowid
+    Table,
+    // JOIN output: values in their Debug form, which the web demo unwraps (`Text("x")`)
+    Join,
+    // SELECT without FROM and aggregate queries: no rowid
+    Single,
+}
+
+fn header_line(columns: &[String], layout: &Layout) -> String {
+    let headers = columns.join(" | ");
+    match layout {
+        Layout::Table => format!("ID | {}", headers),
+        _ => headers,
+    }
+}
+
+fn row_line(id: u64, values: &[Value], layout: &Layout) -> String {
+    let shown: Vec<String> = match layout {
+        Layout::Join => values.iter().map(|v| format!("{:?}", v)).collect(),
+        _ => values.iter().map(expr::display_value).collect(),
+    };
+    match layout {
+        Layout::Table => format!("{}  | {}", id, shown.join(" | ")),
+        _ => shown.join(" | "),
+    }
+}
+
+fn render(result: &ResultSet) -> String {
+    let mut lines = vec![header_line(&result.columns, &result.layout)];
+    for (id, values) in &result.rows {
+        lines.push(row_line(*id, values, &result.layout));
+    }
+    lines.join("\n")
+}
+
+// Renders a result line by line as its rows are read, passing each line to `emit` until it
+// returns false. The first row is read before the header, so a query that fails at once
+// reports only its error.
+fn render_stream(result: ResultStream, emit: &mut dyn FnMut(String) -> bool) -> Result<(), String> {
+    let ResultStream { columns, mut rows, layout } = result;
+    let first = rows.next().transpose()?;
+    if !emit(header_line(&columns, &layout)) {
+        return Ok(());
+    }
+    for row in first.into_iter().map(Ok).chain(rows) {
+        let (id, values) = row?;
+        if !emit(row_line(id, &values, &layout)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run_query(db: &Database, query: &Query) -> Result<ResultSet, String> {
+    stream_query(db, query)?.collect()
+}
+
+fn stream_query<'a>(db: &'a Database, query: &Query) -> Result<ResultStream<'a>, String> {
+    physical::execute(db, &physical::plan(db, rewrite::rewrite(db, plan::build(query)?)?)?)
+}
+
+// Columns and rows of a table, a catalog relation, a view (whose query runs now) or a materialized view.
+fn relation<'a>(db: &'a Database, name: &str) -> Result<(Vec<String>, Vec<Cow<'a, Row>>), String> {
+    if let Some(table) = db.tables.get(name) {
+        let rows = table.data.values().map(|row| table.read_row(row)).collect::<Result<_, _>>()?;
+        return Ok((table.columns.iter().map(|(c, _)| c.clone()).collect(), rows));
+    }
+    if let Some(snapshot) = catalog::relation(db, name) {
+        return Ok((snapshot.columns, snapshot.rows.into_iter().map(Cow::Owned).collect()));
+    }
+    let view = db.views.get(name).ok_or(format!("Table '{}' not found", name))?;
+    if let Some(snapshot) = &view.snapshot {
+        return Ok((snapshot.columns.clone(), snapshot.rows.iter().map(Cow::Borrowed).collect()));
+    }
+    let snapshot = view.evaluate(db)?;
+    Ok((snapshot.columns, snapshot.rows.into_iter().map(Cow::Owned).collect()))
+}
+
+// A table function in FROM: json_each(doc), unnest(arr), ...
+struct TableFunctionCall<'a> {
+    name: String,
+    args: Vec<&'a Expr>,
+    alias: &'a Option<TableAlias>,
+}
+
+fn table_function_call(factor: &TableFactor) -> Result<Option<TableFunctionCall<'_>>, String> {
+    Ok(match factor {
+        TableFactor::Table { name, args: Some(args), alias, .. } => {
+            let mut exprs = Vec::new();
+            for arg in args {
+                match arg {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => exprs.push(e),
+                    _ => return Err(format!("Unsupported argument to {}()", name)),
+                }
+            }
+            Some(TableFunctionCall { name: name.to_string().to_lowercase(), args: exprs, alias })
+        }
+        TableFactor::UNNEST { alias, array_exprs, .. } => {
+            Some(TableFunctionCall { name: "unnest".to_string(), args: array_exprs.iter().collect(), alias })
+        }
+        _ => None,
+    })
+}
+
+impl TableFunctionCall<'_> {
+    fn builtin_columns(&self) -> Result<Vec<String>, String> {
+        let cols: &[&str] = match self.name.as_str() {
+            "json_each" => &json::EACH_COLUMNS,
+            "unnest" => &array::UNNEST_COLUMNS,
+            _ => return Err(format!("Unknown table function: {}()", self.name)),
+        };
+        Ok(cols.iter().map(|c| c.to_string()).collect())
+    }
+
+    // Output columns; `AS t(a, b)` renames them.
+    fn columns(&self) -> Result<Vec<String>, String> {
+        let mut columns = self.builtin_columns()?;
+        if let Some(alias) = self.alias {
+            for (col, renamed) in columns.iter_mut().zip(&alias.columns) {
+                *col = renamed.value.clone();
+            }
+        }
+        Ok(columns)
+    }
+
+    // Evaluates the arguments against `row` and returns the rows the function produces,
+    // keyed by bare column name and by "alias.column".
+    fn rows(&self, row: &BTreeMap<String, Value>) -> Result<Vec<BTreeMap<String, Value>>, String> {
+        let values = self.args.iter().map(|e| eval_expr(e, row)).collect::<Result<Vec<_>, _>>()?;
+        let produced = match (self.name.as_str(), values.as_slice()) {
+            ("json_each", [doc]) => json::each(doc, None)?,
+            ("json_each", [doc, Value::Text(path)]) => json::each(doc, Some(path))?,
+            ("json_each", _) => return Err("json_each() expects a document and an optional path".to_string()),
+            ("unnest", [arr]) => array::unnest(arr)?,
+            ("unnest", _) => return Err("unnest() expects one array".to_string()),
+            _ => return Err(format!("Unknown table function: {}()", self.name)),
+        };
+        let qualifier = self.alias.as_ref().map(|a| a.name.value.clone()).unwrap_or(self.name.clone());
+        let builtin = self.builtin_columns()?;
+        let columns = self.columns()?;
+        Ok(produced.into_iter().map(|mut data| {
+            let mut out = BTreeMap::new();
+            for (builtin_col, col) in builtin.iter().zip(&columns) {
+                let val = data.remove(builtin_col).unwrap_or(Value::Null);
+                out.insert(format!("{}.{}", qualifier, col), val.clone());
+                out.insert(col.clone(), val);
+            }
+            out
+        }).collect())
+    }
+}
+
+// Column types may name a CREATE TYPE ... AS ENUM, also as an array element (mood[]).
+fn resolve_type(db: &Database, data_type: &DataType) -> Result<ColumnType, String> {
+    match data_type {
+        DataType::Custom(name, _) => match db.enums.get(&name.to_string()) {
+            Some(labels) => Ok(ColumnType::Enum { name: name.to_string(), labels: labels.clone() }),
+            None => ColumnType::from_ast(data_type),
+        },
+        DataType::Array(ArrayElemTypeDef::SquareBracket(inner) | ArrayElemTypeDef::AngleBracket(inner)) => {
+            Ok(ColumnType::Array(Box::new(resolve_type(db, inner)?)))
+        }
+        _ => ColumnType::from_ast(data_type),
+    }
+}
+
+// Runs a command, passing its output to `emit` a line at a time. A SELECT's rows are rendered
+// as they are read, so a large result is never held in memory; other commands emit their
+// message when they finish.
+fn execute_streamed(db: &mut Database, command: &Command, emit: &mut dyn FnMut(String) -> bool) -> Result<(), String> {
+    if let Command::Sql(stmt) = command
+        && let Statement::Query(query) = &**stmt
+    {
+        let query = sequence::bind_query(db, query)?;
+        return render_stream(stream_query(db, &query)?, emit);
+    }
+    emit(execute(db, command)?);
+    Ok(())
+}
+
+// Runs one SQL statement. A write that reaches other tables, through triggers or foreign key
+// actions, works on a snapshot of every table and puts it back if any part fails. Statements in
+// trigger bodies run inside the snapshot of the statement that fired them, since their errors
+// fail it too.
+fn run_statement(db: &mut Database, stmt: &Statement) -> Result<String, String> {
+    let writes = matches!(stmt, Statement::Insert { .. } | Statement::Update { .. } | Statement::Delete { .. });
+    let cascades = !matches!(stmt, Statement::Insert { .. }) && db.tables.values().any(|t| !t.foreign_keys.is_empty());
+    if !writes || db.trigger_depth > 0 || (db.triggers.is_empty() && !cascades) {
+        return process_command(db, stmt);
+    }
+    let snapshot = db.tables.clone();
+    let result = process_command(db, stmt);
+    if result.is_err() {
+        db.tables = snapshot;
+    }
+    result
+}
+
+// Runs one parsed command: a standard statement, or one of the extra forms in `parser`.
+fn execute(db: &mut Database, command: &Command) -> Result<String, String> {
+    match command {
+        Command::Sql(stmt) => run_statement(db, stmt),
+        Command::CreateTable(def) => create_table(db, def),
+        Command::CreateTrigger(def) => trigger::create(db, def),
+        Command::CreateSequence { name, if_not_exists, data_type, options } => {
+            schema::check_schema(db, name)?;
+            let data_type = data_type.as_ref().map(ColumnType::from_ast).transpose()?;
+            sequence::create(db, name, *if_not_exists, data_type.as_ref(), options)
+        }
+        Command::DropTrigger { name, if_exists } => trigger::drop(db, name, *if_exists),
+        Command::DropMaterializedView { names, if_exists, cascade } => view::drop(db, names, *if_exists, *cascade, true),
+        Command::RefreshMaterializedView { name } => view::refresh(db, name),
+        Command::Detach { alias } => schema::detach(db, alias),
+        Command::Analyze { tables } => stats::analyze(db, tables),
+        Command::CreateEnum { name, labels } => {
+            if db.enums.contains_key(name) {
+                return Err(format!("Type '{}' already exists", name));
+            }
+            for (i, label) in labels.iter().enumerate() {
+                if labels[..i].contains(label) {
+                    return Err(format!("Enum label '{}' is listed more than once", label));
+                }
+            }
+            db.enums.insert(name.clone(), labels.clone());
+            Ok(format!("Type '{}' created", name))
+        }
+        Command::DropType { name, if_exists } => {
+            if !db.enums.contains_key(name) {
+                return if *if_exists { Ok(format!("Type '{}' does not exist, skipping", name)) } else { Err(format!("Type '{}' not found", name)) };
+            }
+            fn uses(ty: &ColumnType, name: &str) -> bool {
+                match ty {
+                    ColumnType::Enum { name: n, .. } => n == name,
+                    ColumnType::Array(elem) => uses(elem, name),
+                    _ => false,
+                }
+            }
+            for table in db.tables.values() {
+                if let Some((col, _)) = table.columns.iter().find(|(_, ty)| uses(ty, name)) {
+                    return Err(format!("Cannot drop type '{}': column {}.{} uses it", name, table.name, col));
+                }
+            }
+            db.enums.remove(name);
+            Ok(format!("Type '{}' dropped", name))
+        }
+    }
+}
+
+// Sorts rows by ORDER BY. NULLs sort last ascending and first descending, as in PostgreSQL.
+fn order_rows<T>(items: &mut Vec<T>, order_by: &[OrderByExpr], row_of: impl Fn(&T) -> &BTreeMap<String, Value>) -> Result<(), String> {
+    if order_by.is_empty() {
+        return Ok(());
+    }
+    let mut keyed = Vec::new();
+    for item in items.drain(..) {
+        let keys = order_by.iter().map(|o| eval_expr(&o.expr, row_of(&item))).collect::<Result<Vec<_>, _>>()?;
+        keyed.push((keys, item));
+    }
+    keyed.sort_by(|(a, _), (b, _)| {
+        for (o, (x, y)) in order_by.iter().zip(a.iter().zip(b)) {
+            let asc = o.asc.unwrap_or(true);
+            let nulls_first = o.nulls_first.unwrap_or(!asc);
+            let ord = match (x, y) {
+                (Value::Null, Value::Null) => Ordering::Equal,
+                (Value::Null, _) => if nulls_first { Ordering::Less } else { Ordering::Greater },
+                (_, Value::Null) => if nulls_first { Ordering::Greater } else { Ordering::Less },
+                _ => {
+                    let ord = compare_values(x, y).unwrap_or(Ordering::Equal);
+                    if asc { ord } else { ord.reverse() }
+                }
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    });
+    items.extend(keyed.into_iter().map(|(_, item)| item));
+    Ok(())
+}
+
+// Resolves a "WHERE col = literal" clause to the rowids it matches.
+// The pseudo-column `rowid` addresses the internal row id directly.
+fn find_rows(table: &Table, selection: &Option<Expr>) -> Result<(String, Value, Vec<u64>), String> {
+    let (left, right) = match selection {
+        Some(Expr::BinaryOp { left, op: BinaryOperator::Eq, right }) => (left, right),
+        _ => return Err("Only WHERE col = X is supported".to_string()),
+    };
+    let col_name = match &**left {
+        Expr::Identifier(i) => i.value.clone(),
+        _ => return Err("Left side must be column name".to_string()),
+    };
+    let target = eval_expr(right, &BTreeMap::new())?;
+
+    if col_name.eq_ignore_ascii_case("rowid") {
+        let ids = match target {
+            Value::Integer(i) => u64::try_from(i).ok().filter(|id| table.data.contains_key(id)).into_iter().collect(),
+            _ => return Err("rowid must be a number".to_string()),
+        };
+        return Ok((col_name, target, ids));
+    }
+    let col_type = table.columns.iter().find(|(c, _)| *c == col_name).map(|(_, t)| t)
+        .ok_or(format!("Column '{}' not found", col_name))?;
+    // Read the literal as the column's type, so '...' matches a UUID or DATE column
+    let target = col_type.coerce(&col_name, target.clone()).unwrap_or(target);
+    let scanned: Vec<u64> = match index::lookup(table, &col_name, &target) {
+        Some(ids) => ids.into_iter().collect(),
+        None => table.data.keys().copied().collect(),
+    };
+    let mut ids = Vec::new();
+    for row in scanned.iter().filter_map(|id| table.data.get(id)) {
+        if table.read_row(row)?.data.get(&col_name) == Some(&target) {
+            ids.push(row.id);
+        }
+    }
+    Ok((col_name, target, ids))
+}
+
 // --- API HANDLER ---
 // This allows Node.js to talk to Rust over HTTP
+
+// A response body sent a line at a time, so a large result goes out in pieces rather than
+// being copied into one string first.
+struct Lines {
+    lines: std::vec::IntoIter<String>,
+    first: bool,
+}
+
+impl Lines {
+    fn new(lines: Vec<String>) -> Self {
+        Lines { lines: lines.into_iter(), first: true }
+    }
+}
+
+impl MessageBody for Lines {
+    type Error = std::io::Error;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let body = self.get_mut();
+        let chunk = body.lines.next().map(|line| if std::mem::take(&mut body.first) { line } else { format!("\n{}", line) });
+        Poll::Ready(chunk.map(|chunk| Ok(Bytes::from(chunk))))
+    }
+}
+
 #[post("/query")]
 async fn query_endpoint(req_body: String, db: web::Data<Mutex<Database>>) -> impl Responder {
     let input = req_body.trim();
-    let dialect = GenericDialect {};
-    let ast = Parser::parse_sql(&dialect, input);
+    let ast = parse_commands(input);
 
     match ast {
         Ok(statements) => {
-            if statements.is_empty() { return HttpResponse::BadRequest().body("Empty query"); }
-            
-            // LOCK THE DB so only one request happens at a time
-            let mut db_guard = db.lock().unwrap();
-            
-            match process_command(&mut *db_guard, &statements[0]) {
-                Ok(msg) => {
-                    // Auto-save logic
+            let Some(command) = statements.into_iter().next() else {
+                return HttpResponse::BadRequest().body("Empty query");
+            };
+            // The statement runs on the blocking pool and its output is rendered before the lock
+            // is released, so a client that reads slowly (or not at all) holds up no one else
+            let output = web::block(move || {
+                let mut db_guard = db.lock().unwrap();
+                let mut lines = Vec::new();
+                let result = execute_streamed(&mut db_guard, &command, &mut |line| {
+                    lines.push(line);
+                    true
+                });
+                // Auto-save logic
+                if result.is_ok() {
                     let _ = db_guard.save_to_disk();
-                    HttpResponse::Ok().body(msg)
-                },
-                Err(e) => HttpResponse::BadRequest().body(format!("Error: {}", e)),
+                }
+                (lines, result)
+            })
+            .await;
+
+            match output {
+                Ok((lines, Ok(()))) => HttpResponse::Ok().body(Lines::new(lines)),
+                Ok((lines, Err(e))) if lines.is_empty() => HttpResponse::BadRequest().body(format!("Error: {}", e)),
+                // The rows before the failing one still go out, followed by the error
+                Ok((mut lines, Err(e))) => {
+                    lines.push(format!("Error: {}", e));
+                    HttpResponse::Ok().body(Lines::new(lines))
+                }
+                Err(_) => HttpResponse::InternalServerError().body("Error: the query stopped unexpectedly"),
             }
         }
         Err(e) => HttpResponse::BadRequest().body(format!("SQL Syntax Error: {:?}", e)),
     }
 }
 
+// A file that does not load is left alone: starting with an empty database instead would
+// overwrite it on the next save.
+fn load_or_exit() -> Database {
+    Database::load_from_disk().unwrap_or_else(|e| {
+        eprintln!("Cannot load '{}': {}", DB_FILE, e);
+        std::process::exit(1);
+    })
+}
+
 // --- MAIN SERVER LOOP ---
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -429,7 +1520,7 @@ async fn main() -> std::io::Result<()> {
     // MODE 1: Web Server (if argument "server" is passed)
     if args.len() > 1 && args[1] == "server" {
         println!("Starting RustDB HTTP Server on port 8080...");
-        let db = Database::load_from_disk().unwrap_or_else(|_| Database::new());
+        let db = load_or_exit();
         let db_data = web::Data::new(Mutex::new(db));
 
         return HttpServer::new(move || {
@@ -444,7 +1535,7 @@ async fn main() -> std::io::Result<()> {
 
     // MODE 2: Interactive REPL (Default)
     println!("RustDB REPL (Type 'exit' to quit, run with 'server' arg for HTTP mode)");
-    let mut db = Database::load_from_disk().unwrap_or_else(|_| Database::new());
+    let mut db = load_or_exit();
     let mut rl = rustyline::DefaultEditor::new().expect("Failed to init readline");
 
     loop {
@@ -458,15 +1549,20 @@ async fn main() -> std::io::Result<()> {
                 }
                 let _ = rl.add_history_entry(input);
 
-                let dialect = GenericDialect {};
-                let ast = Parser::parse_sql(&dialect, input);
+                let ast = parse_commands(input);
                 match ast {
                     Ok(statements) => {
                         if !statements.is_empty() {
                             // Note: In REPL, we don't need the Mutex locking since it's single threaded here
-                            match process_command(&mut db, &statements[0]) {
-                                Ok(msg) => {
-                                    println!("OK: {}", msg);
+                            // Rows are printed as they are produced
+                            let mut first = true;
+                            let result = execute_streamed(&mut db, &statements[0], &mut |line| {
+                                if first { println!("OK: {}", line) } else { println!("{}", line) }
+                                first = false;
+                                true
+                            });
+                            match result {
+                                Ok(()) => {
                                     let _ = db.save_to_disk(); // Auto-save
                                 },
                                 Err(e) => println!("Error: {}", e),
@@ -480,4 +1576,227 @@ async fn main() -> std::io::Result<()> {
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs each statement in turn, returning the last one's output or the first error
+    pub fn run(db: &mut Database, sql: &str) -> Result<String, String> {
+        let mut output = String::new();
+        for command in parse_commands(sql).map_err(|e| e.to_string())? {
+            output = execute(db, &command)?;
+        }
+        Ok(output)
+    }
+
+    // A new in-memory database with `sql` already run
+    pub fn setup(sql: &str) -> Database {
+        let mut db = Database::new();
+        run(&mut db, sql).unwrap();
+        db
+    }
+
+    // The values of each row a SELECT returns, without rowids
+    pub fn rows(db: &mut Database, sql: &str) -> Vec<Vec<Value>> {
+        query(db, sql).unwrap()
+    }
+
+    pub fn query(db: &mut Database, sql: &str) -> Result<Vec<Vec<Value>>, String> {
+        let commands = parse_commands(sql).map_err(|e| e.to_string())?;
+        let [Command::Sql(stmt)] = &commands[..] else { panic!("expected one SQL statement: {}", sql) };
+        let Statement::Query(query) = &**stmt else { panic!("expected a SELECT: {}", sql) };
+        let query = sequence::bind_query(db, query)?;
+        Ok(run_query(db, &query)?.rows.into_iter().map(|(_, values)| values).collect())
+    }
+
+    fn int(i: i64) -> Value {
+        Value::Integer(i)
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    #[test]
+    fn primary_keys_reject_duplicates_and_nulls() {
+        let mut db = setup("
+            CREATE TABLE k (a TEXT, b INT, v TEXT, PRIMARY KEY (a, b));
+            INSERT INTO k VALUES ('x', 1, 'one'), ('x', 2, 'two');
+            CREATE TABLE n (id INT PRIMARY KEY, v TEXT);
+        ");
+        assert_eq!(
+            run(&mut db, "INSERT INTO k VALUES ('x', 1, 'dup')"),
+            Err("Primary key violation: Duplicate key (a, b) = (Text(\"x\"), Integer(1)) in table 'k'".to_string())
+        );
+        assert_eq!(run(&mut db, "INSERT INTO k VALUES (NULL, 3, 'n')"), Err("Primary key violation: Column 'a' cannot be NULL".to_string()));
+        assert!(run(&mut db, "UPDATE k SET b = 2 WHERE v = 'one'").is_err());
+        // The key is a value like any other, not the row id
+        run(&mut db, "INSERT INTO n VALUES (-5, 'neg')").unwrap();
+        assert!(run(&mut db, "INSERT INTO n VALUES (-5, 'again')").is_err());
+        assert_eq!(run(&mut db, "SELECT id, v FROM n"), Ok("ID | id | v\n1  | -5 | neg".to_string()));
+    }
+
+    #[test]
+    fn not_null_rejects_missing_and_null_values() {
+        let mut db = setup("CREATE TABLE t (id INT PRIMARY KEY, name TEXT NOT NULL)");
+        assert_eq!(run(&mut db, "INSERT INTO t (id) VALUES (1)"), Err("NOT NULL constraint failed: t.name".to_string()));
+        run(&mut db, "INSERT INTO t VALUES (1, 'a')").unwrap();
+        assert_eq!(run(&mut db, "UPDATE t SET name = NULL WHERE id = 1"), Err("NOT NULL constraint failed: t.name".to_string()));
+        assert_eq!(run(&mut db, "SELECT name FROM t"), Ok("ID | name\n1  | a".to_string()));
+    }
+
+    #[test]
+    fn check_and_default_apply_to_omitted_columns() {
+        let mut db = setup("CREATE TABLE t (id INT PRIMARY KEY, qty INT DEFAULT 5 CHECK (qty >= 0))");
+        run(&mut db, "INSERT INTO t (id) VALUES (1)").unwrap();
+        assert_eq!(run(&mut db, "INSERT INTO t VALUES (2, -1)"), Err("CHECK constraint 't_qty_check' failed: qty >= 0".to_string()));
+        // A NULL passes a CHECK, as in SQL
+        run(&mut db, "INSERT INTO t VALUES (3, NULL)").unwrap();
+        assert_eq!(run(&mut db, "SELECT id, qty FROM t"), Ok("ID | id | qty\n1  | 1 | 5\n2  | 3 | NULL".to_string()));
+    }
+
+    #[test]
+    fn composite_unique_lets_nulls_repeat() {
+        let mut db = setup("
+            CREATE TABLE u (sku TEXT, cat INT, CONSTRAINT one_sku UNIQUE (sku, cat));
+            INSERT INTO u VALUES ('s', 1), ('s', 2), ('t', 1);
+        ");
+        assert_eq!(
+            run(&mut db, "INSERT INTO u VALUES ('s', 1)"),
+            Err("Unique constraint 'one_sku' violated: (sku, cat) = (Text(\"s\"), Integer(1)) already exists".to_string())
+        );
+        assert!(run(&mut db, "UPDATE u SET cat = 1 WHERE cat = 2").is_err());
+        // NULL equals nothing, not even another NULL
+        run(&mut db, "INSERT INTO u VALUES ('s', NULL), ('s', NULL)").unwrap();
+        assert_eq!(run(&mut db, "SELECT sku, cat FROM u"), Ok("ID | sku | cat\n1  | s | 1\n2  | s | 2\n3  | t | 1\n4  | s | NULL\n5  | s | NULL".to_string()));
+    }
+
+    #[test]
+    fn generated_columns_follow_their_inputs() {
+        let mut db = setup("
+            CREATE TABLE inv (id INTEGER PRIMARY KEY, price NUMERIC(10, 2), stock INTEGER,
+                total NUMERIC(12, 2) GENERATED ALWAYS AS (price * stock) STORED, half INTEGER AS (stock / 2));
+            INSERT INTO inv VALUES (1, 2.50, 4);
+        ");
+        assert_eq!(run(&mut db, "INSERT INTO inv (id, price, stock, total) VALUES (2, 1, 1, 5)"), Err("Cannot insert into generated column 'total'".to_string()));
+        assert!(run(&mut db, "UPDATE inv SET half = 3 WHERE id = 1").is_err());
+        assert_eq!(rows(&mut db, "SELECT total, half FROM inv"), vec![vec![Value::Decimal(decimal::Decimal::new(1000, 2)), int(2)]]);
+        run(&mut db, "UPDATE inv SET stock = 10 WHERE id = 1").unwrap();
+        assert_eq!(rows(&mut db, "SELECT total, half FROM inv WHERE half = 5"), vec![vec![Value::Decimal(decimal::Decimal::new(2500, 2)), int(5)]]);
+    }
+
+    // The lines `execute_streamed` emits for `sql` until `wanted` of them are taken, and its result
+    fn streamed(db: &mut Database, sql: &str, wanted: usize) -> (Vec<String>, Result<(), String>) {
+        let commands = parse_commands(sql).unwrap();
+        let mut lines = Vec::new();
+        let result = execute_streamed(db, &commands[0], &mut |line| {
+            lines.push(line);
+            lines.len() < wanted
+        });
+        (lines, result)
+    }
+
+    #[test]
+    fn rows_stream_until_the_reader_stops_or_a_row_fails() {
+        let mut db = setup("CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER)");
+        let values: Vec<String> = (1..=50).map(|i| format!("({}, {})", i, i)).collect();
+        run(&mut db, &format!("INSERT INTO t VALUES {}", values.join(", "))).unwrap();
+        let (lines, result) = streamed(&mut db, "SELECT n FROM t", 3);
+        assert_eq!((lines.len(), result), (3, Ok(())));
+        assert!(lines[2].ends_with("| 2"), "{:?}", lines);
+        // Rows before the failing one are already out
+        let (lines, result) = streamed(&mut db, "SELECT 10 / (n - 3) FROM t", usize::MAX);
+        assert_eq!((lines.len(), result), (3, Err("Division by zero".to_string())));
+        // A query that fails on its first row emits nothing
+        let (lines, result) = streamed(&mut db, "SELECT 10 / (n - 1) FROM t", usize::MAX);
+        assert!(lines.is_empty() && result.is_err());
+    }
+
+    #[test]
+    fn limit_stops_reading_once_it_has_its_rows() {
+        let mut db = setup("CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER)");
+        let values: Vec<String> = (1..=50).map(|i| format!("({}, {})", i, i)).collect();
+        run(&mut db, &format!("INSERT INTO t VALUES {}", values.join(", "))).unwrap();
+        let plan = run(&mut db, "EXPLAIN ANALYZE SELECT * FROM t LIMIT 10 OFFSET 5").unwrap();
+        let scan = plan.lines().find(|l| l.contains("Seq Scan on t")).unwrap();
+        assert!(scan.contains("(actual rows=15 "), "{}", plan);
+        assert_eq!(rows(&mut db, "SELECT n FROM t LIMIT 2 OFFSET 48"), vec![vec![int(49)], vec![int(50)]]);
+    }
+
+    #[test]
+    fn failed_cascade_undoes_the_whole_statement() {
+        let mut db = setup("
+            CREATE TABLE p (id INTEGER PRIMARY KEY);
+            CREATE TABLE c (id INTEGER PRIMARY KEY, p_id INTEGER REFERENCES p(id) ON DELETE CASCADE);
+            CREATE TABLE g (id INTEGER PRIMARY KEY, c_id INTEGER REFERENCES c(id) ON DELETE RESTRICT);
+            INSERT INTO p VALUES (1);
+            INSERT INTO c VALUES (10, 1), (11, 1);
+            INSERT INTO g VALUES (100, 11);
+        ");
+        assert!(run(&mut db, "DELETE FROM p WHERE id = 1").is_err());
+        assert_eq!(rows(&mut db, "SELECT id FROM p"), vec![vec![int(1)]]);
+        assert_eq!(rows(&mut db, "SELECT id FROM c"), vec![vec![int(10)], vec![int(11)]]);
+    }
+
+    #[test]
+    fn failed_row_undoes_what_triggers_wrote() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER CHECK (n > 0));
+            CREATE TABLE log (id INTEGER PRIMARY KEY, n INTEGER);
+            CREATE TABLE archive (id INTEGER PRIMARY KEY);
+            CREATE TRIGGER audit AFTER INSERT ON t BEGIN INSERT INTO log (n) VALUES (NEW.n); END;
+            INSERT INTO archive VALUES (1);
+        ");
+        assert!(run(&mut db, "INSERT INTO t VALUES (1, 1), (2, 0)").is_err());
+        assert!(rows(&mut db, "SELECT * FROM t").is_empty());
+        assert!(rows(&mut db, "SELECT * FROM log").is_empty());
+        // A statement in a trigger body that fails part-way fails the statement that fired it
+        run(&mut db, "
+            CREATE TABLE parent (id INTEGER PRIMARY KEY);
+            CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES parent(id) ON DELETE CASCADE);
+            CREATE TABLE pin (id INTEGER PRIMARY KEY, child_id INTEGER REFERENCES child(id));
+            CREATE TRIGGER purge AFTER DELETE ON archive BEGIN DELETE FROM parent WHERE id = OLD.id; END;
+            INSERT INTO parent VALUES (1);
+            INSERT INTO child VALUES (5, 1);
+            INSERT INTO pin VALUES (9, 5);
+        ").unwrap();
+        assert!(run(&mut db, "DELETE FROM archive WHERE id = 1").is_err());
+        assert_eq!(rows(&mut db, "SELECT id FROM archive"), vec![vec![int(1)]]);
+        assert_eq!(rows(&mut db, "SELECT id FROM parent"), vec![vec![int(1)]]);
+        assert_eq!(rows(&mut db, "SELECT id FROM child"), vec![vec![int(5)]]);
+    }
+
+    #[test]
+    fn the_earliest_dates_survive_a_save_and_load() {
+        let mut db = setup("
+            CREATE TABLE d (id INTEGER PRIMARY KEY, day DATE, at TIMESTAMP);
+            INSERT INTO d VALUES (1, DATE '4713-01-01 BC', TIMESTAMP '4713-01-01 00:00:00 BC');
+            INSERT INTO d VALUES (2, DATE '0001-01-01' - 1000, TIMESTAMP '0001-01-01 00:00:00' - INTERVAL '1 second');
+        ");
+        let path = std::env::temp_dir().join(format!("rust_sqlite_dates_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        db.write_to(path).unwrap();
+        let loaded = Database::load_from(path);
+        std::fs::remove_file(path).unwrap();
+        let mut loaded = loaded.unwrap();
+        let sql = "SELECT id, CAST(day AS TEXT), CAST(at AS TEXT) FROM d";
+        assert_eq!(rows(&mut loaded, sql), rows(&mut db, sql));
+        assert_eq!(rows(&mut loaded, sql)[1], vec![int(2), text("0003-04-07 BC"), text("0001-12-31 23:59:59 BC")]);
+    }
+
+    #[actix_web::test]
+    async fn query_output_is_ready_before_the_client_reads_it() {
+        use actix_web::test::{call_service, init_service, read_body, TestRequest};
+        // Inside a transaction nothing is saved, so the test leaves mydb.json alone
+        let db = web::Data::new(Mutex::new(setup("CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER); INSERT INTO t VALUES (1, 1), (2, 0); BEGIN")));
+        let app = init_service(App::new().app_data(db.clone()).service(query_endpoint)).await;
+        let response = call_service(&app, TestRequest::post().uri("/query").set_payload("SELECT 10 / n FROM t").to_request()).await;
+        // The body is still unread, yet the database is free for the next request
+        assert!(db.try_lock().is_ok());
+        assert_eq!(read_body(response).await, "ID | 10 / n\n1  | 10\nError: Division by zero");
+        let response = call_service(&app, TestRequest::post().uri("/query").set_payload("SELECT x FROM t").to_request()).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,302 @@
+// --- SQL PARSING ---
+// sqlparser handles the standard statements. A few forms it does not know yet (CREATE TYPE
+// ... AS ENUM, DROP TYPE, DROP/REFRESH MATERIALIZED VIEW, CREATE/DROP TRIGGER, DETACH, ANALYZE) are recognised
+// here first and become extra commands. CREATE TABLE is parsed here too, for generated and
+// identity columns, and CREATE SEQUENCE, whose options sqlparser only takes in a fixed order.
+use sqlparser::ast::{
+    ColumnDef, ColumnOption, ColumnOptionDef, DataType, Expr, GeneratedAs, MinMaxValue, ObjectName, SequenceOptions,
+    Statement, TableConstraint, UnaryOperator,
+};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::Token;
+
+use crate::object_name;
+use crate::trigger::{Event, Timing};
+
+pub enum Command {
+    Sql(Box<Statement>),
+    CreateTable(Box<CreateTable>),
+    CreateEnum { name: String, labels: Vec<String> },
+    DropType { name: String, if_exists: bool },
+    DropMaterializedView { names: Vec<ObjectName>, if_exists: bool, cascade: bool },
+    RefreshMaterializedView { name: String },
+    CreateTrigger(Box<CreateTrigger>),
+    DropTrigger { name: String, if_exists: bool },
+    CreateSequence { name: String, if_not_exists: bool, data_type: Option<DataType>, options: Vec<SequenceOptions> },
+    Detach { alias: String },
+    // ANALYZE [TABLE] [t, ...]: no names means every table
+    Analyze { tables: Vec<String> },
+}
+
+pub struct CreateTable {
+    pub name: String,
+    pub if_not_exists: bool,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    pub generated: Vec<Generated>,
+}
+
+// `col TYPE AS (expr) [STORED | VIRTUAL]` or `col TYPE GENERATED ALWAYS AS (expr) [STORED | VIRTUAL]`
+pub struct Generated {
+    pub column: String,
+    pub expr: Expr,
+    pub stored: bool,
+}
+
+pub struct CreateTrigger {
+    pub name: String,
+    pub if_not_exists: bool,
+    pub timing: Timing,
+    pub event: Event,
+    // UPDATE OF a, b
+    pub columns: Vec<String>,
+    pub table: String,
+    pub when: Option<Expr>,
+    pub body: Vec<Statement>,
+}
+
+pub fn parse_commands(sql: &str) -> Result<Vec<Command>, ParserError> {
+    let dialect = GenericDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    let mut commands = Vec::new();
+    let mut expecting_delimiter = false;
+    loop {
+        while parser.consume_token(&Token::SemiColon) {
+            expecting_delimiter = false;
+        }
+        if parser.peek_token().token == Token::EOF {
+            break;
+        }
+        if expecting_delimiter {
+            return parser.expected("end of statement", parser.peek_token());
+        }
+        let command = if parser.parse_keywords(&[Keyword::CREATE, Keyword::TABLE]) {
+            Command::CreateTable(Box::new(parse_create_table(&mut parser)?))
+        } else if parser.parse_keywords(&[Keyword::CREATE, Keyword::TYPE]) {
+            parse_create_type(&mut parser)?
+        } else if parser.parse_keywords(&[Keyword::DROP, Keyword::TYPE]) {
+            let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+            Command::DropType { name: parser.parse_object_name()?.to_string(), if_exists }
+        } else if parser.parse_keywords(&[Keyword::CREATE, Keyword::SEQUENCE]) {
+            let if_not_exists = parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+            let name = object_name(&parser.parse_object_name()?);
+            let data_type = if parser.parse_keyword(Keyword::AS) { Some(parser.parse_data_type()?) } else { None };
+            Command::CreateSequence { name, if_not_exists, data_type, options: parse_sequence_options(&mut parser)? }
+        } else if parser.parse_keywords(&[Keyword::CREATE, Keyword::TRIGGER]) {
+            Command::CreateTrigger(Box::new(parse_create_trigger(&mut parser)?))
+        } else if parser.parse_keywords(&[Keyword::DROP, Keyword::TRIGGER]) {
+            let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+            Command::DropTrigger { name: parser.parse_object_name()?.to_string(), if_exists }
+        } else if parser.parse_keywords(&[Keyword::DROP, Keyword::MATERIALIZED, Keyword::VIEW]) {
+            let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+            let names = parser.parse_comma_separated(|p| p.parse_object_name())?;
+            let cascade = parser.parse_keyword(Keyword::CASCADE);
+            let _restrict = !cascade && parser.parse_keyword(Keyword::RESTRICT);
+            Command::DropMaterializedView { names, if_exists, cascade }
+        } else if parse_word(&mut parser, "REFRESH") {
+            parser.expect_keywords(&[Keyword::MATERIALIZED, Keyword::VIEW])?;
+            Command::RefreshMaterializedView { name: object_name(&parser.parse_object_name()?) }
+        } else if parse_word(&mut parser, "DETACH") {
+            let _database = parser.parse_keyword(Keyword::DATABASE);
+            Command::Detach { alias: parser.parse_identifier()?.value }
+        } else if parser.parse_keyword(Keyword::ANALYZE) {
+            let _table = parser.parse_keyword(Keyword::TABLE);
+            let tables = match parser.peek_token().token {
+                Token::EOF | Token::SemiColon => Vec::new(),
+                _ => parser.parse_comma_separated(|p| p.parse_object_name())?.iter().map(object_name).collect(),
+            };
+            Command::Analyze { tables }
+        } else {
+            Command::Sql(Box::new(parser.parse_statement()?))
+        };
+        commands.push(command);
+        expecting_delimiter = true;
+    }
+    Ok(commands)
+}
+
+// CREATE TYPE name AS ENUM ('a', 'b', ...)
+fn parse_create_type(parser: &mut Parser) -> Result<Command, ParserError> {
+    let name = parser.parse_object_name()?.to_string();
+    parser.expect_keyword(Keyword::AS)?;
+    if !parser.parse_keyword(Keyword::ENUM) {
+        return parser.expected("ENUM (only enum types are supported)", parser.peek_token());
+    }
+    parser.expect_token(&Token::LParen)?;
+    let labels = parser.parse_comma_separated(|p| p.parse_literal_string())?;
+    parser.expect_token(&Token::RParen)?;
+    Ok(Command::CreateEnum { name, labels })
+}
+
+// CREATE TABLE [IF NOT EXISTS] name (column | table constraint, ...)
+fn parse_create_table(parser: &mut Parser) -> Result<CreateTable, ParserError> {
+    let if_not_exists = parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+    let name = object_name(&parser.parse_object_name()?);
+    let mut def = CreateTable { name, if_not_exists, columns: Vec::new(), constraints: Vec::new(), generated: Vec::new() };
+    parser.expect_token(&Token::LParen)?;
+    loop {
+        if let Some(constraint) = parser.parse_optional_table_constraint()? {
+            def.constraints.push(constraint);
+        } else {
+            let column = parse_column(parser, &mut def.generated)?;
+            def.columns.push(column);
+        }
+        if !parser.consume_token(&Token::Comma) {
+            parser.expect_token(&Token::RParen)?;
+            break;
+        }
+    }
+    Ok(def)
+}
+
+// As sqlparser's parse_column_def, plus generated columns, whose expressions go to `generated`.
+fn parse_column(parser: &mut Parser, generated: &mut Vec<Generated>) -> Result<ColumnDef, ParserError> {
+    let name = parser.parse_identifier()?;
+    let data_type = parser.parse_data_type()?;
+    let collation = if parser.parse_keyword(Keyword::COLLATE) { Some(parser.parse_object_name()?) } else { None };
+    let mut options = Vec::new();
+    loop {
+        let as_expr = [0, 1].map(|n| parser.peek_nth_token(n).token);
+        let generated_as = [0, 1, 2, 3].map(|n| parser.peek_nth_token(n).token);
+        let starts_generated = matches!(&as_expr, [Token::Word(w), Token::LParen] if w.keyword == Keyword::AS)
+            || matches!(&generated_as, [Token::Word(g), Token::Word(a), Token::Word(s), Token::LParen]
+                if g.keyword == Keyword::GENERATED && a.keyword == Keyword::ALWAYS && s.keyword == Keyword::AS);
+        if starts_generated {
+            while !parser.consume_token(&Token::LParen) {
+                parser.next_token();
+            }
+            let expr = parser.parse_expr()?;
+            parser.expect_token(&Token::RParen)?;
+            // VIRTUAL is the default, as in SQLite and MySQL
+            let stored = parser.parse_keyword(Keyword::STORED);
+            let _virtual = !stored && parser.parse_keyword(Keyword::VIRTUAL);
+            generated.push(Generated { column: name.value.clone(), expr, stored });
+        } else if parser.parse_keywords(&[Keyword::GENERATED, Keyword::ALWAYS, Keyword::AS, Keyword::IDENTITY]) {
+            options.push(parse_identity(parser, GeneratedAs::Always)?);
+        } else if parser.parse_keywords(&[Keyword::GENERATED, Keyword::BY, Keyword::DEFAULT, Keyword::AS, Keyword::IDENTITY]) {
+            options.push(parse_identity(parser, GeneratedAs::ByDefault)?);
+        } else if parser.parse_keyword(Keyword::CONSTRAINT) {
+            let constraint = Some(parser.parse_identifier()?);
+            match parser.parse_optional_column_option()? {
+                Some(option) => options.push(ColumnOptionDef { name: constraint, option }),
+                None => return parser.expected("constraint details after CONSTRAINT <name>", parser.peek_token()),
+            }
+        } else if let Some(option) = parser.parse_optional_column_option()? {
+            options.push(ColumnOptionDef { name: None, option });
+        } else {
+            break;
+        }
+    }
+    Ok(ColumnDef { name, data_type, collation, options })
+}
+
+// The rest of `GENERATED ... AS IDENTITY [(sequence options)]`.
+fn parse_identity(parser: &mut Parser, generated_as: GeneratedAs) -> Result<ColumnOptionDef, ParserError> {
+    let mut sequence_options = Vec::new();
+    if parser.consume_token(&Token::LParen) {
+        sequence_options = parse_sequence_options(parser)?;
+        parser.expect_token(&Token::RParen)?;
+    }
+    let option = ColumnOption::Generated { generated_as, sequence_options: Some(sequence_options), generation_expr: None };
+    Ok(ColumnOptionDef { name: None, option })
+}
+
+// INCREMENT [BY] n, MINVALUE n | NO MINVALUE, MAXVALUE n | NO MAXVALUE, START [WITH] n, CACHE n
+// and [NO] CYCLE, in any order. Numbers may be negative.
+fn parse_sequence_options(parser: &mut Parser) -> Result<Vec<SequenceOptions>, ParserError> {
+    let mut options = Vec::new();
+    loop {
+        let option = if parser.parse_keyword(Keyword::INCREMENT) {
+            let by = parser.parse_keyword(Keyword::BY);
+            SequenceOptions::IncrementBy(parse_signed_number(parser)?, by)
+        } else if parser.parse_keyword(Keyword::START) {
+            let with = parser.parse_keyword(Keyword::WITH);
+            SequenceOptions::StartWith(parse_signed_number(parser)?, with)
+        } else if parser.parse_keyword(Keyword::MINVALUE) {
+            SequenceOptions::MinValue(MinMaxValue::Some(parse_signed_number(parser)?))
+        } else if parser.parse_keyword(Keyword::MAXVALUE) {
+            SequenceOptions::MaxValue(MinMaxValue::Some(parse_signed_number(parser)?))
+        } else if parser.parse_keyword(Keyword::CACHE) {
+            SequenceOptions::Cache(parse_signed_number(parser)?)
+        } else if parser.parse_keyword(Keyword::CYCLE) {
+            SequenceOptions::Cycle(false)
+        } else if parser.parse_keywords(&[Keyword::NO, Keyword::MINVALUE]) {
+            SequenceOptions::MinValue(MinMaxValue::None)
+        } else if parser.parse_keywords(&[Keyword::NO, Keyword::MAXVALUE]) {
+            SequenceOptions::MaxValue(MinMaxValue::None)
+        } else if parser.parse_keywords(&[Keyword::NO, Keyword::CYCLE]) {
+            // Cycle(true) is how sqlparser spells NO CYCLE
+            SequenceOptions::Cycle(true)
+        } else {
+            break;
+        };
+        options.push(option);
+    }
+    Ok(options)
+}
+
+fn parse_signed_number(parser: &mut Parser) -> Result<Expr, ParserError> {
+    let negative = parser.consume_token(&Token::Minus);
+    let number = Expr::Value(parser.parse_number_value()?);
+    Ok(if negative { Expr::UnaryOp { op: UnaryOperator::Minus, expr: Box::new(number) } } else { number })
+}
+
+// CREATE TRIGGER [IF NOT EXISTS] name BEFORE|AFTER INSERT|UPDATE [OF a, b]|DELETE ON table
+// [FOR EACH ROW] [WHEN cond] BEGIN stmt; ... END
+fn parse_create_trigger(parser: &mut Parser) -> Result<CreateTrigger, ParserError> {
+    let if_not_exists = parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+    let name = parser.parse_object_name()?.to_string();
+    let timing = if parse_word(parser, "BEFORE") {
+        Timing::Before
+    } else if parse_word(parser, "AFTER") {
+        Timing::After
+    } else {
+        return parser.expected("BEFORE or AFTER", parser.peek_token());
+    };
+    let mut columns = Vec::new();
+    let event = if parser.parse_keyword(Keyword::INSERT) {
+        Event::Insert
+    } else if parser.parse_keyword(Keyword::UPDATE) {
+        if parser.parse_keyword(Keyword::OF) {
+            columns = parser.parse_comma_separated(|p| p.parse_identifier())?.into_iter().map(|i| i.value).collect();
+        }
+        Event::Update
+    } else if parser.parse_keyword(Keyword::DELETE) {
+        Event::Delete
+    } else {
+        return parser.expected("INSERT, UPDATE or DELETE", parser.peek_token());
+    };
+    parser.expect_keyword(Keyword::ON)?;
+    let table = object_name(&parser.parse_object_name()?);
+    if parser.parse_keywords(&[Keyword::FOR, Keyword::EACH]) && !parse_word(parser, "ROW") {
+        return parser.expected("ROW (only row-level triggers are supported)", parser.peek_token());
+    }
+    let when = if parser.parse_keyword(Keyword::WHEN) { Some(parser.parse_expr()?) } else { None };
+    parser.expect_keyword(Keyword::BEGIN)?;
+    let mut body = Vec::new();
+    loop {
+        while parser.consume_token(&Token::SemiColon) {}
+        if parser.parse_keyword(Keyword::END) {
+            break;
+        }
+        body.push(parser.parse_statement()?);
+        if !parser.consume_token(&Token::SemiColon) {
+            parser.expect_keyword(Keyword::END)?;
+            break;
+        }
+    }
+    Ok(CreateTrigger { name, if_not_exists, timing, event, columns, table, when, body })
+}
+
+// Consumes `word` if it is next; for words sqlparser has no keyword for.
+fn parse_word(parser: &mut Parser, word: &str) -> bool {
+    match parser.peek_token().token {
+        Token::Word(w) if w.value.eq_ignore_ascii_case(word) => {
+            parser.next_token();
+            true
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,996 @@
+// --- PHYSICAL PLANS ---
+// physical::plan chooses how to run each operator of a logical plan. It estimates how many rows
+// each operator produces (from ANALYZE's statistics, or fixed guesses without them) and what
+// producing them costs, and keeps the cheapest choice: a table is read through the index that
+// narrows it most when that beats reading every row, each join probes an index on the joined
+// column or builds a hash table of the joined relation, and a chain of inner joins is run in
+// the order that keeps the intermediate results smallest. execute runs the tree bottom-up.
+// EXPLAIN prints the chosen tree with its estimates; EXPLAIN ANALYZE also runs it and reports
+// how many rows each operator produced and how long it took (its inputs included).
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use sqlparser::ast::{Expr, Ident, OrderByExpr, Query, TableFactor};
+
+use crate::expr::{eval_expr, truth};
+use crate::index::{self, Key};
+use crate::plan::{self, Item, Plan};
+use crate::{aggregate, catalog, fulltext, order_rows, relation, rewrite, stats, table_function_call, Database, Layout, ResultSet, ResultStream, Row, Value};
+
+// Costs are in units of reading one row in a scan.
+// Fetching a row an index points at
+const INDEX_ROW: f64 = 2.0;
+// Evaluating a condition or hashing a key for one row
+const CPU_ROW: f64 = 0.1;
+// The assumed size of a view, which is only known by running it
+const VIEW_ROWS: f64 = 1000.0;
+// The assumed rows of one table function call
+const FUNCTION_ROWS: f64 = 10.0;
+// Longer chains of joins are run in the order written
+const MAX_REORDER: usize = 8;
+
+pub struct Node {
+    // Position in the tree, counting from the root: EXPLAIN ANALYZE keys its figures by it
+    id: usize,
+    op: Op,
+    inputs: Vec<Node>,
+    // Estimated rows produced, and the cost of producing them (inputs included)
+    rows: f64,
+    cost: f64,
+}
+
+enum Op {
+    Empty,
+    // Every row of a table, view or catalog relation; of a table, only `columns` if given
+    SeqScan { table: String, kind: &'static str, columns: Option<Vec<String>> },
+    // The rows of a table that the B-tree index `index` and any full-text indexes say may
+    // satisfy `predicate`; `accesses` describes how they are read
+    IndexScan { table: String, predicate: Expr, index: Option<String>, accesses: Vec<String>, columns: Option<Vec<String>> },
+    Function { factor: TableFactor },
+    Score { table: String, calls: Vec<Expr> },
+    Filter { predicate: Expr },
+    Join(Join),
+    // Keeps the input rows whose `key` is (not) among the values of the second input
+    SemiJoin { key: Expr, negated: bool },
+    Sort { order_by: Vec<OrderByExpr> },
+    Aggregate { items: Vec<Item> },
+    Project { items: Vec<Item> },
+    Limit { limit: Option<u64>, offset: u64 },
+}
+
+struct Join {
+    method: JoinMethod,
+    left_table: Option<String>,
+    right_table: String,
+    left_key: Expr,
+    right_key: String,
+    // Index joins only: the condition the rows looked up must meet
+    filter: Option<Expr>,
+    // The relations in the order written, when the joins were reordered; the output columns are
+    // put back in that order
+    order: Option<Vec<String>>,
+}
+
+enum JoinMethod {
+    // For each left row, look its key up in `index` of `table`, the table the right side reads
+    Index { index: String, table: String },
+    // Read the right side once into a map from key to rows
+    Hash,
+}
+
+pub fn plan(db: &Database, logical: Plan) -> Result<Node, String> {
+    let mut root = choose(db, logical, None)?;
+    number(&mut root, &mut 0);
+    Ok(root)
+}
+
+fn number(node: &mut Node, next: &mut usize) {
+    node.id = *next;
+    *next += 1;
+    for input in &mut node.inputs {
+        number(input, next);
+    }
+}
+
+// Like PostgreSQL, a condition is never estimated to keep less than one row
+fn clamp(rows: f64) -> f64 {
+    rows.max(1.0)
+}
+
+// `filter` is the condition of a Filter right above, which a table scan may answer with indexes.
+fn choose(db: &Database, logical: Plan, filter: Option<&Expr>) -> Result<Node, String> {
+    Ok(match logical {
+        Plan::Empty => Node::new(Op::Empty, Vec::new(), 1.0, 0.0),
+        Plan::Scan { table, columns } => scan(db, table, columns, filter)?,
+        Plan::Function { input: None, factor } => Node::new(Op::Function { factor }, Vec::new(), FUNCTION_ROWS, FUNCTION_ROWS),
+        Plan::Function { input: Some(input), factor } => {
+            let input = choose(db, *input, None)?;
+            let rows = input.rows * FUNCTION_ROWS;
+            let cost = input.cost + rows;
+            Node::new(Op::Function { factor }, vec![input], rows, cost)
+        }
+        Plan::Score { input, table, calls } => {
+            let input = choose(db, *input, filter)?;
+            let (rows, cost) = (input.rows, input.cost + input.rows * CPU_ROW);
+            Node::new(Op::Score { table, calls }, vec![input], rows, cost)
+        }
+        Plan::Filter { input, predicate } => {
+            let input = choose(db, *input, Some(&predicate))?;
+            let mut scope = Vec::new();
+            relations(&input, &mut scope);
+            let rows = (unfiltered(db, &input) * stats::selectivity(db, &scope, &predicate)).min(input.rows);
+            let cost = input.cost + input.rows * CPU_ROW;
+            Node::new(Op::Filter { predicate }, vec![input], clamp(rows), cost)
+        }
+        join @ Plan::Join { .. } => join_chain(db, join)?,
+        Plan::SemiJoin { input, subquery, key, negated } => {
+            let input = choose(db, *input, None)?;
+            let subquery = choose(db, *subquery, None)?;
+            let mut scope = Vec::new();
+            relations(&input, &mut scope);
+            // The share of the key's values the subquery's rows can match
+            let distinct = stats::key_distinct(db, &scope, &key).unwrap_or(input.rows);
+            let share = (subquery.rows / distinct.max(1.0)).min(1.0);
+            let rows = input.rows * if negated { 1.0 - share } else { share };
+            let cost = input.cost + subquery.cost + (input.rows + subquery.rows) * CPU_ROW;
+            Node::new(Op::SemiJoin { key, negated }, vec![input, subquery], clamp(rows), cost)
+        }
+        Plan::Sort { input, order_by } => {
+            let input = choose(db, *input, None)?;
+            let (rows, cost) = (input.rows, input.cost + input.rows * (input.rows + 1.0).log2() * CPU_ROW);
+            Node::new(Op::Sort { order_by }, vec![input], rows, cost)
+        }
+        Plan::Aggregate { input, items } => {
+            let input = choose(db, *input, None)?;
+            let cost = input.cost + input.rows * CPU_ROW;
+            Node::new(Op::Aggregate { items }, vec![input], 1.0, cost)
+        }
+        Plan::Project { input, items } => {
+            let input = choose(db, *input, None)?;
+            let (rows, cost) = (input.rows, input.cost + input.rows * CPU_ROW);
+            Node::new(Op::Project { items }, vec![input], rows, cost)
+        }
+        Plan::Limit { input, limit, offset } => {
+            let input = choose(db, *input, None)?;
+            let rows = (input.rows - offset as f64).max(0.0).min(limit.map_or(f64::INFINITY, |n| n as f64));
+            let cost = input.cost;
+            Node::new(Op::Limit { limit, offset }, vec![input], rows, cost)
+        }
+    })
+}
+
+// The tables and other relations a subtree reads
+fn relations<'a>(node: &'a Node, out: &mut Vec<&'a str>) {
+    match &node.op {
+        Op::SeqScan { table, .. } | Op::IndexScan { table, .. } => out.push(table),
+        Op::Join(Join { method: JoinMethod::Index { table, .. }, .. }) => out.push(table),
+        _ => {}
+    }
+    for input in &node.inputs {
+        relations(input, out);
+    }
+}
+
+// The rows a subtree would produce before an index narrowed its scan
+fn unfiltered(db: &Database, node: &Node) -> f64 {
+    match &node.op {
+        Op::IndexScan { table, .. } => db.tables.get(table).map_or(node.rows, |t| t.data.len() as f64),
+        Op::Score { .. } => unfiltered(db, &node.inputs[0]),
+        _ => node.rows,
+    }
+}
+
+fn scan(db: &Database, table: String, columns: Option<Vec<String>>, filter: Option<&Expr>) -> Result<Node, String> {
+    if let Some(t) = db.tables.get(&table) {
+        let n = t.data.len() as f64;
+        let probe = (n + 1.0).log2();
+        if let Some(predicate) = filter {
+            // The B-tree index that reads the fewest rows, if that beats reading all of them
+            let best = index::accesses(t, predicate).into_iter()
+                .map(|access| (n * access.selectivity(t), access))
+                .filter(|(rows, _)| probe + rows * INDEX_ROW < n)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            // Full-text indexes are always used: without them every row would be scored
+            let full_text: Vec<String> = index::conjuncts(predicate).into_iter().filter_map(|term| fulltext::describe(t, term)).collect();
+            if best.is_some() || !full_text.is_empty() {
+                let (mut rows, mut cost) = (n, 0.0);
+                let mut accesses = Vec::new();
+                let mut index = None;
+                if let Some((estimate, access)) = best {
+                    rows = estimate;
+                    cost += probe;
+                    accesses.push(access.describe());
+                    index = Some(access.index.name.clone());
+                }
+                rows *= stats::DEFAULT_RANGE.powi(full_text.len() as i32);
+                cost += probe * full_text.len() as f64 + rows * INDEX_ROW;
+                accesses.extend(full_text);
+                let op = Op::IndexScan { table, predicate: predicate.clone(), index, accesses, columns };
+                return Ok(Node::new(op, Vec::new(), clamp(rows), cost));
+            }
+        }
+        return Ok(Node::new(Op::SeqScan { table, kind: "Seq Scan", columns }, Vec::new(), n, n));
+    }
+    let (kind, rows) = match db.views.get(&table) {
+        Some(view) => match &view.snapshot {
+            Some(snapshot) => ("Materialized View Scan", snapshot.rows.len() as f64),
+            None => ("View Scan", VIEW_ROWS),
+        },
+        None => match catalog::relation(db, &table) {
+            Some(snapshot) => ("Catalog Scan", snapshot.rows.len() as f64),
+            None => return Err(format!("Table '{}' not found", table)),
+        },
+    };
+    Ok(Node::new(Op::SeqScan { table, kind, columns: None }, Vec::new(), rows, rows))
+}
+
+// One input of a chain of inner joins
+struct Relation {
+    // The table it reads, if any
+    table: Option<String>,
+    // Taken when the chosen order is built
+    node: Option<Node>,
+    rows: f64,
+    cost: f64,
+    // For a table scan, perhaps filtered, which an index join can replace with lookups: the
+    // table's row count and the filter the rows looked up must meet
+    lookup: Option<(f64, Option<Expr>)>,
+}
+
+// One relation in a join order, with the edge and method that join it to the relations before
+// it, and the estimated rows and cost of the join so far
+#[derive(Clone)]
+struct Step {
+    relation: usize,
+    edge: usize,
+    index: Option<String>,
+    rows: f64,
+    cost: f64,
+}
+
+// a JOIN b ON ... JOIN c ON ...: joins in the cheapest order the conditions allow, each with
+// the cheaper of an index join and a hash join.
+fn join_chain(db: &Database, plan: Plan) -> Result<Node, String> {
+    let chain = plan::Chain::new(plan)?;
+    let edges = chain.edges(db);
+    let (names, tables) = (chain.names(), chain.tables());
+    // Other orders also need statistics-backed tables
+    let reorderable = chain.reorderable(&edges) && names.len() <= MAX_REORDER
+        && tables.iter().all(|t| t.as_ref().is_some_and(|t| db.tables.contains_key(t)));
+    let written = chain.written;
+    let mut relations = Vec::new();
+    for ((_, plan), table) in chain.relations.into_iter().zip(tables) {
+        let (scanned, filter) = match plan {
+            Plan::Filter { ref input, ref predicate } => (&**input, Some(predicate.clone())),
+            ref plan => (plan, None),
+        };
+        let lookup = match scanned {
+            Plan::Scan { table, columns: None } => db.tables.get(table).map(|t| (t.data.len() as f64, filter)),
+            _ => None,
+        };
+        let node = choose(db, plan, None)?;
+        relations.push(Relation { table, rows: node.rows, cost: node.cost, node: Some(node), lookup });
+    }
+
+    let mut search = Search { db, relations: &relations, edges: &edges, names: &names, best: None };
+    let first = Step { relation: 0, edge: usize::MAX, index: None, rows: relations[0].rows, cost: relations[0].cost };
+    if reorderable {
+        for (start, r) in relations.iter().enumerate() {
+            search.extend(&mut vec![Step { relation: start, rows: r.rows, cost: r.cost, ..first.clone() }]);
+        }
+    } else {
+        let mut order = vec![first];
+        for (i, edge) in edges.iter().enumerate() {
+            let step = search.step(&order, edge.right, i);
+            order.push(step);
+        }
+        search.best = Some(order);
+    }
+    let order = search.best.ok_or("No join order connects every table")?;
+
+    // Build the chosen tree
+    let reordered = order.iter().map(|s| s.relation).ne(0..relations.len());
+    let mut node = relations[order[0].relation].node.take().ok_or("Relation joined twice")?;
+    for (i, step) in order.iter().enumerate().skip(1) {
+        let (left_key, right_key) = edges[step.edge].keys_for(step.relation, &names);
+        let relation = &mut relations[step.relation];
+        let mut inputs = vec![node];
+        let (method, filter) = match &step.index {
+            Some(index) => {
+                let table = relation.table.clone().ok_or("An index join needs a table")?;
+                (JoinMethod::Index { index: index.clone(), table }, relation.lookup.as_ref().and_then(|(_, filter)| filter.clone()))
+            }
+            None => {
+                inputs.push(relation.node.take().ok_or("Relation joined twice")?);
+                (JoinMethod::Hash, None)
+            }
+        };
+        let left_table = (i == 1).then(|| names[order[0].relation].clone());
+        let right_table = names[step.relation].clone();
+        let op = Op::Join(Join { method, left_table, right_table, left_key, right_key, filter, order: None });
+        node = Node::new(op, inputs, step.rows, step.cost);
+    }
+    if let Op::Join(join) = &mut node.op {
+        join.order = if reordered { Some(written.unwrap_or(names)) } else { written };
+    }
+    Ok(node)
+}
+
+// Depth-first search over the join orders in which each relation is joined by a condition on
+// one already joined, keeping the cheapest; the order written wins ties.
+struct Search<'a> {
+    db: &'a Database,
+    relations: &'a [Relation],
+    edges: &'a [plan::Edge],
+    names: &'a [String],
+    best: Option<Vec<Step>>,
+}
+
+impl Search<'_> {
+    fn extend(&mut self, order: &mut Vec<Step>) {
+        let so_far = &order[order.len() - 1];
+        if self.best.as_ref().is_some_and(|best| so_far.cost >= best[best.len() - 1].cost) {
+            return;
+        }
+        if order.len() == self.relations.len() {
+            self.best = Some(order.clone());
+            return;
+        }
+        for next in 0..self.relations.len() {
+            if order.iter().any(|s| s.relation == next) {
+                continue;
+            }
+            let joined = |i: usize| order.iter().any(|s| s.relation == i);
+            let edge = self.edges.iter().position(|e| e.joins(next, joined));
+            if let Some(edge) = edge {
+                let step = self.step(order, next, edge);
+                order.push(step);
+                self.extend(order);
+                order.pop();
+            }
+        }
+    }
+
+    // Joining relation `next` to the join so far through condition `edge`
+    fn step(&self, order: &[Step], next: usize, edge: usize) -> Step {
+        let so_far = &order[order.len() - 1];
+        let e = &self.edges[edge];
+        let relation = &self.relations[next];
+        // The joined column on each side
+        let (_, col) = e.keys_for(next, self.names);
+        let other = match &e.left {
+            Some((left, _)) if *left == next => Some((e.right, e.right_key.as_str())),
+            left => left.as_ref().map(|(i, c)| (*i, c.as_str())),
+        };
+        let table_of = |r: &Relation| r.table.as_ref().and_then(|t| self.db.tables.get(t));
+        let table = table_of(relation);
+        let distinct = table.and_then(|t| stats::distinct(t, &col)).unwrap_or(relation.rows);
+        let other_distinct = other
+            .and_then(|(i, c)| table_of(&self.relations[i]).map(|t| (t, c, self.relations[i].rows)))
+            .map_or(so_far.rows, |(t, c, rows)| stats::distinct(t, c).unwrap_or(rows));
+        let distinct = distinct.max(other_distinct).max(1.0);
+        let rows = clamp(so_far.rows * relation.rows / distinct);
+
+        let hash = so_far.cost + relation.cost + (so_far.rows + relation.rows) * CPU_ROW + rows * CPU_ROW;
+        // An index join fetches every row matching the key, then applies the relation's filter
+        let index = relation.lookup.as_ref().zip(table).and_then(|((table_rows, filter), t)| {
+            let index = t.index_on(&col)?;
+            let fetched = so_far.rows * table_rows / distinct;
+            let checked = if filter.is_some() { fetched * CPU_ROW } else { 0.0 };
+            Some((index.name.clone(), so_far.cost + so_far.rows * (table_rows + 1.0).log2() + fetched * INDEX_ROW + checked))
+        });
+        match index {
+            Some((index, cost)) if cost <= hash => Step { relation: next, edge, index: Some(index), rows, cost },
+            _ => Step { relation: next, edge, index: None, rows, cost: hash },
+        }
+    }
+}
+
+impl Node {
+    fn new(op: Op, inputs: Vec<Node>, rows: f64, cost: f64) -> Self {
+        Node { id: 0, op, inputs, rows, cost }
+    }
+
+    fn any(&self, test: &impl Fn(&Op) -> bool) -> bool {
+        test(&self.op) || self.inputs.iter().any(|i| i.any(test))
+    }
+
+    fn label(&self) -> String {
+        match &self.op {
+            Op::Empty => "Result (one row)".to_string(),
+            Op::SeqScan { table, kind, columns } => format!("{} on {}{}", kind, table, plan::columns_label(columns)),
+            Op::IndexScan { table, accesses, columns, .. } => {
+                format!("Index Scan on {}{} using {}", table, plan::columns_label(columns), accesses.join(" AND "))
+            }
+            Op::Function { factor } => format!("Table Function: {}", factor),
+            Op::Score { calls, .. } => format!("Full-Text Score: {}", plan::join(calls)),
+            Op::Filter { predicate } => format!("Filter: {}", predicate),
+            Op::Join(Join { method: JoinMethod::Index { index, .. }, right_table, left_key, right_key, filter, .. }) => {
+                let filter = filter.as_ref().map_or(String::new(), |f| format!(" (filter: {})", f));
+                format!("Index Nested Loop Join: {} = {}.{} using {}{}", left_key, right_table, right_key, index, filter)
+            }
+            Op::Join(Join { method: JoinMethod::Hash, right_table, left_key, right_key, .. }) => {
+                format!("Hash Join: {} = {}.{}", left_key, right_table, right_key)
+            }
+            Op::SemiJoin { key, negated: false } => format!("Hash Semi Join: {} IN (subquery)", key),
+            Op::SemiJoin { key, negated: true } => format!("Hash Anti Join: {} NOT IN (subquery)", key),
+            Op::Sort { order_by } => format!("Sort: {}", plan::join(order_by)),
+            Op::Aggregate { items } => format!("Aggregate: {}", plan::items_sql(items)),
+            Op::Project { items } => format!("Project: {}", plan::items_sql(items)),
+            Op::Limit { limit, offset } => plan::limit_label(*limit, *offset),
+        }
+    }
+}
+
+// Rows flowing between operators, before the SELECT list turns them into values. They are
+// pulled one at a time: an operator asks its input for the next row only when it is asked for
+// one itself, so a LIMIT stops the scans below it early and rows reach the client as they are
+// produced. Sort, Aggregate, the build side of a hash join, a semi-join's subquery and full-text
+// scoring read all of their input first, on the first pull.
+type RowIter<'a> = Box<dyn Iterator<Item = Result<Cow<'a, Row>, String>> + 'a>;
+
+// An opened operator: its columns, and its rows as they are pulled
+struct Rows<'a> {
+    columns: Vec<String>,
+    rows: RowIter<'a>,
+}
+
+// EXPLAIN ANALYZE only: node id -> (rows produced, time taken)
+type Actual = Rc<RefCell<BTreeMap<usize, (usize, Duration)>>>;
+
+struct Executor<'a> {
+    db: &'a Database,
+    actual: Option<Actual>,
+}
+
+// Opens the plan; its rows are computed as the stream is read.
+pub fn execute<'a>(db: &'a Database, root: &Node) -> Result<ResultStream<'a>, String> {
+    Executor { db, actual: None }.result(root)
+}
+
+// Counts the rows an operator yields and the time spent in it, its inputs included
+struct Timed<I> {
+    id: usize,
+    inner: I,
+    actual: Actual,
+}
+
+impl<T, I: Iterator<Item = Result<T, String>>> Iterator for Timed<I> {
+    type Item = Result<T, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let item = self.inner.next();
+        let mut actual = self.actual.borrow_mut();
+        let (rows, time) = actual.entry(self.id).or_default();
+        if matches!(item, Some(Ok(_))) {
+            *rows += 1;
+        }
+        *time += start.elapsed();
+        item
+    }
+}
+
+// Rows that `build` can only produce all at once; it runs when the first row is pulled.
+fn deferred<'a, T: 'a>(build: impl FnOnce() -> Result<Vec<T>, String> + 'a) -> Box<dyn Iterator<Item = Result<T, String>> + 'a> {
+    let mut build = Some(build);
+    let mut rows = Vec::new().into_iter();
+    Box::new(std::iter::from_fn(move || {
+        if let Some(build) = build.take() {
+            match build() {
+                Ok(built) => rows = built.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        rows.next().map(Ok)
+    }))
+}
+
+impl<'a> Executor<'a> {
+    fn timed<T: 'a>(&self, node: &Node, rows: Box<dyn Iterator<Item = Result<T, String>> + 'a>) -> Box<dyn Iterator<Item = Result<T, String>> + 'a> {
+        match &self.actual {
+            Some(actual) => Box::new(Timed { id: node.id, inner: rows, actual: actual.clone() }),
+            None => rows,
+        }
+    }
+
+    // The operators that produce the final values: Project, Aggregate and a Limit above them
+    fn result(&self, node: &Node) -> Result<ResultStream<'a>, String> {
+        let stream = match &node.op {
+            Op::Limit { limit, offset } => {
+                let input = self.result(&node.inputs[0])?;
+                let limit = limit.map_or(usize::MAX, |n| n as usize);
+                let mut skip = *offset;
+                // Errors are passed on, not skipped
+                let rows = input.rows.filter(move |row| {
+                    if skip > 0 && row.is_ok() {
+                        skip -= 1;
+                        return false;
+                    }
+                    true
+                });
+                ResultStream { rows: Box::new(rows.take(limit)), ..input }
+            }
+            Op::Project { items } => {
+                let input = self.open(&node.inputs[0])?;
+                let (columns, targets) = targets(items, &input.columns)?;
+                let rows = input.rows.map(move |row| {
+                    let row = row?;
+                    let values = targets.iter().map(|target| match target {
+                        Target::Column(col) => Ok(row.data.get(col).cloned().unwrap_or(Value::Null)),
+                        Target::Expr(expr) => eval_expr(expr, &row.data),
+                    }).collect::<Result<Vec<_>, _>>()?;
+                    Ok((row.id, values))
+                });
+                let layout = if node.any(&|op| matches!(op, Op::Join { .. })) {
+                    Layout::Join
+                } else if node.any(&|op| matches!(op, Op::Empty)) {
+                    Layout::Single
+                } else {
+                    Layout::Table
+                };
+                ResultStream { columns, rows: Box::new(rows), layout }
+            }
+            Op::Aggregate { items } => {
+                let input = self.open(&node.inputs[0])?;
+                let (columns, targets) = targets(items, &input.columns)?;
+                let rows = deferred(move || {
+                    let rows = input.rows.collect::<Result<Vec<_>, _>>()?;
+                    let row_data: Vec<_> = rows.iter().map(|row| &row.data).collect();
+                    let values = targets.into_iter().map(|target| match target {
+                        Target::Column(col) => aggregate::eval_aggregate(&Expr::Identifier(Ident::new(col)), &row_data),
+                        Target::Expr(expr) => aggregate::eval_aggregate(&expr, &row_data),
+                    }).collect::<Result<Vec<_>, _>>()?;
+                    Ok(vec![(0, values)])
+                });
+                ResultStream { columns, rows, layout: Layout::Single }
+            }
+            _ => return Err("A query plan must end in a projection".to_string()),
+        };
+        Ok(ResultStream { rows: self.timed(node, stream.rows), ..stream })
+    }
+
+    // Opens an operator and its inputs. Nothing is read until its rows are pulled, except the
+    // rows of a view or catalog relation.
+    fn open(&self, node: &Node) -> Result<Rows<'a>, String> {
+        let db = self.db;
+        let out = match &node.op {
+            Op::Empty => Rows { columns: Vec::new(), rows: Box::new(std::iter::once(Ok(Cow::Owned(Row { id: 0, data: BTreeMap::new() })))) },
+            Op::SeqScan { table, columns, .. } => match (db.tables.get(table), columns) {
+                (Some(t), Some(columns)) => {
+                    let wanted = columns.clone();
+                    let rows = t.data.values().map(move |row| t.read_columns(row, &wanted).map(Cow::Owned));
+                    Rows { columns: columns.clone(), rows: Box::new(rows) }
+                }
+                (Some(t), None) => {
+                    let rows = t.data.values().map(|row| t.read_row(row));
+                    Rows { columns: t.columns.iter().map(|(c, _)| c.clone()).collect(), rows: Box::new(rows) }
+                }
+                (None, _) => {
+                    let (columns, rows) = relation(db, table)?;
+                    Rows { columns, rows: Box::new(rows.into_iter().map(Ok)) }
+                }
+            },
+            Op::IndexScan { table, predicate, index, columns, .. } => {
+                let t = db.tables.get(table).ok_or(format!("Table '{}' not found", table))?;
+                let (predicate, index) = (predicate.clone(), index.clone());
+                // The index is read on the first pull
+                let stored = std::iter::once(()).flat_map(move |()| -> Box<dyn Iterator<Item = &'a Row>> {
+                    match index::candidates(t, &predicate, index.as_deref()) {
+                        Some(ids) => Box::new(ids.into_iter().filter_map(|id| t.data.get(&id))),
+                        None => Box::new(t.data.values()),
+                    }
+                });
+                match columns {
+                    Some(columns) => {
+                        let wanted = columns.clone();
+                        let rows = stored.map(move |row| t.read_columns(row, &wanted).map(Cow::Owned));
+                        Rows { columns: columns.clone(), rows: Box::new(rows) }
+                    }
+                    None => Rows { columns: t.columns.iter().map(|(c, _)| c.clone()).collect(), rows: Box::new(stored.map(|row| t.read_row(row))) },
+                }
+            }
+            Op::Function { factor } => {
+                let columns = table_function_call(factor)?.ok_or(format!("'{}' is not a table function", factor))?.columns()?;
+                let factor = factor.clone();
+                match node.inputs.first() {
+                    // Evaluated once: its rows are numbered from 1
+                    None => {
+                        let rows = deferred(move || {
+                            let produced = function_rows(&factor, &BTreeMap::new())?;
+                            Ok(produced.into_iter().enumerate().map(|(i, data)| Cow::Owned(Row { id: i as u64 + 1, data })).collect())
+                        });
+                        Rows { columns, rows }
+                    }
+                    // Evaluated per input row, each output row extending the row it came from
+                    Some(input) => {
+                        let mut input = self.open(input)?;
+                        let rows = input.rows.flat_map(move |row| {
+                            let produced = row.and_then(|row| {
+                                let rows = function_rows(&factor, &row.data)?;
+                                Ok(rows.into_iter().map(|data| {
+                                    let mut merged = row.data.clone();
+                                    merged.extend(data);
+                                    Ok(Cow::Owned(Row { id: row.id, data: merged }))
+                                }).collect::<Vec<_>>())
+                            });
+                            produced.unwrap_or_else(|e| vec![Err(e)])
+                        });
+                        input.columns.extend(columns);
+                        Rows { columns: input.columns, rows: Box::new(rows) }
+                    }
+                }
+            }
+            Op::Score { table, calls } => {
+                let input = self.open(&node.inputs[0])?;
+                let (table, calls) = (table.clone(), calls.clone());
+                let rows = deferred(move || {
+                    let mut rows = input.rows.collect::<Result<Vec<_>, _>>()?;
+                    fulltext::bind(db, &table, &calls, &mut rows)?;
+                    Ok(rows)
+                });
+                Rows { columns: input.columns, rows }
+            }
+            Op::Filter { predicate } => {
+                let input = self.open(&node.inputs[0])?;
+                let predicate = predicate.clone();
+                let rows = input.rows.filter_map(move |row| {
+                    let keep = row.as_ref().map_err(|e| e.clone())
+                        .and_then(|row| truth(&eval_expr(&predicate, &row.data)?));
+                    // NULL filters the row out, like false
+                    match keep {
+                        Ok(Some(true)) => Some(row),
+                        Ok(_) => None,
+                        Err(e) => Some(Err(e)),
+                    }
+                });
+                Rows { columns: input.columns, rows: Box::new(rows) }
+            }
+            Op::Join(op) => {
+                let left = self.open(&node.inputs[0])?;
+                let right = match op.method {
+                    JoinMethod::Index { .. } => None,
+                    JoinMethod::Hash => Some(self.open(&node.inputs[1])?),
+                };
+                join(db, left, right, op)?
+            }
+            Op::SemiJoin { key, negated } => {
+                let input = self.open(&node.inputs[0])?;
+                let subquery = self.result(&node.inputs[1])?;
+                if subquery.columns.len() != 1 {
+                    return Err(format!("The subquery of IN must return one column, not {}", subquery.columns.len()));
+                }
+                let (key, negated) = (key.clone(), *negated);
+                let mut subquery = Some(subquery.rows);
+                let mut values = BTreeSet::new();
+                let rows = input.rows.filter_map(move |row| {
+                    let keep = (|| {
+                        if let Some(rows) = subquery.take() {
+                            for row in rows {
+                                values.insert(Key(row?.1));
+                            }
+                        }
+                        // Kept only where `key [NOT] IN (...)` is true, not NULL
+                        let value = eval_expr(&key, &row.as_ref().map_err(|e| e.clone())?.data)?;
+                        Ok(if values.is_empty() {
+                            negated
+                        } else if value == Value::Null {
+                            false
+                        } else {
+                            let found = values.contains(&Key(vec![value]));
+                            if negated { !found && !values.contains(&Key(vec![Value::Null])) } else { found }
+                        })
+                    })();
+                    match keep {
+                        Ok(true) => Some(row),
+                        Ok(false) => None,
+                        Err(e) => Some(Err(e)),
+                    }
+                });
+                Rows { columns: input.columns, rows: Box::new(rows) }
+            }
+            Op::Sort { order_by } => {
+                let input = self.open(&node.inputs[0])?;
+                let order_by = order_by.clone();
+                let rows = deferred(move || {
+                    let mut rows = input.rows.collect::<Result<Vec<_>, _>>()?;
+                    order_rows(&mut rows, &order_by, |row| &row.data)?;
+                    Ok(rows)
+                });
+                Rows { columns: input.columns, rows }
+            }
+            Op::Aggregate { .. } | Op::Project { .. } | Op::Limit { .. } => {
+                return Err("A projection can only end a query plan".to_string());
+            }
+        };
+        Ok(Rows { rows: self.timed(node, out.rows), ..out })
+    }
+}
+
+// The rows a table function produces for one input row
+fn function_rows(factor: &TableFactor, row: &BTreeMap<String, Value>) -> Result<Vec<BTreeMap<String, Value>>, String> {
+    table_function_call(factor)?.ok_or(format!("'{}' is not a table function", factor))?.rows(row)
+}
+
+// `row` keyed both by `table.column` and by bare column name
+fn qualified(table: &str, row: &BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+    let mut out = BTreeMap::new();
+    for (col, val) in row {
+        out.insert(format!("{}.{}", table, col), val.clone());
+        out.insert(col.clone(), val.clone());
+    }
+    out
+}
+
+// Inner equi-join, streaming the left rows. Without `right` rows, partners are found through the
+// right table's index on `right_key`, then checked against the join's filter; with them, through
+// a map built from them on the first pull. Output columns are `relation.column`.
+fn join<'a>(db: &'a Database, left: Rows<'a>, right: Option<Rows<'a>>, op: &Join) -> Result<Rows<'a>, String> {
+    let Join { method, left_table, right_table, left_key, right_key, filter, order } = op;
+    let lookup = match method {
+        JoinMethod::Index { table, .. } => Some(db.tables.get(table).ok_or(format!("Table '{}' not found", table))?),
+        JoinMethod::Hash => None,
+    };
+    let mut columns: Vec<String> = match left_table {
+        Some(table) => left.columns.iter().map(|c| format!("{}.{}", table, c)).collect(),
+        None => left.columns,
+    };
+    let (right_columns, mut build) = match (right, lookup) {
+        (Some(right), _) => (right.columns, Some(right.rows)),
+        (None, Some(table)) => (table.columns.iter().map(|(c, _)| c.clone()).collect(), None),
+        (None, None) => return Err("A hash join needs its right input".to_string()),
+    };
+    columns.extend(right_columns.iter().map(|c| format!("{}.{}", right_table, c)));
+    // Back in the order the tables were written, each table's columns in their own order
+    if let Some(order) = order {
+        let position = |col: &String| order.iter().enumerate()
+            .filter(|(_, t)| col.starts_with(&format!("{}.", t)))
+            .max_by_key(|(_, t)| t.len())
+            .map_or(order.len(), |(i, _)| i);
+        columns.sort_by_key(position);
+    }
+
+    let (left_table, right_table, left_key, right_key, filter) = (left_table.clone(), right_table.clone(), left_key.clone(), right_key.clone(), filter.clone());
+    let mut hashed: BTreeMap<Key, Vec<usize>> = BTreeMap::new();
+    let mut built: Vec<Cow<'a, Row>> = Vec::new();
+    let mut join_row = move |left_row: &Row| -> Result<Vec<Cow<'a, Row>>, String> {
+        if let Some(rows) = build.take() {
+            built = rows.collect::<Result<_, _>>()?;
+            for (i, row) in built.iter().enumerate() {
+                match row.data.get(&right_key) {
+                    None | Some(Value::Null) => {}
+                    Some(value) => hashed.entry(Key(vec![value.clone()])).or_default().push(i),
+                }
+            }
+        }
+        let l_val = eval_expr(&left_key, &left_row.data)?;
+        if l_val == Value::Null {
+            return Ok(Vec::new());
+        }
+        let mut out = Vec::new();
+        let mut add = |right_row: &Row| {
+            // The map and the index group values that compare equal, such as 1 and 1.0
+            if right_row.data.get(&right_key) != Some(&l_val) {
+                return;
+            }
+            let mut joined = match &left_table {
+                Some(table) => qualified(table, &left_row.data),
+                None => left_row.data.clone(),
+            };
+            joined.extend(qualified(&right_table, &right_row.data));
+            out.push(Cow::Owned(Row { id: left_row.id, data: joined }));
+        };
+        if let Some(table) = lookup {
+            let ids = index::lookup(table, &right_key, &l_val).ok_or(format!("Table '{}' has no index on '{}'", table.name, right_key))?;
+            for row in ids.iter().filter_map(|id| table.data.get(id)) {
+                let row = table.read_row(row)?;
+                if let Some(filter) = &filter && truth(&eval_expr(filter, &row.data)?)? != Some(true) {
+                    continue;
+                }
+                add(&row);
+            }
+        } else {
+            for &i in hashed.get(&Key(vec![l_val.clone()])).into_iter().flatten() {
+                add(&built[i]);
+            }
+        }
+        Ok(out)
+    };
+    let rows = left.rows.flat_map(move |row| match row.and_then(|row| join_row(&row)) {
+        Ok(rows) => rows.into_iter().map(Ok).collect(),
+        Err(e) => vec![Err(e)],
+    });
+    Ok(Rows { columns, rows: Box::new(rows) })
+}
+
+enum Target {
+    // From * : read as is, NULL if a row lacks it
+    Column(String),
+    Expr(Expr),
+}
+
+// Headers and value sources of a SELECT list over `columns`
+fn targets(items: &[Item], columns: &[String]) -> Result<(Vec<String>, Vec<Target>), String> {
+    let mut headers = Vec::new();
+    let mut targets = Vec::new();
+    for item in items {
+        match item {
+            Item::Wildcard => {
+                for col in columns {
+                    headers.push(col.clone());
+                    targets.push(Target::Column(col.clone()));
+                }
+            }
+            Item::Column(col) => {
+                if !columns.contains(col) {
+                    return Err(format!("Column '{}' not found", col));
+                }
+                headers.push(col.clone());
+                targets.push(Target::Expr(Expr::Identifier(Ident::new(col))));
+            }
+            Item::Expr(header, expr) => {
+                headers.push(header.clone());
+                targets.push(Target::Expr(expr.clone()));
+            }
+        }
+    }
+    Ok((headers, targets))
+}
+
+// EXPLAIN [ANALYZE] [VERBOSE] SELECT ...: the physical plan, one operator per line with its
+// estimated cost and rows and its inputs indented below it. VERBOSE first prints the logical plan it was chosen from,
+// before and after rewriting; ANALYZE runs the query and adds what each operator produced.
+pub fn explain(db: &Database, query: &Query, analyze: bool, verbose: bool) -> Result<ResultSet, String> {
+    let logical = plan::build(query)?;
+    let mut lines = Vec::new();
+    if verbose {
+        lines.push("Logical plan:".to_string());
+        logical_lines(&logical, 0, &mut lines);
+    }
+    let rewritten = rewrite::rewrite(db, logical)?;
+    if verbose {
+        lines.push("Rewritten plan:".to_string());
+        logical_lines(&rewritten, 0, &mut lines);
+        lines.push("Physical plan:".to_string());
+    }
+    let root = plan(db, rewritten)?;
+    let mut actual = None;
+    let mut total = Duration::ZERO;
+    if analyze {
+        let recorded = Actual::default();
+        let executor = Executor { db, actual: Some(recorded.clone()) };
+        let start = Instant::now();
+        for row in executor.result(&root)?.rows {
+            row?;
+        }
+        total = start.elapsed();
+        actual = Some(recorded.take());
+    }
+    physical_lines(&root, 0, actual.as_ref(), &mut lines);
+    if analyze {
+        lines.push(format!("Execution time: {}", ms(total)));
+    }
+    Ok(ResultSet {
+        columns: vec!["QUERY PLAN".to_string()],
+        rows: lines.into_iter().map(|line| (0, vec![Value::Text(line)])).collect(),
+        layout: Layout::Single,
+    })
+}
+
+fn indent(depth: usize) -> String {
+    if depth == 0 { String::new() } else { format!("{}-> ", "  ".repeat(depth)) }
+}
+
+fn ms(time: Duration) -> String {
+    format!("{:.3} ms", time.as_secs_f64() * 1000.0)
+}
+
+fn logical_lines(plan: &Plan, depth: usize, lines: &mut Vec<String>) {
+    lines.push(format!("{}{}", indent(depth), plan.label()));
+    for input in plan.inputs() {
+        logical_lines(input, depth + 1, lines);
+    }
+}
+
+fn physical_lines(node: &Node, depth: usize, actual: Option<&BTreeMap<usize, (usize, Duration)>>, lines: &mut Vec<String>) {
+    let estimate = format!("  (cost={:.2} rows={:.0})", node.cost, node.rows);
+    let figures = match actual.map(|a| a.get(&node.id)) {
+        None => String::new(),
+        Some(Some((rows, time))) => format!(" (actual rows={} time={})", rows, ms(*time)),
+        Some(None) => " (never executed)".to_string(),
+    };
+    lines.push(format!("{}{}{}{}", indent(depth), node.label(), estimate, figures));
+    for input in &node.inputs {
+        physical_lines(input, depth + 1, actual, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{rows, run, setup};
+    use crate::Value;
+
+    // The operator names of an EXPLAIN, without their costs
+    fn operators(db: &mut crate::Database, sql: &str) -> Vec<String> {
+        let plan = run(db, &format!("EXPLAIN {}", sql)).unwrap();
+        plan.lines().skip(1).map(|line| line.trim_start_matches([' ', '-', '>']).split("  (").next().unwrap_or("").to_string()).collect()
+    }
+
+    #[test]
+    fn explain_shows_the_cheapest_access_and_join() {
+        let mut db = setup("
+            CREATE TABLE big (id INTEGER PRIMARY KEY, k INTEGER);
+            CREATE TABLE small (id INTEGER PRIMARY KEY, big_id INTEGER);
+            INSERT INTO small VALUES (1, 5), (2, 6);
+        ");
+        let values: Vec<String> = (1..=200).map(|i| format!("({}, {})", i, i % 10)).collect();
+        run(&mut db, &format!("INSERT INTO big VALUES {}", values.join(", "))).unwrap();
+        assert_eq!(operators(&mut db, "SELECT * FROM big WHERE id = 5"), ["Project: *", "Filter: id = 5", "Index Scan on big using big_pkey (id = 5)"]);
+        assert_eq!(operators(&mut db, "SELECT * FROM big WHERE k = 5"), ["Project: *", "Filter: k = 5", "Seq Scan on big"]);
+        // Few left rows look their partners up; written the other way round, the join starts from them
+        let index_join = ["Project: *", "Index Nested Loop Join: small.big_id = big.id using big_pkey", "Seq Scan on small"];
+        assert_eq!(operators(&mut db, "SELECT * FROM small JOIN big ON small.big_id = big.id"), index_join);
+        assert_eq!(operators(&mut db, "SELECT * FROM big JOIN small ON big.id = small.big_id")[1..], index_join[1..]);
+        // Without an index the join hashes one side
+        assert_eq!(
+            operators(&mut db, "SELECT * FROM small JOIN big ON small.big_id = big.k"),
+            ["Project: *", "Hash Join: small.big_id = big.k", "Seq Scan on small", "Seq Scan on big"]
+        );
+    }
+
+    #[test]
+    fn explain_analyze_counts_the_rows_each_operator_produced() {
+        let mut db = setup("
+            CREATE TABLE big (id INTEGER PRIMARY KEY, k INTEGER);
+            CREATE TABLE small (id INTEGER PRIMARY KEY, big_id INTEGER);
+            INSERT INTO small VALUES (1, 5), (2, 6);
+            INSERT INTO big VALUES (1, 5), (2, 5), (3, 7);
+        ");
+        let sql = "SELECT * FROM small JOIN big ON small.big_id = big.k WHERE small.id = 9";
+        let plan = run(&mut db, &format!("EXPLAIN ANALYZE {}", sql)).unwrap();
+        let line = |op: &str| plan.lines().find(|l| l.contains(op)).unwrap_or_else(|| panic!("no {} in {}", op, plan)).to_string();
+        assert!(line("Seq Scan on small").contains("(actual rows=2 "), "{}", plan);
+        // The filter runs below the join, so nothing reaches it
+        assert!(line("Filter").contains("(actual rows=0 "), "{}", plan);
+        assert!(line("Hash Join").contains("(actual rows=0 "), "{}", plan);
+        // No left row asked for partners, so the hashed side was never read
+        assert!(line("Seq Scan on big").ends_with("(never executed)"), "{}", plan);
+        assert!(plan.lines().last().is_some_and(|l| l.starts_with("Execution time: ")), "{}", plan);
+        assert!(rows(&mut db, sql).is_empty());
+    }
+
+    #[test]
+    fn self_joins_keep_both_sides_apart() {
+        let mut db = setup("
+            CREATE TABLE g (id INTEGER PRIMARY KEY, k INTEGER);
+            INSERT INTO g VALUES (1, 0), (2, 1), (3, 1);
+        ");
+        let pairs: Vec<Vec<Value>> = [(1, 1), (2, 2), (2, 3), (3, 2), (3, 3)].iter()
+            .map(|&(a, b)| vec![Value::Integer(a), Value::Integer(b)])
+            .collect();
+        let found: Vec<Vec<Value>> = rows(&mut db, "SELECT * FROM g a JOIN g b ON a.k = b.k").into_iter()
+            .map(|row| vec![row[0].clone(), row[2].clone()])
+            .collect();
+        assert_eq!(found, pairs);
+        assert_eq!(run(&mut db, "SELECT * FROM g JOIN g ON g.k = g.k"), Err("Table name 'g' specified more than once; give one of them an alias".to_string()));
+    }
+
+    #[test]
+    fn aliased_tables_still_join_through_their_indexes() {
+        let mut db = setup("
+            CREATE TABLE a (id INTEGER PRIMARY KEY, b_id INTEGER);
+            CREATE TABLE b (id INTEGER PRIMARY KEY, name TEXT);
+            INSERT INTO a VALUES (1, 7);
+        ");
+        let values: Vec<String> = (1..=200).map(|i| format!("({}, 'b{}')", i, i)).collect();
+        run(&mut db, &format!("INSERT INTO b VALUES {}", values.join(", "))).unwrap();
+        let plan = run(&mut db, "EXPLAIN SELECT * FROM a x JOIN b y ON x.b_id = y.id").unwrap();
+        assert!(plan.contains("Index Nested Loop Join: x.b_id = y.id"), "{}", plan);
+        assert_eq!(rows(&mut db, "SELECT * FROM a x JOIN b y ON x.b_id = y.id"), vec![vec![
+            Value::Integer(1), Value::Integer(7), Value::Integer(7), Value::Text("b7".to_string()),
+        ]]);
+    }
+}
@@ -0,0 +1,404 @@
+// --- LOGICAL PLANS ---
+// A SELECT is first turned into a logical plan: a tree of relational operators (scan, filter,
+// join, sort, aggregate, project, limit) saying what to compute, not how. physical::plan then
+// picks an algorithm for each operator, and physical::execute runs the result.
+use std::collections::BTreeMap;
+
+use sqlparser::ast::{BinaryOperator, Expr, Ident, JoinConstraint, JoinOperator, OrderByExpr, Query, SelectItem, SetExpr, TableFactor, TableWithJoins};
+
+use crate::expr::eval_expr;
+use crate::{aggregate, fulltext, object_name, table_function_call, Database, Value};
+
+pub enum Plan {
+    // One row with no columns: what a SELECT without FROM reads
+    Empty,
+    // A table, view or catalog relation; `columns` lists the ones the query reads, when that is
+    // known to be fewer than all of them
+    Scan { table: String, columns: Option<Vec<String>> },
+    // A table function, once (FROM json_each('[1, 2]')) or for each input row
+    // (FROM t, json_each(t.tags))
+    Function { input: Option<Box<Plan>>, factor: TableFactor },
+    // MATCH ... AGAINST scores for the rows of `table`, computed once for all of them
+    Score { input: Box<Plan>, table: String, calls: Vec<Expr> },
+    Filter { input: Box<Plan>, predicate: Expr },
+    // INNER JOIN right ON left_key = right.right_key. `left_table` names the left input when it
+    // is a single relation, whose columns the join qualifies as `table.column`. `written` is set
+    // on the last join of a reordered chain: the relations in the order the query wrote them,
+    // which is the order their columns come out in.
+    Join { left: Box<Plan>, right: Box<Plan>, left_table: Option<String>, right_table: String, left_key: Expr, right_key: String, written: Option<Vec<String>> },
+    // The input rows whose `key` is (or with `negated`, is not) among the values of the
+    // one-column `subquery`: `key [NOT] IN (SELECT ...)`
+    SemiJoin { input: Box<Plan>, subquery: Box<Plan>, key: Expr, negated: bool },
+    Sort { input: Box<Plan>, order_by: Vec<OrderByExpr> },
+    // Collapses all input rows into one
+    Aggregate { input: Box<Plan>, items: Vec<Item> },
+    Project { input: Box<Plan>, items: Vec<Item> },
+    Limit { input: Box<Plan>, limit: Option<u64>, offset: u64 },
+}
+
+// A SELECT list entry
+pub enum Item {
+    // *: every input column
+    Wildcard,
+    // A bare column, which must exist in the input
+    Column(String),
+    // An expression and its header
+    Expr(String, Expr),
+}
+
+impl Item {
+    fn sql(&self) -> String {
+        match self {
+            Item::Wildcard => "*".to_string(),
+            Item::Column(col) => col.clone(),
+            Item::Expr(header, expr) if *header == expr.to_string() => header.clone(),
+            Item::Expr(header, expr) => format!("{} AS {}", expr, header),
+        }
+    }
+}
+
+pub fn build(query: &Query) -> Result<Plan, String> {
+    let SetExpr::Select(select) = &*query.body else {
+        return Err("Only SELECT statements supported".to_string());
+    };
+    let joined = select.from.first().is_some_and(|f| !f.joins.is_empty());
+    let mut plan = if select.from.is_empty() {
+        Plan::Empty
+    } else if joined {
+        joins(&select.from)?
+    } else {
+        from(&select.from)?
+    };
+
+    // Full-text scores are computed for one table, before its rows are filtered
+    let calls = fulltext::calls(&query.to_string())?;
+    if !calls.is_empty() {
+        let Plan::Scan { table, .. } = &plan else {
+            return Err("MATCH ... AGAINST is only supported in a SELECT from one table".to_string());
+        };
+        plan = Plan::Score { table: table.clone(), input: Box::new(plan), calls };
+    }
+    if let Some(predicate) = &select.selection {
+        plan = Plan::Filter { input: Box::new(plan), predicate: predicate.clone() };
+    }
+    if !query.order_by.is_empty() {
+        plan = Plan::Sort { input: Box::new(plan), order_by: query.order_by.clone() };
+    }
+
+    // A join returns every column of both sides, whatever the SELECT list says
+    plan = if joined {
+        Plan::Project { input: Box::new(plan), items: vec![Item::Wildcard] }
+    } else if aggregate::is_aggregate_query(&select.projection) {
+        Plan::Aggregate { input: Box::new(plan), items: items(&select.projection, select.from.is_empty())? }
+    } else {
+        Plan::Project { input: Box::new(plan), items: items(&select.projection, select.from.is_empty())? }
+    };
+
+    if query.limit.is_some() || query.offset.is_some() {
+        let limit = query.limit.as_ref().map(|e| count(e, "LIMIT")).transpose()?;
+        let offset = query.offset.as_ref().map(|o| count(&o.value, "OFFSET")).transpose()?.unwrap_or(0);
+        plan = Plan::Limit { input: Box::new(plan), limit, offset };
+    }
+    Ok(plan)
+}
+
+// FROM t, or FROM t, json_each(t.doc), ...
+fn from(from: &[TableWithJoins]) -> Result<Plan, String> {
+    let mut plan = match (&from[0].relation, table_function_call(&from[0].relation)?) {
+        (factor, Some(_)) => Plan::Function { input: None, factor: factor.clone() },
+        (TableFactor::Table { name, .. }, None) => Plan::Scan { table: object_name(name), columns: None },
+        _ => return Err("Only simple table names supported".to_string()),
+    };
+    for item in &from[1..] {
+        if table_function_call(&item.relation)?.is_none() {
+            return Err("Only table functions may follow the first table in FROM; use JOIN ... ON to combine tables".to_string());
+        }
+        plan = Plan::Function { input: Some(Box::new(plan)), factor: item.relation.clone() };
+    }
+    Ok(plan)
+}
+
+// FROM a JOIN b ON a.x = b.y [JOIN c ON ...], joined left to right. Each relation is named by
+// its alias, or else its table, and no name may appear twice.
+fn joins(from: &[TableWithJoins]) -> Result<Plan, String> {
+    if from.len() > 1 {
+        return Err("JOIN cannot be combined with other tables in FROM; join them too".to_string());
+    }
+    let (first, scan) = relation(&from[0].relation).ok_or("Only simple table names supported")?;
+    let mut names = vec![first.clone()];
+    let mut left_table = Some(first);
+    let mut plan = scan;
+    for join in &from[0].joins {
+        let (right_table, right) = relation(&join.relation).ok_or("Only simple table joins supported")?;
+        if names.contains(&right_table) {
+            return Err(format!("Table name '{}' specified more than once; give one of them an alias", right_table));
+        }
+        names.push(right_table.clone());
+        let (left_key, right_key) = match &join.join_operator {
+            JoinOperator::Inner(JoinConstraint::On(Expr::BinaryOp { left, op: BinaryOperator::Eq, right })) => {
+                join_keys(left, right, &right_table)?
+            }
+            _ => return Err("Only INNER JOIN ... ON supported".to_string()),
+        };
+        plan = Plan::Join {
+            left: Box::new(plan),
+            right: Box::new(right),
+            left_table: left_table.take(),
+            right_table,
+            left_key,
+            right_key,
+            written: None,
+        };
+    }
+    Ok(plan)
+}
+
+// A joined table: the name its columns are qualified with, and its scan
+fn relation(factor: &TableFactor) -> Option<(String, Plan)> {
+    let TableFactor::Table { name, alias, .. } = factor else { return None };
+    let table = object_name(name);
+    let name = alias.as_ref().map_or_else(|| table.clone(), |alias| alias.name.value.clone());
+    Some((name, Plan::Scan { table, columns: None }))
+}
+
+// The table a relation of a join chain reads, under the filters and semi-joins pushed onto it
+pub fn scanned_table(plan: &Plan) -> Option<&str> {
+    match plan {
+        Plan::Scan { table, .. } => Some(table),
+        Plan::Filter { input, .. } | Plan::SemiJoin { input, .. } => scanned_table(input),
+        _ => None,
+    }
+}
+
+// The two sides of ON a.x = b.y, where b is the table being joined: the key read from the rows
+// joined so far, and b's column. Unqualified columns are taken as ON left = right.
+fn join_keys(left: &Expr, right: &Expr, right_table: &str) -> Result<(Expr, String), String> {
+    fn column(expr: &Expr) -> Option<(Option<String>, String)> {
+        match expr {
+            Expr::Identifier(ident) => Some((None, ident.value.clone())),
+            Expr::CompoundIdentifier(idents) => {
+                let (last, qualifier) = idents.split_last()?;
+                Some((Some(qualifier.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(".")), last.value.clone()))
+            }
+            _ => None,
+        }
+    }
+    let (Some(l), Some(r)) = (column(left), column(right)) else {
+        return Err("Unsupported ON condition".to_string());
+    };
+    let names_right = |qualifier: &Option<String>| qualifier.as_deref() == Some(right_table);
+    // Written the other way round: ON b.y = a.x
+    if names_right(&l.0) && !names_right(&r.0) {
+        return Ok((right.clone(), l.1));
+    }
+    Ok((left.clone(), r.1))
+}
+
+fn items(projection: &[SelectItem], no_from: bool) -> Result<Vec<Item>, String> {
+    projection.iter().map(|item| Ok(match item {
+        SelectItem::Wildcard(_) if !no_from => Item::Wildcard,
+        SelectItem::UnnamedExpr(Expr::Identifier(ident)) if !no_from => Item::Column(ident.value.clone()),
+        SelectItem::UnnamedExpr(expr) => Item::Expr(expr.to_string(), expr.clone()),
+        SelectItem::ExprWithAlias { expr, alias } => Item::Expr(alias.value.clone(), expr.clone()),
+        _ if no_from => return Err("SELECT * requires a FROM clause".to_string()),
+        _ => return Err("Unsupported SELECT item".to_string()),
+    })).collect()
+}
+
+// The row count of LIMIT n or OFFSET n
+fn count(expr: &Expr, clause: &str) -> Result<u64, String> {
+    match eval_expr(expr, &BTreeMap::new())? {
+        Value::Integer(n) if n >= 0 => Ok(n as u64),
+        _ => Err(format!("{} must be a non-negative integer, got {}", clause, expr)),
+    }
+}
+
+impl Plan {
+    pub fn inputs(&self) -> Vec<&Plan> {
+        match self {
+            Plan::Empty | Plan::Scan { .. } => Vec::new(),
+            Plan::Function { input, .. } => input.iter().map(|i| &**i).collect(),
+            Plan::Join { left, right, .. } => vec![left, right],
+            Plan::SemiJoin { input, subquery, .. } => vec![input, subquery],
+            Plan::Score { input, .. } | Plan::Filter { input, .. } | Plan::Sort { input, .. }
+            | Plan::Aggregate { input, .. } | Plan::Project { input, .. } | Plan::Limit { input, .. } => vec![input],
+        }
+    }
+
+    // One line describing the operator, for EXPLAIN VERBOSE
+    pub fn label(&self) -> String {
+        match self {
+            Plan::Empty => "Empty (one row)".to_string(),
+            Plan::Scan { table, columns } => format!("Scan: {}{}", table, columns_label(columns)),
+            Plan::Function { factor, .. } => format!("Table Function: {}", factor),
+            Plan::Score { calls, .. } => format!("Full-Text Score: {}", join(calls)),
+            Plan::Filter { predicate, .. } => format!("Filter: {}", predicate),
+            Plan::Join { right_table, left_key, right_key, .. } => format!("Inner Join: {} = {}.{}", left_key, right_table, right_key),
+            Plan::SemiJoin { key, negated: false, .. } => format!("Semi Join: {} IN (subquery)", key),
+            Plan::SemiJoin { key, negated: true, .. } => format!("Anti Join: {} NOT IN (subquery)", key),
+            Plan::Sort { order_by, .. } => format!("Sort: {}", join(order_by)),
+            Plan::Aggregate { items, .. } => format!("Aggregate: {}", items_sql(items)),
+            Plan::Project { items, .. } => format!("Project: {}", items_sql(items)),
+            Plan::Limit { limit, offset, .. } => limit_label(*limit, *offset),
+        }
+    }
+}
+
+pub fn join<T: std::fmt::Display>(items: &[T]) -> String {
+    items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+pub fn items_sql(items: &[Item]) -> String {
+    items.iter().map(Item::sql).collect::<Vec<_>>().join(", ")
+}
+
+pub fn limit_label(limit: Option<u64>, offset: u64) -> String {
+    match (limit, offset) {
+        (Some(n), 0) => format!("Limit: {}", n),
+        (Some(n), offset) => format!("Limit: {} offset {}", n, offset),
+        (None, offset) => format!("Offset: {}", offset),
+    }
+}
+
+// " [a, b]" after a scan that reads only some columns
+pub fn columns_label(columns: &Option<Vec<String>>) -> String {
+    columns.as_ref().map_or(String::new(), |columns| format!(" [{}]", columns.join(", ")))
+}
+
+// A chain of inner joins taken apart: its relations in join order, and for each one after the
+// first the condition joining it to those before: (left_key, right_key), as in Plan::Join.
+pub struct Chain {
+    pub relations: Vec<(String, Plan)>,
+    pub conditions: Vec<(Expr, String)>,
+    pub written: Option<Vec<String>>,
+}
+
+// A join condition `left_key = right.right_key` as an edge between two relations of a chain.
+// `left` is the relation and column `left_key` names, when it names a column of one.
+pub struct Edge {
+    pub left: Option<(usize, String)>,
+    pub left_key: Expr,
+    pub right: usize,
+    pub right_key: String,
+}
+
+impl Chain {
+    pub fn new(mut plan: Plan) -> Result<Chain, String> {
+        let mut joined = Vec::new();
+        let mut written = None;
+        let first = loop {
+            let Plan::Join { left, right, left_table, right_table, left_key, right_key, written: order } = plan else {
+                return Err("A join must start from a table".to_string());
+            };
+            written = written.or(order);
+            joined.push((right_table, *right, left_key, right_key));
+            match left_table {
+                Some(name) => break (name, *left),
+                None => plan = *left,
+            }
+        };
+        joined.reverse();
+        let mut chain = Chain { relations: vec![first], conditions: Vec::new(), written };
+        for (name, right, left_key, right_key) in joined {
+            chain.relations.push((name, right));
+            chain.conditions.push((left_key, right_key));
+        }
+        Ok(chain)
+    }
+
+    // The chain as a left-deep tree of joins
+    pub fn into_plan(self) -> Plan {
+        let mut relations = self.relations.into_iter();
+        let Some((first, mut plan)) = relations.next() else { return Plan::Empty };
+        let mut left_table = Some(first);
+        for ((right_table, right), (left_key, right_key)) in relations.zip(self.conditions) {
+            let left = Box::new(plan);
+            plan = Plan::Join { left, right: Box::new(right), left_table: left_table.take(), right_table, left_key, right_key, written: None };
+        }
+        if let Plan::Join { written, .. } = &mut plan {
+            *written = self.written;
+        }
+        plan
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.relations.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    // The table each relation reads, which an alias names differently
+    pub fn tables(&self) -> Vec<Option<String>> {
+        self.relations.iter().map(|(_, plan)| scanned_table(plan).map(str::to_string)).collect()
+    }
+
+    // Condition i joins relation i + 1 to the relation its left key names: `t.col`, or a bare
+    // `col` that only one of the tables before it has.
+    pub fn edges(&self, db: &Database) -> Vec<Edge> {
+        self.conditions.iter().enumerate().map(|(i, (left_key, right_key))| {
+            let before = &self.relations[..=i];
+            let left = match left_key {
+                Expr::CompoundIdentifier(idents) => idents.split_last().and_then(|(last, qualifier)| {
+                    let qualifier = qualifier.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(".");
+                    let position = before.iter().position(|(name, _)| *name == qualifier)?;
+                    Some((position, last.value.clone()))
+                }),
+                Expr::Identifier(ident) => {
+                    let has = |plan: &Plan| scanned_table(plan).and_then(|t| db.tables.get(t)).is_some_and(|t| t.columns.iter().any(|(c, _)| *c == ident.value));
+                    let mut owners = before.iter().enumerate().filter(|(_, (_, plan))| has(plan));
+                    match (owners.next(), owners.next()) {
+                        (Some((position, _)), None) => Some((position, ident.value.clone())),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            Edge { left, left_key: left_key.clone(), right: i + 1, right_key: right_key.clone() }
+        }).collect()
+    }
+
+    // Can its relations be joined in another order? Each condition must name its columns, and
+    // each relation must appear once.
+    pub fn reorderable(&self, edges: &[Edge]) -> bool {
+        let names = self.names();
+        edges.iter().all(|e| e.left.is_some()) && names.iter().enumerate().all(|(i, name)| !names[..i].contains(name))
+    }
+
+    // The chain with its relations in `order`, each joined through an edge to one before it.
+    // The output columns keep the order written.
+    pub fn reorder(self, edges: &[Edge], order: &[usize]) -> Chain {
+        let names = self.names();
+        let written = self.written.clone().unwrap_or_else(|| names.clone());
+        let mut conditions = Vec::new();
+        for (k, &next) in order.iter().enumerate().skip(1) {
+            let joined = |i: usize| order[..k].contains(&i);
+            if let Some(edge) = edges.iter().find(|e| e.joins(next, joined)) {
+                conditions.push(edge.keys_for(next, &names));
+            }
+        }
+        let mut relations: Vec<Option<(String, Plan)>> = self.relations.into_iter().map(Some).collect();
+        let relations = order.iter().filter_map(|&i| relations[i].take()).collect();
+        let reordered = order.iter().copied().ne(0..names.len());
+        Chain { relations, conditions, written: if reordered || self.written.is_some() { Some(written) } else { None } }
+    }
+}
+
+impl Edge {
+    // Does it join relation `next` to one for which `joined` holds?
+    pub fn joins(&self, next: usize, joined: impl Fn(usize) -> bool) -> bool {
+        let Some((left, _)) = &self.left else { return false };
+        (self.right == next && joined(*left)) || (*left == next && joined(self.right))
+    }
+
+    // The condition as it joins `next`: the key read from the relations joined before it, and
+    // `next`'s column
+    pub fn keys_for(&self, next: usize, names: &[String]) -> (Expr, String) {
+        match &self.left {
+            Some((left, col)) if *left == next => (column_expr(&names[self.right], &self.right_key), col.clone()),
+            _ => (self.left_key.clone(), self.right_key.clone()),
+        }
+    }
+}
+
+// `table.col` as an expression
+pub fn column_expr(table: &str, col: &str) -> Expr {
+    Expr::CompoundIdentifier(table.split('.').chain([col]).map(Ident::new).collect())
+}
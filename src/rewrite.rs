@@ -0,0 +1,511 @@
+// --- QUERY REWRITES ---
+// Rule-based rewrites of a logical plan, applied before the physical planner sees it. Each rule
+// keeps the query's result and makes less work for it: constants are folded, `x IN (SELECT ...)`
+// becomes a semi-join, filters move below joins onto the table they restrict, inner joins start
+// from the restricted tables, and a table scan reads only the columns the query mentions.
+use std::collections::BTreeMap;
+
+use sqlparser::ast::{BinaryOperator, Expr, FunctionArg, FunctionArgExpr, Value as SqlValue};
+
+use crate::expr::{eval_expr, parse_expr, sql_literal};
+use crate::index::conjuncts;
+use crate::plan::{self, Chain, Item, Plan};
+use crate::{aggregate, Database};
+
+// Functions whose result changes from call to call, or which act on the database
+const VOLATILE: [&str; 9] = ["gen_random_uuid", "uuid_generate_v4", "uuidv4", "uuidv7", "uuid_generate_v7", "raise", "nextval", "currval", "setval"];
+
+pub fn rewrite(db: &Database, plan: Plan) -> Result<Plan, String> {
+    let plan = fold(plan)?;
+    let plan = semi_joins(db, plan)?;
+    let plan = push_down(db, plan)?;
+    let plan = reorder(db, plan)?;
+    prune(db, plan)
+}
+
+// Rebuilds `plan` with `f` applied to each of its inputs. The subquery of a semi-join is left
+// alone: it was rewritten on its own when the semi-join was made.
+fn map_inputs(plan: Plan, f: &mut impl FnMut(Plan) -> Result<Plan, String>) -> Result<Plan, String> {
+    let mut apply = |input: Box<Plan>| f(*input).map(Box::new);
+    Ok(match plan {
+        Plan::Empty | Plan::Scan { .. } => plan,
+        Plan::Function { input, factor } => Plan::Function { input: input.map(&mut apply).transpose()?, factor },
+        Plan::Score { input, table, calls } => Plan::Score { input: apply(input)?, table, calls },
+        Plan::Filter { input, predicate } => Plan::Filter { input: apply(input)?, predicate },
+        Plan::Join { left, right, left_table, right_table, left_key, right_key, written } => {
+            Plan::Join { left: apply(left)?, right: apply(right)?, left_table, right_table, left_key, right_key, written }
+        }
+        Plan::SemiJoin { input, subquery, key, negated } => Plan::SemiJoin { input: apply(input)?, subquery, key, negated },
+        Plan::Sort { input, order_by } => Plan::Sort { input: apply(input)?, order_by },
+        Plan::Aggregate { input, items } => Plan::Aggregate { input: apply(input)?, items },
+        Plan::Project { input, items } => Plan::Project { input: apply(input)?, items },
+        Plan::Limit { input, limit, offset } => Plan::Limit { input: apply(input)?, limit, offset },
+    })
+}
+
+// `a AND b`
+fn and(a: Expr, b: Expr) -> Expr {
+    Expr::BinaryOp { left: Box::new(a), op: BinaryOperator::And, right: Box::new(b) }
+}
+
+// The conjuncts joined back up with AND, or None if there are none
+fn all(terms: Vec<Expr>) -> Option<Expr> {
+    terms.into_iter().reduce(and)
+}
+
+// Wraps `input` in a filter on `predicate`, merged into a filter already there
+fn filtered(input: Plan, predicate: Expr) -> Plan {
+    match input {
+        Plan::Filter { input, predicate: before } => Plan::Filter { input, predicate: and(before, predicate) },
+        input => Plan::Filter { input: Box::new(input), predicate },
+    }
+}
+
+// --- Constant folding ---
+
+// Folds the constant parts of filters and SELECT lists, and drops filters that are always true.
+fn fold(plan: Plan) -> Result<Plan, String> {
+    Ok(match map_inputs(plan, &mut fold)? {
+        Plan::Filter { input, predicate } => match simplify(fold_expr(predicate)) {
+            Expr::Value(SqlValue::Boolean(true)) => *input,
+            predicate => Plan::Filter { input, predicate },
+        },
+        Plan::Project { input, items } => Plan::Project { input, items: fold_items(items) },
+        Plan::Aggregate { input, items } => Plan::Aggregate { input, items: fold_items(items) },
+        plan => plan,
+    })
+}
+
+fn fold_items(items: Vec<Item>) -> Vec<Item> {
+    items.into_iter().map(|item| match item {
+        Item::Expr(header, expr) => Item::Expr(header, fold_expr(expr)),
+        item => item,
+    }).collect()
+}
+
+// Can `expr` be computed without a row, giving the same value every time?
+fn constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Value(_) | Expr::TypedString { .. } => true,
+        Expr::Nested(e) | Expr::UnaryOp { expr: e, .. } | Expr::Cast { expr: e, .. } | Expr::IsNull(e) | Expr::IsNotNull(e)
+        | Expr::Extract { expr: e, .. } => constant(e),
+        Expr::BinaryOp { left, right, .. } => constant(left) && constant(right),
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => constant(expr) && constant(pattern),
+        Expr::Between { expr, low, high, .. } => constant(expr) && constant(low) && constant(high),
+        Expr::InList { expr, list, .. } => constant(expr) && list.iter().all(constant),
+        Expr::Interval(iv) => constant(&iv.value),
+        Expr::Function(f) => {
+            let name = f.name.to_string().to_lowercase();
+            !VOLATILE.contains(&name.as_str()) && !aggregate::is_aggregate_name(&name) && f.over.is_none()
+                && f.args.iter().all(|a| matches!(a, FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) if constant(e)))
+        }
+        _ => false,
+    }
+}
+
+// The value of a constant expression as a literal, if it evaluates without error and its
+// literal evaluates back to the same value
+fn literal(expr: &Expr) -> Option<Expr> {
+    let value = eval_expr(expr, &BTreeMap::new()).ok()?;
+    let literal = parse_expr(&sql_literal(&value)).ok()?;
+    (eval_expr(&literal, &BTreeMap::new()).ok()? == value).then_some(literal)
+}
+
+fn fold_expr(expr: Expr) -> Expr {
+    if !matches!(expr, Expr::Value(_)) && constant(&expr)
+        && let Some(literal) = literal(&expr)
+    {
+        return literal;
+    }
+    let fold = |e: Box<Expr>| Box::new(fold_expr(*e));
+    match expr {
+        Expr::BinaryOp { left, op, right } => Expr::BinaryOp { left: fold(left), op, right: fold(right) },
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp { op, expr: fold(expr) },
+        Expr::Nested(inner) => Expr::Nested(fold(inner)),
+        Expr::Between { expr, negated, low, high } => Expr::Between { expr: fold(expr), negated, low: fold(low), high: fold(high) },
+        Expr::InList { expr, list, negated } => Expr::InList { expr: fold(expr), list: list.into_iter().map(fold_expr).collect(), negated },
+        Expr::InSubquery { expr, subquery, negated } => Expr::InSubquery { expr: fold(expr), subquery, negated },
+        expr => expr,
+    }
+}
+
+fn boolean(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Value(SqlValue::Boolean(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+// Drops TRUE and FALSE from the ANDs and ORs of a filter. Only for filters, where a term that
+// is not a boolean is read as one anyway.
+fn simplify(expr: Expr) -> Expr {
+    match expr {
+        Expr::BinaryOp { left, op: op @ (BinaryOperator::And | BinaryOperator::Or), right } => {
+            let (left, right) = (simplify(*left), simplify(*right));
+            let and = op == BinaryOperator::And;
+            match (boolean(&left), boolean(&right)) {
+                // x AND FALSE, x OR TRUE
+                (Some(b), _) | (_, Some(b)) if b != and => Expr::Value(SqlValue::Boolean(b)),
+                // TRUE AND x, FALSE OR x
+                (Some(_), _) => right,
+                (_, Some(_)) => left,
+                _ => Expr::BinaryOp { left: Box::new(left), op, right: Box::new(right) },
+            }
+        }
+        Expr::Nested(inner) => match simplify(*inner) {
+            value @ Expr::Value(_) => value,
+            inner => Expr::Nested(Box::new(inner)),
+        },
+        expr => expr,
+    }
+}
+
+// --- IN subqueries ---
+
+// WHERE ... AND x [NOT] IN (SELECT ...) AND ...: each such term becomes a semi-join (an
+// anti-join with NOT) against the subquery's rows, above a filter on the other terms. The
+// subquery is planned and rewritten on its own, once; it may not refer to the outer query.
+fn semi_joins(db: &Database, plan: Plan) -> Result<Plan, String> {
+    let (input, predicate) = match map_inputs(plan, &mut |p| semi_joins(db, p))? {
+        Plan::Filter { input, predicate } => (input, predicate),
+        plan => return Ok(plan),
+    };
+    let mut rest = Vec::new();
+    let mut subqueries = Vec::new();
+    for term in conjuncts(&predicate) {
+        match term {
+            Expr::InSubquery { expr, subquery, negated } => subqueries.push((expr, subquery, *negated)),
+            term => rest.push(term.clone()),
+        }
+    }
+    if subqueries.is_empty() {
+        return Ok(Plan::Filter { input, predicate });
+    }
+    let mut plan = match all(rest) {
+        Some(predicate) => Plan::Filter { input, predicate },
+        None => *input,
+    };
+    for (key, subquery, negated) in subqueries {
+        let subquery = rewrite(db, plan::build(subquery)?)?;
+        plan = Plan::SemiJoin { input: Box::new(plan), subquery: Box::new(subquery), key: (**key).clone(), negated };
+    }
+    Ok(plan)
+}
+
+// --- Filter pushdown ---
+
+// The columns `expr` reads, as (qualifier, column), or None if it has a part whose columns are
+// not known
+fn references(expr: &Expr, out: &mut Vec<(Option<String>, String)>) -> Option<()> {
+    match expr {
+        Expr::Identifier(ident) => out.push((None, ident.value.clone())),
+        Expr::CompoundIdentifier(idents) => {
+            let (last, qualifier) = idents.split_last()?;
+            out.push((Some(qualifier.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(".")), last.value.clone()));
+        }
+        Expr::Value(_) | Expr::TypedString { .. } => {}
+        Expr::Nested(e) | Expr::UnaryOp { expr: e, .. } | Expr::Cast { expr: e, .. } | Expr::IsNull(e) | Expr::IsNotNull(e)
+        | Expr::IsTrue(e) | Expr::IsFalse(e) | Expr::IsNotTrue(e) | Expr::IsNotFalse(e) | Expr::Extract { expr: e, .. } => references(e, out)?,
+        Expr::BinaryOp { left, right, .. } => {
+            references(left, out)?;
+            references(right, out)?;
+        }
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+            references(expr, out)?;
+            references(pattern, out)?;
+        }
+        Expr::Between { expr, low, high, .. } => {
+            for e in [expr, low, high] {
+                references(e, out)?;
+            }
+        }
+        Expr::InList { expr, list, .. } => {
+            references(expr, out)?;
+            for e in list {
+                references(e, out)?;
+            }
+        }
+        Expr::Interval(iv) => references(&iv.value, out)?,
+        Expr::Case { operand, conditions, results, else_result } => {
+            for e in operand.iter().chain(else_result).map(|e| &**e).chain(conditions).chain(results) {
+                references(e, out)?;
+            }
+        }
+        Expr::Function(f) => {
+            for arg in &f.args {
+                match arg {
+                    FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => references(e, out)?,
+                    // COUNT(*)
+                    FunctionArg::Unnamed(FunctionArgExpr::Wildcard) => {}
+                    _ => return None,
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+// The one relation of a join chain that every column of `expr` belongs to. A qualified column
+// names its relation; a bare one must be a column of exactly one table, and is only placed when
+// every relation is a table, whose columns are known. `tables` are the tables the relations
+// `names` read.
+fn only_relation(db: &Database, names: &[String], tables: &[Option<String>], expr: &Expr) -> Option<usize> {
+    let mut refs = Vec::new();
+    references(expr, &mut refs)?;
+    let tables = tables.iter().map(|t| t.as_ref().and_then(|t| db.tables.get(t))).collect::<Option<Vec<_>>>();
+    let mut found = None;
+    for (qualifier, col) in refs {
+        let owner = match qualifier {
+            Some(q) => names.iter().position(|name| *name == q)?,
+            None => {
+                let tables = tables.as_ref()?;
+                let mut owners = tables.iter().enumerate().filter(|(_, t)| t.columns.iter().any(|(c, _)| *c == col));
+                match (owners.next(), owners.next()) {
+                    (Some((i, _)), None) => i,
+                    _ => return None,
+                }
+            }
+        };
+        if found.is_some_and(|f| f != owner) {
+            return None;
+        }
+        found = Some(owner);
+    }
+    found
+}
+
+// What a stack of filters and semi-joins stands on
+fn bottom(plan: &mut Plan) -> &mut Plan {
+    match plan {
+        Plan::Filter { input, .. } | Plan::SemiJoin { input, .. } => bottom(input),
+        plan => plan,
+    }
+}
+
+// Moves the terms of a filter above a join chain that read only one of its relations into a
+// filter on that relation, so its rows are dropped before they are joined, and possibly never
+// read thanks to an index. Semi-joins whose key is a column of one relation move likewise.
+fn push_down(db: &Database, plan: Plan) -> Result<Plan, String> {
+    let mut plan = map_inputs(plan, &mut |p| push_down(db, p))?;
+    let (Plan::Filter { input, .. } | Plan::SemiJoin { input, .. }) = &mut plan else { return Ok(plan) };
+    let join = bottom(input);
+    if !matches!(join, Plan::Join { .. }) {
+        return Ok(plan);
+    }
+    let mut chain = Chain::new(std::mem::replace(join, Plan::Empty))?;
+    let (names, tables) = (chain.names(), chain.tables());
+    if names.iter().enumerate().any(|(i, name)| names[..i].contains(name)) {
+        *join = chain.into_plan();
+        return Ok(plan);
+    }
+    let mut place = |i: usize, wrap: &mut dyn FnMut(Plan) -> Plan| {
+        let leaf = std::mem::replace(&mut chain.relations[i].1, Plan::Empty);
+        chain.relations[i].1 = wrap(leaf);
+    };
+    let plan = match plan {
+        Plan::Filter { input, predicate } => {
+            let mut rest = Vec::new();
+            for term in conjuncts(&predicate) {
+                match only_relation(db, &names, &tables, term) {
+                    Some(i) => place(i, &mut |leaf| filtered(leaf, term.clone())),
+                    None => rest.push(term.clone()),
+                }
+            }
+            let mut input = *input;
+            *bottom(&mut input) = chain.into_plan();
+            match all(rest) {
+                Some(predicate) => Plan::Filter { input: Box::new(input), predicate },
+                None => input,
+            }
+        }
+        Plan::SemiJoin { input, subquery, key, negated } => {
+            let mut input = *input;
+            let Some(i) = only_relation(db, &names, &tables, &key) else {
+                *bottom(&mut input) = chain.into_plan();
+                return Ok(Plan::SemiJoin { input: Box::new(input), subquery, key, negated });
+            };
+            let mut semi = Some((subquery, key));
+            place(i, &mut |leaf| match semi.take() {
+                Some((subquery, key)) => Plan::SemiJoin { input: Box::new(leaf), subquery, key, negated },
+                None => leaf,
+            });
+            *bottom(&mut input) = chain.into_plan();
+            input
+        }
+        plan => plan,
+    };
+    Ok(plan)
+}
+
+// --- Join order ---
+
+// Is a relation of a join chain restricted by a filter or semi-join pushed onto it?
+fn restricted(plan: &Plan) -> bool {
+    !matches!(plan, Plan::Scan { .. })
+}
+
+// Starts a join chain from its first restricted relation, then joins one connected relation
+// at a time, restricted ones first, so few rows are carried through the joins. The physical
+// planner may still pick a cheaper order for chains of tables from their statistics.
+fn reorder(db: &Database, plan: Plan) -> Result<Plan, String> {
+    let plan = map_inputs(plan, &mut |p| reorder(db, p))?;
+    if !matches!(plan, Plan::Join { .. }) {
+        return Ok(plan);
+    }
+    let chain = Chain::new(plan)?;
+    let edges = chain.edges(db);
+    let start = chain.relations.iter().position(|(_, plan)| restricted(plan));
+    let Some(start) = start.filter(|_| chain.reorderable(&edges)) else { return Ok(chain.into_plan()) };
+    let mut order = vec![start];
+    while order.len() < chain.relations.len() {
+        let joined = |i: usize| order.contains(&i);
+        let connected: Vec<usize> = (0..chain.relations.len())
+            .filter(|&i| !joined(i) && edges.iter().any(|e| e.joins(i, joined)))
+            .collect();
+        let next = connected.iter().find(|&&i| restricted(&chain.relations[i].1)).or(connected.first());
+        let Some(&next) = next else { return Ok(chain.into_plan()) };
+        order.push(next);
+    }
+    Ok(chain.reorder(&edges, &order).into_plan())
+}
+
+// --- Column pruning ---
+
+// A query over one table, with no join and no *, scans only the columns it mentions.
+fn prune(db: &Database, mut plan: Plan) -> Result<Plan, String> {
+    let mut refs = Vec::new();
+    if mentions(&plan, &mut refs).is_some()
+        && let Some(Plan::Scan { table, columns }) = scan_below(&mut plan)
+        && let Some(t) = db.tables.get(table)
+    {
+        let used: Vec<String> = t.columns.iter().map(|(c, _)| c.clone()).filter(|c| refs.iter().any(|(_, r)| r == c)).collect();
+        *columns = (used.len() < t.columns.len()).then_some(used);
+    }
+    Ok(plan)
+}
+
+// The scan under a plan of filters, semi-joins, sorts, projections and limits
+fn scan_below(plan: &mut Plan) -> Option<&mut Plan> {
+    match plan {
+        Plan::Scan { .. } => Some(plan),
+        Plan::Filter { input, .. } | Plan::SemiJoin { input, .. } | Plan::Sort { input, .. }
+        | Plan::Aggregate { input, .. } | Plan::Project { input, .. } | Plan::Limit { input, .. } => scan_below(input),
+        _ => None,
+    }
+}
+
+// Every column the operators above the scan read, or None if they may read any
+fn mentions(plan: &Plan, out: &mut Vec<(Option<String>, String)>) -> Option<()> {
+    match plan {
+        Plan::Scan { .. } => {}
+        Plan::Filter { input, predicate } => {
+            references(predicate, out)?;
+            mentions(input, out)?;
+        }
+        Plan::SemiJoin { input, key, .. } => {
+            references(key, out)?;
+            mentions(input, out)?;
+        }
+        Plan::Sort { input, order_by } => {
+            for o in order_by {
+                references(&o.expr, out)?;
+            }
+            mentions(input, out)?;
+        }
+        Plan::Aggregate { input, items } | Plan::Project { input, items } => {
+            for item in items {
+                match item {
+                    Item::Wildcard => return None,
+                    Item::Column(col) => out.push((None, col.clone())),
+                    Item::Expr(_, expr) => references(expr, out)?,
+                }
+            }
+            mentions(input, out)?;
+        }
+        Plan::Limit { input, .. } => mentions(input, out)?,
+        _ => return None,
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{rows, run, setup};
+    use crate::{Database, Value};
+
+    // The rewritten logical plan EXPLAIN VERBOSE prints
+    fn rewritten(db: &mut Database, sql: &str) -> String {
+        let explained = run(db, &format!("EXPLAIN VERBOSE {}", sql)).unwrap();
+        let (_, rest) = explained.split_once("Rewritten plan:\n").unwrap();
+        rest.split("\nPhysical plan:").next().unwrap_or("").to_string()
+    }
+
+    #[test]
+    fn constants_fold_and_true_filters_disappear() {
+        let mut db = setup("CREATE TABLE a (id INTEGER PRIMARY KEY, x INTEGER)");
+        assert_eq!(rewritten(&mut db, "SELECT x FROM a WHERE x > length('abc') - 1"), "Project: x\n  -> Filter: x > 2\n    -> Scan: a [x]");
+        assert_eq!(rewritten(&mut db, "SELECT id FROM a WHERE 1 = 1 OR x > 0"), "Project: id\n  -> Scan: a [id]");
+        // Volatile calls are left to run
+        assert_eq!(rewritten(&mut db, "SELECT id FROM a WHERE x < random() + 1"), "Project: id\n  -> Filter: x < random() + 1\n    -> Scan: a");
+    }
+
+    #[test]
+    fn filters_move_onto_the_joined_table_they_read() {
+        let mut db = setup("
+            CREATE TABLE a (id INTEGER PRIMARY KEY, x INTEGER);
+            CREATE TABLE b (id INTEGER PRIMARY KEY, a_id INTEGER, y INTEGER);
+            CREATE TABLE c (id INTEGER PRIMARY KEY, b_id INTEGER);
+            INSERT INTO a VALUES (1, 1), (2, 2);
+            INSERT INTO b VALUES (10, 1, 6), (11, 2, 6), (12, 2, 7);
+            INSERT INTO c VALUES (100, 11);
+        ");
+        let sql = "SELECT * FROM a JOIN b ON a.id = b.a_id JOIN c ON c.b_id = b.id WHERE b.y = 2 * 3 AND a.x > 1 AND a.x + b.y > 0";
+        assert_eq!(rewritten(&mut db, sql), "\
+Project: *
+  -> Filter: a.x + b.y > 0
+    -> Inner Join: b.id = c.b_id
+      -> Inner Join: a.id = b.a_id
+        -> Filter: a.x > 1
+          -> Scan: a
+        -> Filter: b.y = 6
+          -> Scan: b
+      -> Scan: c");
+        let found = rows(&mut db, sql);
+        assert_eq!(found, vec![vec![2, 2, 11, 2, 6, 100, 11].into_iter().map(Value::Integer).collect::<Vec<_>>()]);
+    }
+
+    #[test]
+    fn joins_start_from_the_restricted_table() {
+        let mut db = setup("
+            CREATE TABLE a (id INTEGER PRIMARY KEY, x INTEGER);
+            CREATE TABLE b (id INTEGER PRIMARY KEY, a_id INTEGER, y INTEGER);
+            INSERT INTO a VALUES (1, 1), (2, 2);
+            INSERT INTO b VALUES (10, 1, 6), (11, 2, 6), (12, 2, 7);
+        ");
+        let sql = "SELECT * FROM a JOIN b ON a.id = b.a_id WHERE b.y = 7";
+        assert_eq!(rewritten(&mut db, sql), "\
+Project: *
+  -> Inner Join: b.a_id = a.id
+    -> Filter: b.y = 7
+      -> Scan: b
+    -> Scan: a");
+        // The columns still come out in the order the tables were written
+        assert_eq!(rows(&mut db, sql), vec![vec![2, 2, 12, 2, 7].into_iter().map(Value::Integer).collect::<Vec<_>>()]);
+    }
+
+    #[test]
+    fn in_subqueries_become_semi_joins() {
+        let mut db = setup("
+            CREATE TABLE a (id INTEGER PRIMARY KEY, x INTEGER);
+            CREATE TABLE b (id INTEGER PRIMARY KEY, a_id INTEGER);
+            INSERT INTO a VALUES (1, 1), (2, 2), (3, NULL);
+            INSERT INTO b VALUES (10, 1), (11, 2);
+        ");
+        assert!(rewritten(&mut db, "SELECT id FROM a WHERE id IN (SELECT a_id FROM b)").contains("Semi Join: id IN (subquery)"));
+        assert_eq!(rows(&mut db, "SELECT id FROM a WHERE id IN (SELECT a_id FROM b)"), vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]);
+        assert_eq!(rows(&mut db, "SELECT id FROM a WHERE id NOT IN (SELECT a_id FROM b)"), vec![vec![Value::Integer(3)]]);
+        // A NULL among the subquery's values makes NOT IN keep nothing
+        assert!(rows(&mut db, "SELECT id FROM b WHERE a_id NOT IN (SELECT x FROM a)").is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+// --- SCHEMAS AND ATTACHED DATABASES ---
+// CREATE SCHEMA makes a namespace: `sales.orders` is stored as the table "sales.orders".
+// ATTACH DATABASE 'file' AS alias merges another database file's tables and sequences in under
+// `alias.`, so they can be queried, joined and written like local ones. Saving writes them back
+// to their own file, and DETACH takes them out again.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path};
+
+use sqlparser::ast::ObjectName;
+
+use crate::sequence::{self, Sequence};
+use crate::{view, Database, Table};
+
+pub const MAIN: &str = "main";
+// Names the catalog uses; no schema or attached database may take them
+const RESERVED: [&str; 2] = [MAIN, "information_schema"];
+
+#[derive(Debug, Clone)]
+pub struct Attached {
+    pub path: String,
+    // The rest of the file (views, triggers, types), written back unchanged
+    pub rest: Database,
+}
+
+// The schema part of a stored name, if it has one.
+pub fn schema_of(name: &str) -> Option<&str> {
+    name.split_once('.').map(|(schema, _)| schema)
+}
+
+// A stored name as (schema, bare name).
+pub fn split(name: &str) -> (&str, &str) {
+    name.split_once('.').unwrap_or((MAIN, name))
+}
+
+// `sales.orders` can only be created once schema `sales` exists or a database is attached as `sales`.
+pub fn check_schema(db: &Database, name: &str) -> Result<(), String> {
+    match schema_of(name) {
+        Some(schema) if !db.schemas.contains(schema) && !db.attached.contains_key(schema) => {
+            Err(format!("Schema '{}' does not exist", schema))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Foreign keys cannot cross database files: the other file may not be attached next time.
+pub fn check_same_database(db: &Database, table: &str, other: &str) -> Result<(), String> {
+    let file_of = |name: &str| schema_of(name).filter(|s| db.attached.contains_key(*s)).map(str::to_string);
+    if file_of(table) != file_of(other) {
+        return Err(format!("'{}' and '{}' are in different database files", table, other));
+    }
+    Ok(())
+}
+
+fn check_new_name(db: &Database, name: &str) -> Result<(), String> {
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+        return Err(format!("'{}' is a reserved schema name", name));
+    }
+    if db.schemas.contains(name) {
+        return Err(format!("Schema '{}' already exists", name));
+    }
+    if db.attached.contains_key(name) {
+        return Err(format!("A database is already attached as '{}'", name));
+    }
+    Ok(())
+}
+
+pub fn create_schema(db: &mut Database, name: &str, if_not_exists: bool) -> Result<String, String> {
+    if if_not_exists && db.schemas.contains(name) {
+        return Ok(format!("Schema '{}' already exists, skipping", name));
+    }
+    check_new_name(db, name)?;
+    db.schemas.insert(name.to_string());
+    Ok(format!("Schema '{}' created", name))
+}
+
+// DROP SCHEMA. CASCADE also drops the tables, views, triggers and sequences in it.
+pub fn drop_schema(db: &mut Database, names: &[ObjectName], if_exists: bool, cascade: bool) -> Result<String, String> {
+    let mut dropped = Vec::new();
+    for name in names.iter().map(|n| n.to_string()) {
+        if db.attached.contains_key(&name) {
+            return Err(format!("'{}' is an attached database; use DETACH", name));
+        }
+        if !db.schemas.contains(&name) {
+            if if_exists {
+                continue;
+            }
+            return Err(format!("Schema '{}' not found", name));
+        }
+        let inside = |key: &str| schema_of(key) == Some(name.as_str());
+        let contents: Vec<String> = db.tables.keys().chain(db.views.keys()).filter(|k| inside(k)).cloned().collect();
+        if let Some(first) = contents.first().filter(|_| !cascade) {
+            return Err(format!("Cannot drop schema '{}': it contains '{}' (use CASCADE)", name, first));
+        }
+        // Nothing outside the schema may still depend on what is in it
+        for table in db.tables.values().filter(|t| !inside(&t.name)) {
+            if let Some(fk) = table.foreign_keys.iter().find(|fk| inside(&fk.ref_table)) {
+                return Err(format!("Cannot drop schema '{}': foreign key '{}' on '{}' references '{}'", name, fk.name, table.name, fk.ref_table));
+            }
+        }
+        for object in &contents {
+            if let Some(user) = view::dependents(db, object).into_iter().find(|v| !inside(v)) {
+                return Err(format!("Cannot drop schema '{}': view '{}' depends on '{}'", name, user, object));
+            }
+        }
+        db.tables.retain(|k, _| !inside(k));
+        db.views.retain(|k, _| !inside(k));
+        db.triggers.retain(|_, t| !inside(&t.table));
+        db.sequences.retain(|k, _| !inside(k));
+        db.schemas.remove(&name);
+        dropped.push(name);
+    }
+    if dropped.is_empty() {
+        return Ok("No schemas dropped".to_string());
+    }
+    Ok(format!("Dropped {}", dropped.iter().map(|n| format!("'{}'", n)).collect::<Vec<_>>().join(", ")))
+}
+
+// An ATTACH path as a plain relative path. Files stay inside the data directory (the one
+// holding mydb.json), so a client of the HTTP server cannot create or overwrite others.
+fn data_file(path: &str) -> Result<String, String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str().ok_or(format!("Invalid file name '{}'", path))?),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(format!("Cannot attach '{}': the file must be inside the data directory (a relative path without '..')", path));
+            }
+        }
+    }
+    if parts.is_empty() {
+        return Err(format!("Invalid file name '{}'", path));
+    }
+    Ok(parts.join("/"))
+}
+
+// ATTACH [DATABASE] 'file' AS alias. A file that does not exist yet starts out empty.
+pub fn attach(db: &mut Database, path: &str, alias: &str) -> Result<String, String> {
+    if db.transaction.is_some() {
+        return Err("Cannot ATTACH inside a transaction".to_string());
+    }
+    check_new_name(db, alias)?;
+    let path = data_file(path)?;
+    if path == crate::DB_FILE || db.attached.values().any(|a| a.path == path) {
+        return Err(format!("'{}' is already open", path));
+    }
+    let mut file = Database::load_from(&path).map_err(|e| format!("Cannot attach '{}': {}", path, e))?;
+    let tables = std::mem::take(&mut file.tables);
+    let sequences = std::mem::take(&mut file.sequences);
+    let count = tables.len();
+    let (tables, sequences) = requalify(tables, sequences, |name| format!("{}.{}", alias, name));
+    db.tables.extend(tables);
+    db.sequences.extend(sequences);
+    db.attached.insert(alias.to_string(), Attached { path: path.clone(), rest: file });
+    Ok(format!("Database '{}' attached as '{}' ({} tables)", path, alias, count))
+}
+
+// DETACH [DATABASE] alias: writes the database back to its file and removes its tables.
+pub fn detach(db: &mut Database, alias: &str) -> Result<String, String> {
+    if db.transaction.is_some() {
+        return Err("Cannot DETACH inside a transaction".to_string());
+    }
+    let (path, file) = extract(db, alias).ok_or(format!("No database attached as '{}'", alias))?;
+    file.write_to(&path).map_err(|e| format!("Cannot save '{}': {}", path, e))?;
+    Ok(format!("Database '{}' detached", alias))
+}
+
+// `db` without its attached databases, and each attached database as the file it came from.
+pub fn split_attached(db: &Database) -> (Database, Vec<(String, Database)>) {
+    let mut main = db.clone();
+    let aliases: Vec<String> = main.attached.keys().cloned().collect();
+    let files = aliases.iter().filter_map(|alias| extract(&mut main, alias)).collect();
+    (main, files)
+}
+
+// Takes an attached database's tables and sequences out of `db`, under their names in its file.
+fn extract(db: &mut Database, alias: &str) -> Option<(String, Database)> {
+    let attached = db.attached.remove(alias)?;
+    let prefix = format!("{}.", alias);
+    let keys: Vec<String> = db.tables.keys().filter(|k| k.starts_with(&prefix)).cloned().collect();
+    let tables = keys.into_iter().filter_map(|k| db.tables.remove_entry(&k)).collect();
+    let keys: Vec<String> = db.sequences.keys().filter(|k| k.starts_with(&prefix)).cloned().collect();
+    let sequences = keys.into_iter().filter_map(|k| db.sequences.remove_entry(&k)).collect();
+    let (tables, sequences) = requalify(tables, sequences, |name| name.strip_prefix(&prefix).unwrap_or(name).to_string());
+    let mut file = attached.rest;
+    file.tables = tables;
+    file.sequences = sequences;
+    Some((attached.path, file))
+}
+
+// Renames tables and sequences, and the references between them (foreign keys, nextval() defaults).
+fn requalify(tables: HashMap<String, Table>, sequences: BTreeMap<String, Sequence>, rename: impl Fn(&str) -> String)
+    -> (HashMap<String, Table>, BTreeMap<String, Sequence>) {
+    let renamed: Vec<(String, String)> = sequences.keys().map(|s| (format!("'{}'", s), format!("'{}'", rename(s)))).collect();
+    let tables = tables.into_values().map(|mut table| {
+        table.name = rename(&table.name);
+        for fk in &mut table.foreign_keys {
+            fk.ref_table = rename(&fk.ref_table);
+        }
+        for sql in table.defaults.values_mut().filter(|sql| sequence::mentioned(sql)) {
+            for (old, new) in &renamed {
+                *sql = sql.replace(old, new);
+            }
+        }
+        (table.name.clone(), table)
+    }).collect();
+    let sequences = sequences.into_values().map(|mut seq| {
+        seq.name = rename(&seq.name);
+        seq.owned_by = seq.owned_by.map(|owner| rename(&owner));
+        (seq.name.clone(), seq)
+    }).collect();
+    (tables, sequences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{rows, run, setup};
+    use crate::Value;
+
+    #[test]
+    fn attach_paths_stay_in_the_data_directory() {
+        assert_eq!(data_file("archive.json"), Ok("archive.json".to_string()));
+        assert_eq!(data_file("./old/archive.json"), Ok("old/archive.json".to_string()));
+        assert!(data_file("/etc/passwd").is_err());
+        assert!(data_file("../mydb.json").is_err());
+        assert!(data_file("old/../../x.json").is_err());
+        assert!(data_file("").is_err());
+        // ./mydb.json is the main file under another spelling
+        let mut db = Database::new();
+        assert_eq!(run(&mut db, "ATTACH DATABASE './mydb.json' AS other"), Err("'mydb.json' is already open".to_string()));
+        assert!(run(&mut db, "ATTACH DATABASE '/tmp/x.json' AS other").is_err());
+    }
+
+    #[test]
+    fn attached_tables_live_under_the_alias() {
+        let mut db = setup("ATTACH DATABASE 'attach-test-never-saved.json' AS archive; CREATE TABLE archive.orders (id INTEGER PRIMARY KEY)");
+        run(&mut db, "INSERT INTO archive.orders VALUES (7)").unwrap();
+        assert_eq!(rows(&mut db, "SELECT id FROM archive.orders"), vec![vec![Value::Integer(7)]]);
+        let (main, files) = split_attached(&db);
+        assert!(main.tables.is_empty());
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "attach-test-never-saved.json");
+        assert!(files[0].1.tables.contains_key("orders"));
+    }
+
+    #[test]
+    fn drop_schema_needs_cascade_for_contents() {
+        let mut db = setup("CREATE SCHEMA sales; CREATE TABLE sales.orders (id INTEGER PRIMARY KEY)");
+        assert!(run(&mut db, "CREATE TABLE hr.people (id INTEGER PRIMARY KEY)").is_err());
+        assert!(run(&mut db, "DROP SCHEMA sales").is_err());
+        run(&mut db, "DROP SCHEMA sales CASCADE").unwrap();
+        assert!(db.tables.is_empty() && db.schemas.is_empty());
+    }
+}
@@ -0,0 +1,316 @@
+// --- SEQUENCES ---
+// CREATE SEQUENCE name [AS type] [INCREMENT BY n] [MINVALUE n] [MAXVALUE n] [START WITH n] [[NO] CYCLE],
+// read with nextval('name'), currval('name') and setval('name', n [, is_called]). SERIAL and
+// GENERATED AS IDENTITY columns get a sequence of their own and use nextval() as their DEFAULT.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{Expr, FunctionArg, FunctionArgExpr, Ident, MinMaxValue, ObjectName, Query, SelectItem, SequenceOptions, SetExpr};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+
+use crate::expr::{eval_expr, parse_expr, sql_literal};
+use crate::types::ColumnType;
+use crate::{object_name, Database, Value};
+
+const FUNCTIONS: [&str; 3] = ["nextval", "currval", "setval"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Sequence {
+    pub name: String,
+    pub increment: i64,
+    pub min: i64,
+    pub max: i64,
+    pub cycle: bool,
+    // As in PostgreSQL: the last value handed out, or the start value while `is_called` is false
+    pub last_value: i64,
+    pub is_called: bool,
+    // "table.column" for the sequence behind a SERIAL or IDENTITY column
+    #[serde(default)]
+    pub owned_by: Option<String>,
+}
+
+impl Sequence {
+    // `data_type` bounds the values (SMALLINT, INT or BIGINT, the default).
+    pub fn new(name: &str, data_type: Option<&ColumnType>, options: &[SequenceOptions]) -> Result<Self, String> {
+        let (type_min, type_max) = match data_type {
+            Some(ColumnType::SmallInt) => (i16::MIN as i64, i16::MAX as i64),
+            Some(ColumnType::Integer) => (i32::MIN as i64, i32::MAX as i64),
+            Some(ColumnType::BigInt) | None => (i64::MIN, i64::MAX),
+            Some(other) => return Err(format!("Sequence type must be SMALLINT, INT or BIGINT, not {}", other)),
+        };
+        let number = |expr: &Expr| match eval_expr(expr, &BTreeMap::new())? {
+            Value::Integer(n) if (type_min..=type_max).contains(&n) => Ok(n),
+            other => Err(format!("Invalid value {:?} for sequence '{}'", other, name)),
+        };
+        let (mut increment, mut min, mut max, mut start, mut cycle) = (1, None, None, None, false);
+        for option in options {
+            match option {
+                SequenceOptions::IncrementBy(expr, _) => increment = number(expr)?,
+                SequenceOptions::MinValue(MinMaxValue::Some(expr)) => min = Some(number(expr)?),
+                SequenceOptions::MaxValue(MinMaxValue::Some(expr)) => max = Some(number(expr)?),
+                SequenceOptions::MinValue(_) | SequenceOptions::MaxValue(_) | SequenceOptions::Cache(_) => {}
+                SequenceOptions::StartWith(expr, _) => start = Some(number(expr)?),
+                // Cycle(true) is NO CYCLE
+                SequenceOptions::Cycle(no) => cycle = !no,
+            }
+        }
+        if increment == 0 {
+            return Err(format!("INCREMENT of sequence '{}' must not be zero", name));
+        }
+        // Ascending sequences count up from 1, descending ones down from -1
+        let min = min.unwrap_or(if increment > 0 { 1 } else { type_min });
+        let max = max.unwrap_or(if increment > 0 { type_max } else { -1 });
+        if min >= max {
+            return Err(format!("MINVALUE ({}) must be less than MAXVALUE ({}) for sequence '{}'", min, max, name));
+        }
+        let start = start.unwrap_or(if increment > 0 { min } else { max });
+        if !(min..=max).contains(&start) {
+            return Err(format!("START value ({}) must be between MINVALUE ({}) and MAXVALUE ({}) for sequence '{}'", start, min, max, name));
+        }
+        Ok(Sequence { name: name.to_string(), increment, min, max, cycle, last_value: start, is_called: false, owned_by: None })
+    }
+
+    pub fn next(&mut self) -> Result<i64, String> {
+        if !self.is_called {
+            self.is_called = true;
+            return Ok(self.last_value);
+        }
+        let next = match self.last_value.checked_add(self.increment).filter(|v| (self.min..=self.max).contains(v)) {
+            Some(v) => v,
+            None if self.cycle => if self.increment > 0 { self.min } else { self.max },
+            None => {
+                let (which, bound) = if self.increment > 0 { ("maximum", self.max) } else { ("minimum", self.min) };
+                return Err(format!("nextval: reached {} value of sequence '{}' ({})", which, self.name, bound));
+            }
+        };
+        self.last_value = next;
+        Ok(next)
+    }
+
+    fn current(&self) -> Result<i64, String> {
+        if !self.is_called {
+            return Err(format!("currval of sequence '{}' is not yet defined; call nextval first", self.name));
+        }
+        Ok(self.last_value)
+    }
+
+    // setval(n) makes the next nextval return n + increment; setval(n, false) makes it return n.
+    fn set(&mut self, value: i64, is_called: bool) -> Result<i64, String> {
+        if !(self.min..=self.max).contains(&value) {
+            return Err(format!("setval: value {} is out of bounds for sequence '{}' ({}..{})", value, self.name, self.min, self.max));
+        }
+        self.last_value = value;
+        self.is_called = is_called;
+        Ok(value)
+    }
+}
+
+// Name of the sequence created for a SERIAL or IDENTITY column, as in PostgreSQL.
+pub fn owned_name(table: &str, column: &str) -> String {
+    format!("{}_{}_seq", table, column)
+}
+
+pub fn create(db: &mut Database, name: &str, if_not_exists: bool, data_type: Option<&ColumnType>, options: &[SequenceOptions]) -> Result<String, String> {
+    if db.sequences.contains_key(name) {
+        return if if_not_exists {
+            Ok(format!("Sequence '{}' already exists, skipping", name))
+        } else {
+            Err(format!("Sequence '{}' already exists", name))
+        };
+    }
+    let sequence = Sequence::new(name, data_type, options)?;
+    db.sequences.insert(name.to_string(), sequence);
+    Ok(format!("Sequence '{}' created", name))
+}
+
+// DROP SEQUENCE. A sequence still used by a column DEFAULT needs CASCADE, which removes the DEFAULT.
+pub fn drop(db: &mut Database, names: &[ObjectName], if_exists: bool, cascade: bool) -> Result<String, String> {
+    let mut dropped = Vec::new();
+    for name in names.iter().map(object_name) {
+        if !db.sequences.contains_key(&name) {
+            if if_exists {
+                continue;
+            }
+            return Err(format!("Sequence '{}' not found", name));
+        }
+        let users: Vec<(String, String)> = db.tables.values()
+            .flat_map(|t| t.defaults.iter().filter(|(_, sql)| calls(sql, &name)).map(|(col, _)| (t.name.clone(), col.clone())))
+            .collect();
+        if let Some((table, col)) = users.first().filter(|_| !cascade) {
+            return Err(format!("Cannot drop sequence '{}': column {}.{} uses it (use CASCADE)", name, table, col));
+        }
+        for (table, col) in users {
+            if let Some(t) = db.tables.get_mut(&table) {
+                t.defaults.remove(&col);
+                t.identity_always.retain(|c| *c != col);
+            }
+        }
+        db.sequences.remove(&name);
+        dropped.push(name);
+    }
+    if dropped.is_empty() {
+        return Ok("No sequences dropped".to_string());
+    }
+    Ok(format!("Dropped {}", dropped.iter().map(|n| format!("'{}'", n)).collect::<Vec<_>>().join(", ")))
+}
+
+// Could `sql` call a sequence function? A cheap test before tokenizing.
+pub fn mentioned(sql: &str) -> bool {
+    let lower = sql.to_lowercase();
+    FUNCTIONS.iter().any(|f| lower.contains(f))
+}
+
+// Does `sql` call a sequence function on `name`?
+fn calls(sql: &str, name: &str) -> bool {
+    mentioned(sql) && sql.contains(&format!("'{}'", name.replace('\'', "''")))
+}
+
+// Replaces nextval('s'), currval('s') and setval('s', n [, is_called]) in `sql` with the values
+// they return, left to right, so each call in a multi-row VALUES list gets its own value.
+pub fn bind(db: &mut Database, sql: &str) -> Result<String, String> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql).tokenize().map_err(|e| e.to_string())?;
+    let mut out = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let (Token::Word(word), Some(Token::LParen)) = (&tokens[i], tokens.get(i + 1))
+            && word.quote_style.is_none()
+            && let Some(func) = FUNCTIONS.into_iter().find(|f| word.value.eq_ignore_ascii_case(f))
+            && let Some(close) = closing_paren(&tokens, i + 1)
+        {
+            // Arguments may themselves call sequence functions
+            let args = bind(db, &tokens[i + 2..close].iter().map(token_sql).collect::<String>())?;
+            let value = call(db, func, &args)?;
+            out.push_str(&format!("({})", sql_literal(&value)));
+            i = close + 1;
+            continue;
+        }
+        out.push_str(&token_sql(&tokens[i]));
+        i += 1;
+    }
+    Ok(out)
+}
+
+// An expression with its sequence calls replaced by their values.
+pub fn bind_expr<'a>(db: &mut Database, expr: &'a Expr) -> Result<Cow<'a, Expr>, String> {
+    let sql = expr.to_string();
+    if !mentioned(&sql) {
+        return Ok(Cow::Borrowed(expr));
+    }
+    Ok(Cow::Owned(parse_expr(&bind(db, &sql)?)?))
+}
+
+// As bind_expr, for a whole SELECT. Each call is made once, not once per row.
+pub fn bind_query<'a>(db: &mut Database, query: &'a Query) -> Result<Cow<'a, Query>, String> {
+    if !mentioned(&query.to_string()) {
+        return Ok(Cow::Borrowed(query));
+    }
+    // Keep `nextval('s')` as the column header rather than the value it was replaced with
+    let mut query = query.clone();
+    if let SetExpr::Select(select) = &mut *query.body {
+        for item in &mut select.projection {
+            if let SelectItem::UnnamedExpr(expr) = item
+                && mentioned(&expr.to_string())
+            {
+                let alias = Ident::with_quote('"', expr.to_string());
+                *item = SelectItem::ExprWithAlias { expr: expr.clone(), alias };
+            }
+        }
+    }
+    let bound = bind(db, &query.to_string())?;
+    Parser::new(&GenericDialect {}).try_with_sql(&bound)
+        .and_then(|mut p| p.parse_query())
+        .map(Cow::Owned)
+        .map_err(|e| e.to_string())
+}
+
+fn call(db: &mut Database, func: &str, args: &str) -> Result<Value, String> {
+    let values = match parse_expr(&format!("{}({})", func, args))? {
+        Expr::Function(f) => f.args.iter().map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => eval_expr(e, &BTreeMap::new()),
+            _ => Err(format!("Unsupported argument to {}()", func)),
+        }).collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(format!("Invalid call to {}()", func)),
+    };
+    let Some((Value::Text(name), rest)) = values.split_first() else {
+        return Err(format!("{}() expects a sequence name", func));
+    };
+    let sequence = db.sequences.get_mut(name).ok_or(format!("Sequence '{}' not found", name))?;
+    let value = match (func, rest) {
+        ("nextval", []) => sequence.next()?,
+        ("currval", []) => sequence.current()?,
+        ("setval", [Value::Integer(n)]) => sequence.set(*n, true)?,
+        ("setval", [Value::Integer(n), Value::Bool(is_called)]) => sequence.set(*n, *is_called)?,
+        ("setval", _) => return Err("setval() expects a sequence name, a value and an optional is_called flag".to_string()),
+        _ => return Err(format!("{}() expects a sequence name", func)),
+    };
+    Ok(Value::Integer(value))
+}
+
+// Index of the parenthesis closing the one at `open`.
+pub fn closing_paren(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Token's Display does not re-escape quotes
+pub fn token_sql(token: &Token) -> String {
+    match token {
+        Token::SingleQuotedString(s) => format!("'{}'", s.replace('\'', "''")),
+        token => token.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{rows, run, setup};
+    use crate::Value;
+
+    fn one(i: i64) -> Vec<Vec<Value>> {
+        vec![vec![Value::Integer(i)]]
+    }
+
+    #[test]
+    fn sequences_step_cycle_and_stop_at_their_bounds() {
+        let mut db = setup("
+            CREATE SEQUENCE s INCREMENT BY 5 MINVALUE 1 MAXVALUE 11 START WITH 1 CYCLE;
+            CREATE SEQUENCE t AS SMALLINT START 32767;
+        ");
+        for expected in [1, 6, 11, 1] {
+            assert_eq!(rows(&mut db, "SELECT nextval('s')"), one(expected));
+        }
+        assert_eq!(rows(&mut db, "SELECT currval('s')"), one(1));
+        rows(&mut db, "SELECT setval('s', 6, false)");
+        assert_eq!(rows(&mut db, "SELECT nextval('s')"), one(6));
+        assert_eq!(rows(&mut db, "SELECT nextval('t')"), one(32767));
+        assert_eq!(run(&mut db, "SELECT nextval('t')"), Err("nextval: reached maximum value of sequence 't' (32767)".to_string()));
+    }
+
+    #[test]
+    fn serial_and_identity_columns_number_rows() {
+        let mut db = setup("
+            CREATE TABLE o (id SERIAL PRIMARY KEY, x TEXT);
+            CREATE TABLE q (id INT GENERATED ALWAYS AS IDENTITY (START WITH 100), x TEXT);
+            INSERT INTO o (x) VALUES ('a'), ('b');
+        ");
+        assert_eq!(rows(&mut db, "SELECT id FROM o"), vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]);
+        assert_eq!(run(&mut db, "INSERT INTO q VALUES (1, 'a')"), Err("Cannot insert into column 'id': it is GENERATED ALWAYS AS IDENTITY".to_string()));
+        run(&mut db, "INSERT INTO q VALUES (DEFAULT, 'a')").unwrap();
+        assert_eq!(rows(&mut db, "SELECT id FROM q"), one(100));
+        assert_eq!(rows(&mut db, "SELECT currval('o_id_seq')"), one(2));
+    }
+}
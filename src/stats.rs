@@ -0,0 +1,344 @@
+// --- STATISTICS ---
+// ANALYZE [t, ...] reads every row of the named tables (all tables if none are named) and
+// records, per column, how many values are NULL, how many distinct values there are, and an
+// equal-depth histogram: the values at evenly spaced ranks, so the share of rows below a value
+// can be read off by finding where it falls. The figures are saved with the table, listed in the
+// sqlite_stat catalog relation, and used by the planner to estimate how many rows a condition
+// keeps. They are not kept current by writes; run ANALYZE again after large changes. Without
+// them the planner falls back to fixed guesses.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{BinaryOperator, Expr, UnaryOperator, Value as SqlValue};
+
+use crate::expr::{as_f64, compare_values, eval_expr};
+use crate::index::{flip, Key};
+use crate::{Database, Table, Value};
+
+// Histogram buckets per column
+const BUCKETS: usize = 10;
+// Guesses for conditions the statistics cannot answer, as in PostgreSQL
+pub const DEFAULT_EQ: f64 = 0.005;
+pub const DEFAULT_RANGE: f64 = 1.0 / 3.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TableStats {
+    pub rows: u64,
+    pub columns: BTreeMap<String, ColumnStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ColumnStats {
+    pub nulls: u64,
+    pub distinct: u64,
+    // BUCKETS + 1 bounds (fewer for a column with few values), smallest first; consecutive
+    // bounds hold equal shares of the non-NULL values between them
+    pub histogram: Vec<Value>,
+}
+
+// ANALYZE [t, ...]
+pub fn analyze(db: &mut Database, names: &[String]) -> Result<String, String> {
+    let mut names = names.to_vec();
+    if names.is_empty() {
+        names = db.tables.keys().cloned().collect();
+        names.sort();
+    }
+    for name in &names {
+        let table = db.tables.get(name).ok_or(format!("Table '{}' not found", name))?;
+        let stats = collect(table)?;
+        if let Some(table) = db.tables.get_mut(name) {
+            table.stats = Some(stats);
+        }
+    }
+    Ok(format!("Analyzed {} table{}", names.len(), if names.len() == 1 { "" } else { "s" }))
+}
+
+fn collect(table: &Table) -> Result<TableStats, String> {
+    let mut values: BTreeMap<&str, Vec<Key>> = table.columns.iter().map(|(c, _)| (c.as_str(), Vec::new())).collect();
+    let mut nulls: BTreeMap<&str, u64> = BTreeMap::new();
+    for row in table.data.values() {
+        let row = table.read_row(row)?;
+        for (col, column_values) in values.iter_mut() {
+            match row.data.get(*col) {
+                None | Some(Value::Null) => *nulls.entry(col).or_default() += 1,
+                Some(value) => column_values.push(Key(vec![value.clone()])),
+            }
+        }
+    }
+    let columns = values.into_iter().map(|(col, mut sorted)| {
+        sorted.sort();
+        let mut distinct = sorted.len() as u64;
+        for pair in sorted.windows(2) {
+            if pair[0] == pair[1] {
+                distinct -= 1;
+            }
+        }
+        let buckets = BUCKETS.min(sorted.len().saturating_sub(1));
+        let histogram = match buckets {
+            0 => sorted.first().map(|k| k.0[0].clone()).into_iter().collect(),
+            _ => (0..=buckets).map(|i| sorted[i * (sorted.len() - 1) / buckets].0[0].clone()).collect(),
+        };
+        let stats = ColumnStats { nulls: nulls.get(col).copied().unwrap_or(0), distinct, histogram };
+        (col.to_string(), stats)
+    }).collect();
+    Ok(TableStats { rows: table.data.len() as u64, columns })
+}
+
+// The rows of sqlite_stat: one per analyzed column.
+pub fn catalog_rows(db: &Database) -> Vec<Vec<Value>> {
+    let mut tables: Vec<&Table> = db.tables.values().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut rows = Vec::new();
+    for table in tables {
+        let Some(stats) = &table.stats else { continue };
+        for (col, _) in &table.columns {
+            let Some(column) = stats.columns.get(col) else { continue };
+            rows.push(vec![
+                Value::Text(table.name.clone()),
+                Value::Text(col.clone()),
+                Value::Integer(stats.rows as i64),
+                Value::Integer(column.nulls as i64),
+                Value::Integer(column.distinct as i64),
+                Value::Array(column.histogram.clone()),
+            ]);
+        }
+    }
+    rows
+}
+
+fn column<'a>(table: &'a Table, col: &str) -> Option<&'a ColumnStats> {
+    table.stats.as_ref()?.columns.get(col)
+}
+
+// Share of a table's rows where `col` is NULL, if ANALYZE has looked.
+fn null_fraction(table: &Table, col: &str) -> Option<f64> {
+    let stats = table.stats.as_ref()?;
+    let column = stats.columns.get(col)?;
+    Some(if stats.rows == 0 { 0.0 } else { column.nulls as f64 / stats.rows as f64 })
+}
+
+// How many different non-NULL values `col` holds, if known: from ANALYZE, or because a
+// single-column unique index makes every value different.
+pub fn distinct(table: &Table, col: &str) -> Option<f64> {
+    if let Some(column) = column(table, col) {
+        return Some(column.distinct.max(1) as f64);
+    }
+    let unique = table.indexes.iter().any(|i| i.unique && i.predicate.is_none() && i.columns == [col]);
+    unique.then_some(table.data.len().max(1) as f64)
+}
+
+// Share of rows where `col = value`.
+pub fn eq_fraction(table: &Table, col: &str, value: &Value) -> f64 {
+    if *value == Value::Null {
+        return 0.0;
+    }
+    let Some(distinct) = distinct(table, col) else { return DEFAULT_EQ };
+    let non_null = 1.0 - null_fraction(table, col).unwrap_or(0.0);
+    // A value outside the histogram's range is probably not there at all
+    if let Some(column) = column(table, col)
+        && let (Some(first), Some(last)) = (column.histogram.first(), column.histogram.last())
+        && (compare(value, first) == Ordering::Less || compare(value, last) == Ordering::Greater)
+    {
+        return 0.0;
+    }
+    non_null / distinct
+}
+
+// Share of rows whose `col` lies between the bounds.
+pub fn range_fraction(table: &Table, col: &str, lower: Bound<&Value>, upper: Bound<&Value>) -> f64 {
+    let Some(column) = column(table, col) else {
+        let sides = [&lower, &upper].iter().filter(|b| !matches!(b, Bound::Unbounded)).count();
+        return DEFAULT_RANGE.powi(sides as i32);
+    };
+    let non_null = 1.0 - null_fraction(table, col).unwrap_or(0.0);
+    let low = match lower {
+        Bound::Unbounded => 0.0,
+        Bound::Included(v) => below(column, v, false),
+        Bound::Excluded(v) => below(column, v, true),
+    };
+    let high = match upper {
+        Bound::Unbounded => 1.0,
+        Bound::Included(v) => below(column, v, true),
+        Bound::Excluded(v) => below(column, v, false),
+    };
+    (high - low).max(0.0) * non_null
+}
+
+// Share of a column's non-NULL values below `value` (or at most `value` when `inclusive`),
+// interpolating within the bucket it falls in when the values are numbers.
+fn below(column: &ColumnStats, value: &Value, inclusive: bool) -> f64 {
+    let bounds = &column.histogram;
+    let Some(first) = bounds.first() else { return 0.0 };
+    if bounds.len() == 1 {
+        return match compare(value, first) {
+            Ordering::Less => 0.0,
+            Ordering::Equal if !inclusive => 0.0,
+            _ => 1.0,
+        };
+    }
+    let buckets = (bounds.len() - 1) as f64;
+    // The first bound above `value` (or at `value`, when not inclusive)
+    let upper = bounds.iter().position(|b| match compare(value, b) {
+        Ordering::Less => true,
+        Ordering::Equal => !inclusive,
+        Ordering::Greater => false,
+    });
+    match upper {
+        Some(0) => 0.0,
+        None => 1.0,
+        Some(i) => {
+            let (lo, hi) = (&bounds[i - 1], &bounds[i]);
+            let within = match (as_f64(lo), as_f64(hi), as_f64(value)) {
+                (Some(lo), Some(hi), Some(v)) if hi > lo => ((v - lo) / (hi - lo)).clamp(0.0, 1.0),
+                _ => 0.5,
+            };
+            ((i - 1) as f64 + within) / buckets
+        }
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Ordering {
+    compare_values(a, b).unwrap_or(Ordering::Equal)
+}
+
+// The table (among `scope`) and column an expression names, if it is a column.
+fn resolve<'a>(db: &'a Database, scope: &[&str], expr: &Expr) -> Option<(&'a Table, String)> {
+    let (qualifier, col) = match expr {
+        Expr::Identifier(ident) => (None, ident.value.clone()),
+        Expr::CompoundIdentifier(idents) => {
+            let (last, qualifier) = idents.split_last()?;
+            (Some(qualifier.iter().map(|i| i.value.as_str()).collect::<Vec<_>>().join(".")), last.value.clone())
+        }
+        Expr::Nested(inner) => return resolve(db, scope, inner),
+        _ => return None,
+    };
+    let table = scope.iter()
+        .filter(|name| qualifier.as_deref().is_none_or(|q| q == **name))
+        .filter_map(|name| db.tables.get(*name))
+        .find(|table| table.columns.iter().any(|(c, _)| *c == col))?;
+    Some((table, col))
+}
+
+// How many different values `expr` takes over the tables in `scope`, if it is a column whose
+// count is known.
+pub fn key_distinct(db: &Database, scope: &[&str], expr: &Expr) -> Option<f64> {
+    let (table, col) = resolve(db, scope, expr)?;
+    distinct(table, &col)
+}
+
+fn constant(expr: &Expr) -> Option<Value> {
+    eval_expr(expr, &BTreeMap::new()).ok()
+}
+
+// Estimated share of rows from the tables in `scope` for which `expr` is true. Terms are taken
+// to be independent of each other.
+pub fn selectivity(db: &Database, scope: &[&str], expr: &Expr) -> f64 {
+    let estimate = match expr {
+        Expr::Nested(inner) => selectivity(db, scope, inner),
+        // WHERE false, or a condition folded to a constant
+        Expr::Value(SqlValue::Boolean(b)) => if *b { 1.0 } else { 0.0 },
+        Expr::Value(SqlValue::Null) => 0.0,
+        Expr::BinaryOp { left, op: BinaryOperator::And, right } => selectivity(db, scope, left) * selectivity(db, scope, right),
+        Expr::BinaryOp { left, op: BinaryOperator::Or, right } => {
+            let (a, b) = (selectivity(db, scope, left), selectivity(db, scope, right));
+            a + b - a * b
+        }
+        Expr::UnaryOp { op: UnaryOperator::Not, expr } => 1.0 - selectivity(db, scope, expr),
+        Expr::BinaryOp { left, op, right } => comparison(db, scope, left, op, right)
+            .or_else(|| comparison(db, scope, right, &flip(op)?, left))
+            .unwrap_or(DEFAULT_RANGE),
+        Expr::Between { expr, negated, low, high } => {
+            let fraction = match (resolve(db, scope, expr), constant(low), constant(high)) {
+                (Some((table, col)), Some(low), Some(high)) => range_fraction(table, &col, Bound::Included(&low), Bound::Included(&high)),
+                _ => DEFAULT_RANGE * DEFAULT_RANGE,
+            };
+            if *negated { 1.0 - fraction } else { fraction }
+        }
+        Expr::InList { expr, list, negated } => {
+            let fraction = match resolve(db, scope, expr) {
+                Some((table, col)) => list.iter().map(|item| match constant(item) {
+                    Some(value) => eq_fraction(table, &col, &value),
+                    None => DEFAULT_EQ,
+                }).sum(),
+                None => DEFAULT_EQ * list.len() as f64,
+            };
+            let fraction = fraction.min(1.0);
+            if *negated { 1.0 - fraction } else { fraction }
+        }
+        Expr::IsNull(inner) | Expr::IsNotNull(inner) => {
+            let nulls = resolve(db, scope, inner).and_then(|(table, col)| null_fraction(table, &col)).unwrap_or(DEFAULT_EQ);
+            if matches!(expr, Expr::IsNull(_)) { nulls } else { 1.0 - nulls }
+        }
+        _ => DEFAULT_RANGE,
+    };
+    estimate.clamp(0.0, 1.0)
+}
+
+// `col op constant`, or `a.x = b.y` between two columns
+fn comparison(db: &Database, scope: &[&str], left: &Expr, op: &BinaryOperator, right: &Expr) -> Option<f64> {
+    let (table, col) = resolve(db, scope, left)?;
+    if let Some((other, other_col)) = resolve(db, scope, right) {
+        if *op != BinaryOperator::Eq {
+            return None;
+        }
+        return match (distinct(table, &col), distinct(other, &other_col)) {
+            (Some(a), Some(b)) => Some(1.0 / a.max(b)),
+            (Some(n), None) | (None, Some(n)) => Some(1.0 / n),
+            (None, None) => None,
+        };
+    }
+    let value = constant(right)?;
+    Some(match op {
+        BinaryOperator::Eq => eq_fraction(table, &col, &value),
+        BinaryOperator::NotEq => 1.0 - eq_fraction(table, &col, &value) - null_fraction(table, &col).unwrap_or(0.0),
+        BinaryOperator::Lt => range_fraction(table, &col, Bound::Unbounded, Bound::Excluded(&value)),
+        BinaryOperator::LtEq => range_fraction(table, &col, Bound::Unbounded, Bound::Included(&value)),
+        BinaryOperator::Gt => range_fraction(table, &col, Bound::Excluded(&value), Bound::Unbounded),
+        BinaryOperator::GtEq => range_fraction(table, &col, Bound::Included(&value), Bound::Unbounded),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{rows, run, setup};
+
+    #[test]
+    fn analyze_records_counts_and_histograms() {
+        let mut db = setup("CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER, note TEXT)");
+        // `n` counts 1..=100, `note` is NULL for half of the rows
+        let values: Vec<String> = (1..=100).map(|i| format!("({}, {}, {})", i, i, if i % 2 == 0 { "'x'" } else { "NULL" })).collect();
+        run(&mut db, &format!("INSERT INTO t VALUES {}", values.join(", "))).unwrap();
+        assert_eq!(run(&mut db, "ANALYZE t"), Ok("Analyzed 1 table".to_string()));
+        let n = rows(&mut db, "SELECT row_count, null_count, distinct_count FROM sqlite_stat WHERE column_name = 'n'");
+        assert_eq!(n, vec![vec![Value::Integer(100), Value::Integer(0), Value::Integer(100)]]);
+        let note = rows(&mut db, "SELECT null_count, distinct_count FROM sqlite_stat WHERE column_name = 'note'");
+        assert_eq!(note, vec![vec![Value::Integer(50), Value::Integer(1)]]);
+        let histogram = &db.tables["t"].stats.as_ref().unwrap().columns["n"].histogram;
+        assert_eq!(histogram.len(), BUCKETS + 1);
+        assert_eq!((histogram.first(), histogram.last()), (Some(&Value::Integer(1)), Some(&Value::Integer(100))));
+        // Statistics wait for the next ANALYZE
+        run(&mut db, "DELETE FROM t WHERE id = 1").unwrap();
+        assert_eq!(rows(&mut db, "SELECT row_count FROM sqlite_stat WHERE column_name = 'n'"), vec![vec![Value::Integer(100)]]);
+    }
+
+    #[test]
+    fn estimates_follow_the_statistics() {
+        let mut db = setup("CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER, note TEXT)");
+        let values: Vec<String> = (1..=40).map(|i| format!("({}, {}, {})", i, i, if i % 2 == 0 { "'x'" } else { "'y'" })).collect();
+        run(&mut db, &format!("INSERT INTO t VALUES {}; ANALYZE t", values.join(", "))).unwrap();
+        let t = &db.tables["t"];
+        assert!((eq_fraction(t, "n", &Value::Integer(7)) - 0.025).abs() < 1e-9);
+        assert_eq!(eq_fraction(t, "n", &Value::Integer(500)), 0.0);
+        assert!((eq_fraction(t, "note", &Value::Text("x".to_string())) - 0.5).abs() < 1e-9);
+        let quarter = range_fraction(t, "n", Bound::Unbounded, Bound::Included(&Value::Integer(10)));
+        assert!((quarter - 0.25).abs() < 0.03, "{}", quarter);
+        // Without statistics, the fixed guesses
+        let fresh = setup("CREATE TABLE u (id INTEGER PRIMARY KEY, n INTEGER)");
+        assert_eq!(eq_fraction(&fresh.tables["u"], "n", &Value::Integer(1)), DEFAULT_EQ);
+        assert_eq!(range_fraction(&fresh.tables["u"], "n", Bound::Excluded(&Value::Integer(1)), Bound::Unbounded), DEFAULT_RANGE);
+    }
+}
@@ -0,0 +1,310 @@
+// --- TRIGGERS ---
+// Row-level triggers: CREATE TRIGGER name BEFORE|AFTER INSERT|UPDATE [OF cols]|DELETE ON table
+// [FOR EACH ROW] [WHEN cond] BEGIN ...; END. The body sees the row as NEW/OLD. A BEFORE
+// trigger may change NEW (SET NEW.col = expr) or skip the row with RAISE(IGNORE);
+// RAISE(ABORT, 'message') fails the whole statement.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::Statement;
+use sqlparser::dialect::GenericDialect;
+use sqlparser::tokenizer::{Token, Tokenizer};
+
+use crate::expr::{eval_expr, parse_expr, sql_literal, truth, RAISE_IGNORE};
+use crate::parser::{parse_commands, Command, CreateTrigger};
+use crate::{run_statement, Database, Value};
+
+type RowData = BTreeMap<String, Value>;
+
+// A trigger whose body writes to its own table could otherwise fire itself forever.
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Timing {
+    Before,
+    After,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Event {
+    Insert,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub table: String,
+    pub timing: Timing,
+    pub event: Event,
+    // UPDATE OF a, b: fire only when the UPDATE assigns one of these columns
+    #[serde(default)]
+    pub columns: Vec<String>,
+    // WHEN condition and body statements are stored as SQL text, with NEW/OLD bound on each firing
+    pub when: Option<String>,
+    pub body: Vec<Step>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Step {
+    // SET NEW.col = expr
+    SetNew { column: String, expr: String },
+    Sql(String),
+}
+
+impl Trigger {
+    // The CREATE statement for the catalog.
+    pub fn sql(&self) -> String {
+        let timing = match self.timing {
+            Timing::Before => "BEFORE",
+            Timing::After => "AFTER",
+        };
+        let event = match self.event {
+            Event::Insert => "INSERT".to_string(),
+            Event::Update if !self.columns.is_empty() => format!("UPDATE OF {}", self.columns.join(", ")),
+            Event::Update => "UPDATE".to_string(),
+            Event::Delete => "DELETE".to_string(),
+        };
+        let when = self.when.as_ref().map(|cond| format!(" WHEN {}", cond)).unwrap_or_default();
+        let body: Vec<String> = self.body.iter().map(|step| match step {
+            Step::SetNew { column, expr } => format!("SET NEW.{} = {}; ", column, expr),
+            Step::Sql(sql) => format!("{}; ", sql),
+        }).collect();
+        format!("CREATE TRIGGER {} {} {} ON {} FOR EACH ROW{} BEGIN {}END", self.name, timing, event, self.table, when, body.concat())
+    }
+}
+
+pub fn create(db: &mut Database, def: &CreateTrigger) -> Result<String, String> {
+    if db.triggers.contains_key(&def.name) {
+        return if def.if_not_exists {
+            Ok(format!("Trigger '{}' already exists, skipping", def.name))
+        } else {
+            Err(format!("Trigger '{}' already exists", def.name))
+        };
+    }
+    let table = db.tables.get(&def.table).ok_or(if db.views.contains_key(&def.table) {
+        format!("Cannot create trigger on view '{}'", def.table)
+    } else {
+        format!("Table '{}' not found", def.table)
+    })?;
+    for col in &def.columns {
+        if !table.columns.iter().any(|(c, _)| c == col) {
+            return Err(format!("Column '{}' not found", col));
+        }
+    }
+    let mut body = Vec::new();
+    for stmt in &def.body {
+        body.push(match stmt {
+            Statement::SetVariable { variable, value, .. } if variable.0.len() == 2 && variable.0[0].value.eq_ignore_ascii_case("new") => {
+                if def.timing != Timing::Before || def.event == Event::Delete {
+                    return Err("SET NEW is only allowed in BEFORE INSERT and BEFORE UPDATE triggers".to_string());
+                }
+                let column = variable.0[1].value.clone();
+                if !table.columns.iter().any(|(c, _)| *c == column) {
+                    return Err(format!("Column '{}' not found", column));
+                }
+                match value.as_slice() {
+                    [expr] => Step::SetNew { column, expr: expr.to_string() },
+                    _ => return Err("SET NEW.col expects a single value".to_string()),
+                }
+            }
+            Statement::Insert { .. } | Statement::Update { .. } | Statement::Delete { .. } | Statement::Query(_) => Step::Sql(stmt.to_string()),
+            other => return Err(format!("Not allowed in a trigger body: {}", other)),
+        });
+    }
+    let trigger = Trigger {
+        name: def.name.clone(),
+        table: def.table.clone(),
+        timing: def.timing,
+        event: def.event,
+        columns: def.columns.clone(),
+        when: def.when.as_ref().map(|e| e.to_string()),
+        body,
+    };
+
+    // Bind a row of NULLs once, so a misspelt NEW.col or an OLD in an INSERT trigger fails now
+    let nulls: RowData = table.columns.iter().map(|(c, _)| (c.clone(), Value::Null)).collect();
+    let (old, new) = rows_for(trigger.event, &nulls, &nulls);
+    for sql in trigger.when.iter().chain(trigger.body.iter().map(|step| match step {
+        Step::SetNew { expr, .. } => expr,
+        Step::Sql(sql) => sql,
+    })) {
+        bind(sql, old, new)?;
+    }
+
+    db.triggers.insert(trigger.name.clone(), trigger);
+    Ok(format!("Trigger '{}' created", def.name))
+}
+
+pub fn drop(db: &mut Database, name: &str, if_exists: bool) -> Result<String, String> {
+    match db.triggers.remove(name) {
+        Some(_) => Ok(format!("Trigger '{}' dropped", name)),
+        None if if_exists => Ok(format!("Trigger '{}' does not exist, skipping", name)),
+        None => Err(format!("Trigger '{}' not found", name)),
+    }
+}
+
+// Which of OLD and NEW an event provides.
+fn rows_for<'a>(event: Event, old: &'a RowData, new: &'a RowData)
+    -> (Option<&'a RowData>, Option<&'a RowData>) {
+    match event {
+        Event::Insert => (None, Some(new)),
+        Event::Update => (Some(old), Some(new)),
+        Event::Delete => (Some(old), None),
+    }
+}
+
+// Runs the table's triggers for one row, in name order. `new` is the row being written
+// (BEFORE triggers may change it); `assigned` lists the columns an UPDATE sets.
+// Returns false when a BEFORE trigger skipped the row with RAISE(IGNORE).
+pub fn fire(
+    db: &mut Database,
+    timing: Timing,
+    event: Event,
+    table: &str,
+    old: Option<&RowData>,
+    mut new: Option<&mut RowData>,
+    assigned: &[String],
+) -> Result<bool, String> {
+    let triggers: Vec<Trigger> = db.triggers.values()
+        .filter(|t| t.table == table && t.timing == timing && t.event == event)
+        .filter(|t| t.columns.is_empty() || t.columns.iter().any(|c| assigned.contains(c)))
+        .cloned()
+        .collect();
+    if triggers.is_empty() {
+        return Ok(true);
+    }
+    if db.trigger_depth >= MAX_DEPTH {
+        return Err(format!("Too many levels of trigger recursion (limit {})", MAX_DEPTH));
+    }
+    db.trigger_depth += 1;
+    let mut result = Ok(true);
+    for trigger in &triggers {
+        result = run(db, trigger, old, new.as_deref_mut());
+        if result != Ok(true) {
+            break;
+        }
+    }
+    db.trigger_depth -= 1;
+    result
+}
+
+fn run(db: &mut Database, trigger: &Trigger, old: Option<&RowData>, mut new: Option<&mut RowData>) -> Result<bool, String> {
+    let no_row = BTreeMap::new();
+    if let Some(cond) = &trigger.when {
+        let cond = parse_expr(&bind(cond, old, new.as_deref())?)?;
+        if truth(&eval_expr(&cond, &no_row)?)? != Some(true) {
+            return Ok(true);
+        }
+    }
+    for step in &trigger.body {
+        let outcome = match step {
+            Step::SetNew { column, expr } => {
+                let value = parse_expr(&bind(expr, old, new.as_deref())?).and_then(|e| eval_expr(&e, &no_row));
+                match (value, new.as_deref_mut()) {
+                    (Ok(value), Some(row)) => {
+                        let col_type = db.tables.get(&trigger.table)
+                            .and_then(|t| t.columns.iter().find(|(c, _)| c == column))
+                            .map(|(_, ty)| ty)
+                            .ok_or(format!("Column '{}' not found", column))?;
+                        row.insert(column.clone(), col_type.coerce(column, value)?);
+                        Ok(())
+                    }
+                    (Ok(_), None) => Err("NEW is not available in this trigger".to_string()),
+                    (Err(e), _) => Err(e),
+                }
+            }
+            Step::Sql(sql) => run_sql(db, &bind(sql, old, new.as_deref())?),
+        };
+        match outcome {
+            Err(e) if e == RAISE_IGNORE => return Ok(false),
+            Err(e) => return Err(e),
+            Ok(()) => {}
+        }
+    }
+    Ok(true)
+}
+
+fn run_sql(db: &mut Database, sql: &str) -> Result<(), String> {
+    for command in parse_commands(sql).map_err(|e| format!("Syntax error in trigger: {}", e))? {
+        if let Command::Sql(stmt) = command {
+            run_statement(db, &stmt)?;
+        }
+    }
+    Ok(())
+}
+
+// Replaces NEW.col and OLD.col in `sql` with the row's values, written as literals.
+fn bind(sql: &str, old: Option<&RowData>, new: Option<&RowData>) -> Result<String, String> {
+    let tokens = Tokenizer::new(&GenericDialect {}, sql).tokenize().map_err(|e| e.to_string())?;
+    let mut out = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if let (Token::Word(qualifier), Some(Token::Period), Some(Token::Word(col))) = (&tokens[i], tokens.get(i + 1), tokens.get(i + 2))
+            && qualifier.quote_style.is_none()
+            && let Some((which, row)) = [("NEW", new), ("OLD", old)].into_iter().find(|(w, _)| qualifier.value.eq_ignore_ascii_case(w))
+        {
+            let row = row.ok_or(format!("{} is not available in this trigger", which))?;
+            let value = row.get(&col.value).ok_or(format!("Column '{}' not found in {}", col.value, which))?;
+            out.push_str(&format!("({})", sql_literal(value)));
+            i += 3;
+            continue;
+        }
+        match &tokens[i] {
+            // Token's Display does not re-escape quotes
+            Token::SingleQuotedString(s) => out.push_str(&format!("'{}'", s.replace('\'', "''"))),
+            token => out.push_str(&token.to_string()),
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{rows, run, setup};
+    use crate::Value;
+
+    #[test]
+    fn body_statements_may_match_no_rows() {
+        let mut db = setup("
+            CREATE TABLE parent (id INTEGER PRIMARY KEY);
+            CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER);
+            CREATE TRIGGER cascade AFTER DELETE ON parent BEGIN
+                UPDATE child SET parent_id = NULL WHERE parent_id = OLD.id;
+                DELETE FROM child WHERE parent_id = OLD.id;
+            END;
+            INSERT INTO parent VALUES (1), (2);
+            INSERT INTO child VALUES (10, 1);
+        ");
+        assert_eq!(run(&mut db, "DELETE FROM parent WHERE id = 2"), Ok("Deleted 1 rows".to_string()));
+        assert_eq!(run(&mut db, "DELETE FROM parent WHERE id = 1"), Ok("Deleted 1 rows".to_string()));
+        assert_eq!(rows(&mut db, "SELECT id, parent_id FROM child"), vec![vec![Value::Integer(10), Value::Null]]);
+        // Outside a trigger, matching nothing is still an error
+        assert!(run(&mut db, "DELETE FROM parent WHERE id = 3").is_err());
+    }
+
+    #[test]
+    fn before_triggers_rewrite_skip_or_abort_rows() {
+        let mut db = setup("
+            CREATE TABLE log (id INTEGER PRIMARY KEY, msg TEXT);
+            CREATE TRIGGER shout BEFORE INSERT ON log BEGIN SET NEW.msg = upper(NEW.msg); END;
+            CREATE TRIGGER quiet BEFORE INSERT ON log WHEN NEW.msg = 'skip' BEGIN SELECT RAISE(IGNORE); END;
+            CREATE TRIGGER halt BEFORE INSERT ON log WHEN NEW.msg = 'stop' BEGIN SELECT RAISE(ABORT, 'stopped'); END;
+        ");
+        assert_eq!(run(&mut db, "INSERT INTO log VALUES (1, 'hi'), (2, 'skip')"), Ok("Inserted 1 rows".to_string()));
+        assert_eq!(run(&mut db, "INSERT INTO log VALUES (3, 'ok'), (4, 'stop')"), Err("stopped".to_string()));
+        assert_eq!(rows(&mut db, "SELECT id, msg FROM log"), vec![vec![Value::Integer(1), Value::Text("HI".to_string())]]);
+    }
+
+    #[test]
+    fn recursion_is_limited() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY);
+            CREATE TRIGGER again AFTER INSERT ON t BEGIN INSERT INTO t VALUES (NEW.id + 1); END;
+        ");
+        assert_eq!(run(&mut db, "INSERT INTO t VALUES (1)"), Err("Too many levels of trigger recursion (limit 16)".to_string()));
+        assert!(rows(&mut db, "SELECT id FROM t").is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+// --- COLUMN TYPES ---
+// The declared type of a column, mapped from the SQL type name (including aliases such as
+// BIGINT or DOUBLE PRECISION) and used to validate and coerce values on write.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{ArrayElemTypeDef, CharacterLength, DataType, ExactNumberInfo, TimezoneInfo};
+
+use crate::Value;
+use crate::array;
+use crate::blob::Blob;
+use crate::json::Json;
+use crate::uuid::Uuid;
+use crate::expr::cast;
+use crate::datetime::{Date, Interval, Time, Timestamp};
+use crate::decimal::Decimal;
+
+// Unit variants serialize as plain strings ("Integer", "Text", ...), which is exactly how
+// column types were stored before this enum existed, so old mydb.json files load unchanged.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ColumnType {
+    SmallInt,
+    Integer,
+    BigInt,
+    Float,
+    Numeric { precision: Option<u32>, scale: u32 },
+    Text,
+    Varchar(Option<u64>),
+    Char(u64),
+    Bool,
+    // BYTEA/BLOB, with an optional maximum length in bytes
+    Blob(Option<u64>),
+    // JSON and JSONB; the document is validated on write
+    Json,
+    Uuid,
+    // CREATE TYPE name AS ENUM (...); the labels are copied in when the column is created
+    Enum { name: String, labels: Vec<String> },
+    // INT[], TEXT[], ...
+    Array(Box<ColumnType>),
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+    Interval,
+}
+
+fn char_len(len: &Option<CharacterLength>) -> Option<u64> {
+    len.as_ref().map(|l| l.length)
+}
+
+impl ColumnType {
+    pub fn from_ast(data_type: &DataType) -> Result<Self, String> {
+        Ok(match data_type {
+            DataType::SmallInt(_) | DataType::Int2(_) | DataType::TinyInt(_) => ColumnType::SmallInt,
+            DataType::Int(_) | DataType::Integer(_) | DataType::Int4(_) | DataType::MediumInt(_) => ColumnType::Integer,
+            DataType::BigInt(_) | DataType::Int8(_) | DataType::Int64 => ColumnType::BigInt,
+            DataType::Float(_) | DataType::Real | DataType::Double | DataType::DoublePrecision
+            | DataType::Float4 | DataType::Float8 | DataType::Float64 => ColumnType::Float,
+            DataType::Numeric(info) | DataType::Decimal(info) | DataType::Dec(info) => {
+                let (precision, scale) = match info {
+                    ExactNumberInfo::None => (None, 0),
+                    ExactNumberInfo::Precision(p) => (Some(*p), 0),
+                    ExactNumberInfo::PrecisionAndScale(p, s) => (Some(*p), *s),
+                };
+                if let Some(p) = precision
+                    && (p == 0 || p > crate::decimal::MAX_DIGITS as u64 || scale > p)
+                {
+                    return Err(format!("Invalid NUMERIC precision/scale ({}, {})", p, scale));
+                }
+                ColumnType::Numeric { precision: precision.map(|p| p as u32), scale: scale as u32 }
+            }
+            DataType::Text | DataType::String(None) | DataType::Clob(None) => ColumnType::Text,
+            DataType::Varchar(len) | DataType::CharVarying(len) | DataType::CharacterVarying(len) => {
+                ColumnType::Varchar(char_len(len))
+            }
+            DataType::Nvarchar(len) | DataType::String(len) | DataType::Clob(len) => ColumnType::Varchar(*len),
+            // CHAR without a length means CHAR(1), as in standard SQL
+            DataType::Char(len) | DataType::Character(len) => ColumnType::Char(char_len(len).unwrap_or(1)),
+            DataType::Bool | DataType::Boolean => ColumnType::Bool,
+            DataType::Bytea => ColumnType::Blob(None),
+            DataType::Blob(len) | DataType::Binary(len) | DataType::Varbinary(len) => ColumnType::Blob(*len),
+            DataType::Date => ColumnType::Date,
+            DataType::Time(_, _) => ColumnType::Time,
+            DataType::Timestamp(_, TimezoneInfo::WithTimeZone | TimezoneInfo::Tz) => ColumnType::TimestampTz,
+            DataType::Timestamp(_, _) | DataType::Datetime(_) => ColumnType::Timestamp,
+            DataType::Interval => ColumnType::Interval,
+            DataType::JSON => ColumnType::Json,
+            DataType::Uuid => ColumnType::Uuid,
+            DataType::Array(ArrayElemTypeDef::SquareBracket(inner) | ArrayElemTypeDef::AngleBracket(inner)) => {
+                ColumnType::Array(Box::new(ColumnType::from_ast(inner)?))
+            }
+            DataType::Custom(name, _) if name.to_string().eq_ignore_ascii_case("jsonb") => ColumnType::Json,
+            _ => return Err(format!("Unsupported type: {}", data_type)),
+        })
+    }
+
+    // SMALLSERIAL, SERIAL and BIGSERIAL: the integer type behind the shorthand.
+    pub fn serial(data_type: &DataType) -> Option<Self> {
+        let DataType::Custom(name, _) = data_type else { return None };
+        match name.to_string().to_lowercase().as_str() {
+            "smallserial" | "serial2" => Some(ColumnType::SmallInt),
+            "serial" | "serial4" => Some(ColumnType::Integer),
+            "bigserial" | "serial8" => Some(ColumnType::BigInt),
+            _ => None,
+        }
+    }
+
+    // Validates a value for this column, applying the implicit conversions SQL allows on
+    // assignment (integer into a float/numeric column, CHAR padding, NUMERIC rounding).
+    // NULL is always accepted here; NOT NULL is a separate constraint.
+    pub fn coerce(&self, col_name: &str, value: Value) -> Result<Value, String> {
+        let mismatch = |v: &Value| format!("Type Mismatch! Column '{}' expects {}, but got {:?}", col_name, self, v);
+        let out_of_range = || format!("Value out of range for column '{}' of type {}", col_name, self);
+        match (self, value) {
+            (_, Value::Null) => Ok(Value::Null),
+            (ColumnType::SmallInt, Value::Integer(i)) => i16::try_from(i).map(|_| Value::Integer(i)).map_err(|_| out_of_range()),
+            (ColumnType::Integer, Value::Integer(i)) => i32::try_from(i).map(|_| Value::Integer(i)).map_err(|_| out_of_range()),
+            (ColumnType::BigInt, Value::Integer(i)) => Ok(Value::Integer(i)),
+            (ColumnType::Float, Value::Float(f)) => Ok(Value::Float(f)),
+            (ColumnType::Float, Value::Integer(i)) => Ok(Value::Float(i as f64)),
+            (ColumnType::Float, Value::Decimal(d)) => Ok(Value::Float(d.to_f64())),
+            (ColumnType::Numeric { precision, scale }, v @ (Value::Integer(_) | Value::Float(_) | Value::Decimal(_))) => {
+                let d = match v {
+                    Value::Integer(i) => Decimal::from(i),
+                    Value::Float(f) => Decimal::from_f64(f)?,
+                    Value::Decimal(d) => d,
+                    _ => unreachable!(),
+                };
+                // Unconstrained NUMERIC keeps the value as given
+                let Some(p) = precision else { return Ok(Value::Decimal(d)) };
+                let rounded = d.rescale(*scale).map_err(|_| out_of_range())?;
+                // NUMERIC(p, s) leaves p - s digits before the point
+                if rounded.integer_digits() > *p - *scale {
+                    return Err(out_of_range());
+                }
+                Ok(Value::Decimal(rounded))
+            }
+            (ColumnType::Text, Value::Text(s)) => Ok(Value::Text(s)),
+            (ColumnType::Varchar(max), Value::Text(s)) => match max {
+                Some(n) if s.chars().count() as u64 > *n => {
+                    Err(format!("Value too long for column '{}' of type {}", col_name, self))
+                }
+                _ => Ok(Value::Text(s)),
+            },
+            (ColumnType::Char(n), Value::Text(s)) => {
+                let len = s.chars().count() as u64;
+                if len > *n {
+                    return Err(format!("Value too long for column '{}' of type {}", col_name, self));
+                }
+                Ok(Value::Text(format!("{}{}", s, " ".repeat((*n - len) as usize))))
+            }
+            (ColumnType::Bool, Value::Bool(b)) => Ok(Value::Bool(b)),
+            (ColumnType::Blob(max), v @ (Value::Blob(_) | Value::Text(_))) => {
+                let bytes = match v {
+                    Value::Text(s) => Blob::from_text(&s)?,
+                    Value::Blob(b) => b,
+                    _ => unreachable!(),
+                };
+                match max {
+                    Some(n) if bytes.0.len() as u64 > *n => {
+                        Err(format!("Value too long for column '{}' of type {}", col_name, self))
+                    }
+                    _ => Ok(Value::Blob(bytes)),
+                }
+            }
+            // Date/time columns accept their own type, a compatible one, or a string literal
+            (ColumnType::Date, Value::Date(d)) => Ok(Value::Date(d)),
+            (ColumnType::Date, Value::Timestamp(ts) | Value::TimestampTz(ts)) => Ok(Value::Date(ts.date())),
+            (ColumnType::Date, Value::Text(s)) => Date::parse(&s).map(Value::Date),
+            (ColumnType::Time, Value::Time(t)) => Ok(Value::Time(t)),
+            (ColumnType::Time, Value::Timestamp(ts) | Value::TimestampTz(ts)) => Ok(Value::Time(ts.time())),
+            (ColumnType::Time, Value::Text(s)) => Time::parse(&s).map(Value::Time),
+            (ColumnType::Timestamp, Value::Timestamp(ts) | Value::TimestampTz(ts)) => Ok(Value::Timestamp(ts)),
+            (ColumnType::Timestamp, Value::Date(d)) => Ok(Value::Timestamp(d.to_timestamp())),
+            (ColumnType::Timestamp, Value::Text(s)) => Timestamp::parse(&s).map(|(ts, _)| Value::Timestamp(ts)),
+            (ColumnType::TimestampTz, Value::Timestamp(ts) | Value::TimestampTz(ts)) => Ok(Value::TimestampTz(ts)),
+            (ColumnType::TimestampTz, Value::Date(d)) => Ok(Value::TimestampTz(d.to_timestamp())),
+            (ColumnType::TimestampTz, Value::Text(s)) => Timestamp::parse_utc(&s).map(Value::TimestampTz),
+            (ColumnType::Interval, Value::Interval(iv)) => Ok(Value::Interval(iv)),
+            (ColumnType::Json, Value::Json(j)) => Ok(Value::Json(j)),
+            (ColumnType::Uuid, Value::Uuid(u)) => Ok(Value::Uuid(u)),
+            (ColumnType::Uuid, Value::Text(s)) => Uuid::parse(&s).map(Value::Uuid),
+            (ColumnType::Enum { name, labels }, Value::Text(s)) => {
+                if labels.contains(&s) {
+                    Ok(Value::Text(s))
+                } else {
+                    Err(format!("Invalid value for enum {} in column '{}': '{}' (expected one of: {})",
+                        name, col_name, s, labels.iter().map(|l| format!("'{}'", l)).collect::<Vec<_>>().join(", ")))
+                }
+            }
+            // Every element is checked against the element type
+            (ColumnType::Array(elem), Value::Array(items)) => {
+                items.into_iter().map(|v| elem.coerce(col_name, v)).collect::<Result<_, _>>().map(Value::Array)
+            }
+            (ColumnType::Array(elem), Value::Text(s)) => {
+                let mut items = Vec::new();
+                for item in array::parse_literal(&s)? {
+                    items.push(match item {
+                        None => Value::Null,
+                        // Text elements are taken as-is where the element type accepts text, else converted
+                        Some(text) => elem.coerce(col_name, Value::Text(text.clone()))
+                            .or_else(|e| cast(Value::Text(text), elem).map_err(|_| e))?,
+                    });
+                }
+                Ok(Value::Array(items))
+            }
+            (ColumnType::Json, Value::Text(s)) => {
+                Json::parse(&s).map(Value::Json).map_err(|e| format!("{} (column '{}')", e, col_name))
+            }
+            (ColumnType::Interval, Value::Text(s)) => Interval::parse(&s).map(Value::Interval),
+            (_, v) => Err(mismatch(&v)),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnType::SmallInt => write!(f, "SMALLINT"),
+            ColumnType::Integer => write!(f, "INTEGER"),
+            ColumnType::BigInt => write!(f, "BIGINT"),
+            ColumnType::Float => write!(f, "FLOAT"),
+            ColumnType::Numeric { precision: None, .. } => write!(f, "NUMERIC"),
+            ColumnType::Numeric { precision: Some(p), scale } => write!(f, "NUMERIC({}, {})", p, scale),
+            ColumnType::Text => write!(f, "TEXT"),
+            ColumnType::Varchar(None) => write!(f, "VARCHAR"),
+            ColumnType::Varchar(Some(n)) => write!(f, "VARCHAR({})", n),
+            ColumnType::Char(n) => write!(f, "CHAR({})", n),
+            ColumnType::Bool => write!(f, "BOOLEAN"),
+            ColumnType::Blob(None) => write!(f, "BYTEA"),
+            ColumnType::Blob(Some(n)) => write!(f, "BLOB({})", n),
+            ColumnType::Date => write!(f, "DATE"),
+            ColumnType::Time => write!(f, "TIME"),
+            ColumnType::Timestamp => write!(f, "TIMESTAMP"),
+            ColumnType::TimestampTz => write!(f, "TIMESTAMP WITH TIME ZONE"),
+            ColumnType::Interval => write!(f, "INTERVAL"),
+            ColumnType::Json => write!(f, "JSON"),
+            ColumnType::Uuid => write!(f, "UUID"),
+            ColumnType::Enum { name, .. } => write!(f, "{}", name),
+            ColumnType::Array(elem) => write!(f, "{}[]", elem),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numeric(precision: u32, scale: u32, value: &str) -> Result<String, String> {
+        let col = ColumnType::Numeric { precision: Some(precision), scale };
+        col.coerce("n", Value::Decimal(Decimal::parse(value)?)).map(|v| format!("{:?}", v))
+    }
+
+    // The type a column declared as `sql_type` gets
+    fn declared(sql_type: &str) -> Result<ColumnType, String> {
+        use sqlparser::ast::Statement;
+        use sqlparser::dialect::GenericDialect;
+        use sqlparser::parser::Parser;
+        let sql = format!("CREATE TABLE t (c {})", sql_type);
+        let statements = Parser::parse_sql(&GenericDialect {}, &sql).map_err(|e| e.to_string())?;
+        let Statement::CreateTable { columns, .. } = &statements[0] else { panic!("not a CREATE TABLE: {}", sql) };
+        ColumnType::from_ast(&columns[0].data_type)
+    }
+
+    #[test]
+    fn type_names_and_aliases_map_to_one_type() {
+        assert_eq!(declared("INT8"), Ok(ColumnType::BigInt));
+        assert_eq!(declared("DOUBLE PRECISION"), Ok(ColumnType::Float));
+        assert_eq!(declared("CHARACTER VARYING(20)"), Ok(ColumnType::Varchar(Some(20))));
+        assert_eq!(declared("CHAR"), Ok(ColumnType::Char(1)));
+        assert_eq!(declared("DECIMAL(10, 2)"), Ok(ColumnType::Numeric { precision: Some(10), scale: 2 }));
+        assert_eq!(declared("TIMESTAMP WITH TIME ZONE"), Ok(ColumnType::TimestampTz));
+        assert_eq!(declared("INT[]"), Ok(ColumnType::Array(Box::new(ColumnType::Integer))));
+        assert_eq!(declared("NUMERIC(2, 3)"), Err("Invalid NUMERIC precision/scale (2, 3)".to_string()));
+        assert_eq!(declared("VARCHAR(5)").map(|t| t.to_string()), Ok("VARCHAR(5)".to_string()));
+    }
+
+    #[test]
+    fn coercion_checks_ranges_and_lengths() {
+        assert_eq!(ColumnType::SmallInt.coerce("n", Value::Integer(40_000)), Err("Value out of range for column 'n' of type SMALLINT".to_string()));
+        assert_eq!(ColumnType::Integer.coerce("n", Value::Integer(1 << 31)), Err("Value out of range for column 'n' of type INTEGER".to_string()));
+        assert_eq!(ColumnType::BigInt.coerce("n", Value::Integer(1 << 31)), Ok(Value::Integer(1 << 31)));
+        assert_eq!(ColumnType::Float.coerce("n", Value::Integer(2)), Ok(Value::Float(2.0)));
+        assert_eq!(ColumnType::Char(3).coerce("s", Value::Text("a".to_string())), Ok(Value::Text("a  ".to_string())));
+        assert_eq!(ColumnType::Varchar(Some(2)).coerce("s", Value::Text("abc".to_string())), Err("Value too long for column 's' of type VARCHAR(2)".to_string()));
+        assert_eq!(ColumnType::Bool.coerce("b", Value::Integer(1)), Err("Type Mismatch! Column 'b' expects BOOLEAN, but got Integer(1)".to_string()));
+        assert_eq!(ColumnType::Integer.coerce("n", Value::Null), Ok(Value::Null));
+    }
+
+    #[test]
+    fn numeric_bounds_count_digits_before_the_point() {
+        // NUMERIC(3, 3) holds values with no integer part
+        assert_eq!(numeric(3, 3, "0"), Ok("Decimal(0.000)".to_string()));
+        assert_eq!(numeric(3, 3, "0.999"), Ok("Decimal(0.999)".to_string()));
+        assert_eq!(numeric(3, 3, "-0.5"), Ok("Decimal(-0.500)".to_string()));
+        assert!(numeric(3, 3, "1").is_err());
+        // Rounding can carry into the integer part
+        assert!(numeric(3, 3, "0.9996").is_err());
+        // NUMERIC(3, 0) holds integers of up to three digits
+        assert_eq!(numeric(3, 0, "999"), Ok("Decimal(999)".to_string()));
+        assert_eq!(numeric(3, 0, "0.4"), Ok("Decimal(0)".to_string()));
+        assert_eq!(numeric(3, 0, "-999.4"), Ok("Decimal(-999)".to_string()));
+        assert_eq!(numeric(3, 0, "999.5"), Err("Value out of range for column 'n' of type NUMERIC(3, 0)".to_string()));
+        assert!(numeric(5, 2, "123.456").is_ok());
+        assert!(numeric(5, 2, "1234.5").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+// --- UUIDS ---
+// 128-bit identifiers held as a single integer (16 bytes, not a 36-character string), and
+// saved to mydb.json as 32 hex digits.
+// gen_random_uuid() makes version 4 ids; uuidv7() makes time-ordered version 7 ids, which
+// sort by creation time and so make good primary keys.
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Uuid(pub u128);
+
+// 16 random bytes from the OS, or (where /dev/urandom is missing) from std's per-process
+// random hash keys mixed with the clock and a counter.
+fn random_bits() -> u128 {
+    let mut buf = [0u8; 16];
+    if File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut buf)).is_ok() {
+        return u128::from_le_bytes(buf);
+    }
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let mut halves = [0u64; 2];
+    for half in &mut halves {
+        let mut h = RandomState::new().build_hasher();
+        h.write_u128(nanos);
+        h.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        *half = h.finish();
+    }
+    (halves[0] as u128) << 64 | halves[1] as u128
+}
+
+fn with_version(bits: u128, version: u128) -> Uuid {
+    let bits = bits & !(0xF << 76) | version << 76; // version nibble
+    Uuid(bits & !(0b11 << 62) | 0b10 << 62) // RFC 4122 variant
+}
+
+impl Uuid {
+    pub fn new_v4() -> Self {
+        with_version(random_bits(), 4)
+    }
+
+    // 48-bit Unix milliseconds followed by random bits.
+    pub fn new_v7() -> Self {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        with_version((millis & 0xFFFF_FFFF_FFFF) << 80 | random_bits() & ((1 << 80) - 1), 7)
+    }
+
+    // Accepts the canonical form, upper case, braces and a missing hyphenation.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid UUID: '{}'", s);
+        let t = s.trim();
+        let t = t.strip_prefix('{').and_then(|t| t.strip_suffix('}')).unwrap_or(t);
+        let hex: String = t.chars().filter(|c| *c != '-').collect();
+        let hyphens_ok = t.len() == 32 || (t.len() == 36 && [8, 13, 18, 23].iter().all(|&i| t.as_bytes()[i] == b'-'));
+        if hex.len() != 32 || !hyphens_ok || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        u128::from_str_radix(&hex, 16).map(Uuid).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let h = format!("{:032x}", self.0);
+        write!(f, "{}-{}-{}-{}-{}", &h[0..8], &h[8..12], &h[12..16], &h[16..20], &h[20..32])
+    }
+}
+
+impl fmt::Debug for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Serialize for Uuid {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format!("{:032x}", self.0))
+    }
+}
+
+// Also reads the hyphenated form that older files hold
+impl<'de> Deserialize<'de> for Uuid {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        Uuid::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_accepted_spellings() {
+        let id = Uuid::parse("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+        assert_eq!(id.to_string(), "a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11");
+        assert_eq!(Uuid::parse("{A0EEBC99-9C0B-4EF8-BB6D-6BB9BD380A11}"), Ok(id));
+        assert_eq!(Uuid::parse("a0eebc999c0b4ef8bb6d6bb9bd380a11"), Ok(id));
+        assert!(Uuid::parse("a0eebc99-9c0b4-ef8-bb6d-6bb9bd380a11").is_err());
+        assert!(Uuid::parse("a0eebc99").is_err());
+    }
+
+    #[test]
+    fn saved_as_32_hex_digits() {
+        let id = Uuid::parse("a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11").unwrap();
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""a0eebc999c0b4ef8bb6d6bb9bd380a11""#);
+        assert_eq!(serde_json::from_str::<Uuid>(r#""a0eebc999c0b4ef8bb6d6bb9bd380a11""#).unwrap(), id);
+        assert_eq!(serde_json::from_str::<Uuid>(r#""a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11""#).unwrap(), id);
+    }
+
+    #[test]
+    fn generated_ids_carry_their_version() {
+        assert_eq!(Uuid::new_v4().to_string().as_bytes()[14], b'4');
+        let (a, b) = (Uuid::new_v7(), Uuid::new_v7());
+        assert_eq!(a.to_string().as_bytes()[14], b'7');
+        assert_eq!(a.0 >> 62 & 0b11, 0b10);
+        assert_ne!(a, b);
+    }
+}
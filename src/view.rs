@@ -0,0 +1,249 @@
+// --- VIEWS ---
+// CREATE VIEW stores a query and runs it whenever the view is read. A materialized view
+// also stores the rows the query produced; REFRESH MATERIALIZED VIEW runs it again.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{ObjectName, Query, SetExpr, TableFactor};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+
+use crate::{object_name, run_query, Database, ResultSet, Row};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct View {
+    pub name: String,
+    // The SELECT, stored as SQL text and re-parsed on use (like column DEFAULTs).
+    pub query: String,
+    // Names from CREATE VIEW v (a, b) AS ...; the query's own column names are used for the rest.
+    #[serde(default)]
+    pub column_names: Vec<String>,
+    // Materialized views only: the result of the last refresh.
+    #[serde(default)]
+    pub snapshot: Option<Snapshot>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+impl View {
+    pub fn is_materialized(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    pub fn parse_query(&self) -> Result<Query, String> {
+        Parser::new(&GenericDialect {}).try_with_sql(&self.query)
+            .and_then(|mut p| p.parse_query())
+            .map_err(|e| format!("Invalid query in view '{}': {}", self.name, e))
+    }
+
+    // Runs the view's query and names its columns.
+    pub fn evaluate(&self, db: &Database) -> Result<Snapshot, String> {
+        to_snapshot(&self.name, &self.column_names, run_query(db, &self.parse_query()?)?)
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.is_materialized() { "materialized view" } else { "view" }
+    }
+
+    // The CREATE statement for the catalog.
+    pub fn sql(&self) -> String {
+        let columns = if self.column_names.is_empty() { String::new() } else { format!(" ({})", self.column_names.join(", ")) };
+        let kind = if self.is_materialized() { "MATERIALIZED VIEW" } else { "VIEW" };
+        format!("CREATE {} {}{} AS {}", kind, self.name, columns, self.query)
+    }
+}
+
+// Result rows become view rows numbered from 1, keyed by the (renamed) column names.
+fn to_snapshot(name: &str, column_names: &[String], result: ResultSet) -> Result<Snapshot, String> {
+    if column_names.len() > result.columns.len() {
+        return Err(format!("View '{}' names {} columns but its query returns {}", name, column_names.len(), result.columns.len()));
+    }
+    let mut columns = result.columns;
+    for (col, renamed) in columns.iter_mut().zip(column_names) {
+        *col = renamed.clone();
+    }
+    for (i, col) in columns.iter().enumerate() {
+        if columns[..i].contains(col) {
+            return Err(format!("Column '{}' appears more than once in view '{}'; give it an alias", col, name));
+        }
+    }
+    let rows = result.rows.into_iter().enumerate().map(|(i, (_, values))| Row {
+        id: i as u64 + 1,
+        data: columns.iter().cloned().zip(values).collect::<BTreeMap<_, _>>(),
+    }).collect();
+    Ok(Snapshot { columns, rows })
+}
+
+// Tables and views named in a query's FROM clause and joins.
+fn relations(query: &Query) -> Vec<String> {
+    let mut names = Vec::new();
+    if let SetExpr::Select(select) = &*query.body {
+        for item in &select.from {
+            for factor in std::iter::once(&item.relation).chain(item.joins.iter().map(|j| &j.relation)) {
+                if let TableFactor::Table { name, args: None, .. } = factor {
+                    names.push(object_name(name));
+                }
+            }
+        }
+    }
+    names
+}
+
+// Does `query` read `name`, directly or through other views?
+fn reads(db: &Database, query: &Query, name: &str) -> bool {
+    relations(query).iter().any(|rel| {
+        rel == name || db.views.get(rel).and_then(|v| v.parse_query().ok()).is_some_and(|q| reads(db, &q, name))
+    })
+}
+
+// Views whose query names `name` directly.
+pub fn dependents(db: &Database, name: &str) -> Vec<String> {
+    db.views.values()
+        .filter(|v| v.parse_query().is_ok_and(|q| relations(&q).iter().any(|rel| rel == name)))
+        .map(|v| v.name.clone())
+        .collect()
+}
+
+pub struct CreateView<'a> {
+    pub name: String,
+    pub column_names: Vec<String>,
+    pub query: &'a Query,
+    pub materialized: bool,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+}
+
+pub fn create(db: &mut Database, def: CreateView) -> Result<String, String> {
+    let name = def.name;
+    if db.tables.contains_key(&name) {
+        return Err(format!("Table '{}' already exists", name));
+    }
+    crate::schema::check_schema(db, &name)?;
+    if let Some(existing) = db.views.get(&name) {
+        if def.if_not_exists {
+            return Ok(format!("View '{}' already exists, skipping", name));
+        }
+        if !def.or_replace || def.materialized || existing.is_materialized() {
+            return Err(format!("View '{}' already exists", name));
+        }
+        if reads(db, def.query, &name) {
+            return Err(format!("View '{}' cannot read from itself", name));
+        }
+    }
+    // Running the query now reports bad tables and columns at CREATE time
+    let result = run_query(db, def.query)?;
+    let snapshot = to_snapshot(&name, &def.column_names, result)?;
+    let rows = snapshot.rows.len();
+    let view = View {
+        name: name.clone(),
+        query: def.query.to_string(),
+        column_names: def.column_names,
+        snapshot: def.materialized.then_some(snapshot),
+    };
+    db.views.insert(name.clone(), view);
+    Ok(if def.materialized {
+        format!("Materialized view '{}' created ({} rows)", name, rows)
+    } else {
+        format!("View '{}' created", name)
+    })
+}
+
+// DROP VIEW / DROP MATERIALIZED VIEW. Views built on a dropped view go with it under CASCADE.
+pub fn drop(db: &mut Database, names: &[ObjectName], if_exists: bool, cascade: bool, materialized: bool) -> Result<String, String> {
+    // Check every name before dropping anything
+    let mut targets = Vec::new();
+    for name in names.iter().map(object_name) {
+        let Some(view) = db.views.get(&name) else {
+            if db.tables.contains_key(&name) {
+                return Err(format!("'{}' is a table, not a view", name));
+            }
+            if if_exists {
+                continue;
+            }
+            return Err(format!("View '{}' not found", name));
+        };
+        if view.is_materialized() != materialized {
+            let statement = if view.is_materialized() { "DROP MATERIALIZED VIEW" } else { "DROP VIEW" };
+            return Err(format!("'{}' is a {}; use {}", name, view.kind(), statement));
+        }
+        if let Some(user) = dependents(db, &name).into_iter().find(|u| !cascade && !names.iter().any(|n| object_name(n) == *u)) {
+            return Err(format!("Cannot drop '{}': view '{}' depends on it (use CASCADE)", name, user));
+        }
+        targets.push(name);
+    }
+    let mut dropped = Vec::new();
+    while let Some(next) = targets.pop() {
+        if db.views.remove(&next).is_some() {
+            targets.extend(dependents(db, &next));
+            dropped.push(next);
+        }
+    }
+    if dropped.is_empty() {
+        return Ok("No views dropped".to_string());
+    }
+    Ok(format!("Dropped {}", dropped.iter().map(|n| format!("'{}'", n)).collect::<Vec<_>>().join(", ")))
+}
+
+pub fn refresh(db: &mut Database, name: &str) -> Result<String, String> {
+    let view = db.views.get(name).ok_or(format!("Materialized view '{}' not found", name))?;
+    if !view.is_materialized() {
+        return Err(format!("'{}' is not a materialized view", name));
+    }
+    let snapshot = view.evaluate(db)?;
+    let rows = snapshot.rows.len();
+    if let Some(view) = db.views.get_mut(name) {
+        view.snapshot = Some(snapshot);
+    }
+    Ok(format!("Materialized view '{}' refreshed ({} rows)", name, rows))
+}
+
+// INSERT, UPDATE and DELETE only target tables.
+pub fn reject_write(db: &Database, name: &str) -> Result<(), String> {
+    match db.views.get(name) {
+        Some(view) if view.is_materialized() => Err(format!("Cannot modify materialized view '{}'; use REFRESH MATERIALIZED VIEW", name)),
+        Some(_) => Err(format!("Cannot modify view '{}'", name)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{rows, run, setup};
+    use crate::Value;
+
+    fn ids(values: &[i64]) -> Vec<Vec<Value>> {
+        values.iter().map(|&i| vec![Value::Integer(i)]).collect()
+    }
+
+    #[test]
+    fn views_rerun_and_materialized_views_wait_for_refresh() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);
+            INSERT INTO t VALUES (1, 10), (2, 20);
+            CREATE VIEW big (ident, amount) AS SELECT id, n FROM t WHERE n > 15;
+            CREATE MATERIALIZED VIEW snap AS SELECT id, n FROM t;
+            INSERT INTO t VALUES (3, 30);
+        ");
+        assert_eq!(rows(&mut db, "SELECT ident FROM big"), ids(&[2, 3]));
+        assert_eq!(rows(&mut db, "SELECT id FROM snap"), ids(&[1, 2]));
+        assert_eq!(run(&mut db, "REFRESH MATERIALIZED VIEW snap"), Ok("Materialized view 'snap' refreshed (3 rows)".to_string()));
+        assert_eq!(rows(&mut db, "SELECT id FROM snap"), ids(&[1, 2, 3]));
+        assert_eq!(run(&mut db, "INSERT INTO big VALUES (4, 40)"), Err("Cannot modify view 'big'".to_string()));
+    }
+
+    #[test]
+    fn dropping_a_view_others_read_needs_cascade() {
+        let mut db = setup("
+            CREATE TABLE t (id INTEGER PRIMARY KEY);
+            CREATE VIEW a AS SELECT id FROM t;
+            CREATE VIEW b AS SELECT id FROM a;
+        ");
+        assert_eq!(run(&mut db, "DROP VIEW a"), Err("Cannot drop 'a': view 'b' depends on it (use CASCADE)".to_string()));
+        assert_eq!(run(&mut db, "DROP VIEW a CASCADE"), Ok("Dropped 'a', 'b'".to_string()));
+        assert!(run(&mut db, "SELECT * FROM b").is_err());
+    }
+}
@@ -26,16 +26,22 @@ async function initDB() {
   try {
     // Try to create Categories table
     // We ignore errors because if it exists, that's fine!
-    await queryDB("CREATE TABLE categories (id INT, name TEXT UNIQUE)").catch(
+    await queryDB("CREATE TABLE categories (id UUID PRIMARY KEY DEFAULT gen_random_uuid(), name TEXT UNIQUE)").catch(
       () => {}
     );
     console.log("✔ Categories Table Ready");
 
     // Try to create Products table
     await queryDB(
-      "CREATE TABLE products (id INT, name TEXT, price FLOAT, stock INT, category_id INT)"
+      "CREATE TABLE products (id UUID PRIMARY KEY DEFAULT gen_random_uuid(), name TEXT, price NUMERIC(10, 2), stock INT, category_id UUID REFERENCES categories(id) ON DELETE CASCADE, total_value NUMERIC(12, 2) AS (price * stock) STORED)"
     ).catch(() => {});
     console.log("✔ Products Table Ready");
+
+    // The inventory page reads products with their category through a view
+    await queryDB(
+      "CREATE VIEW inventory AS SELECT * FROM products JOIN categories ON products.category_id = categories.id"
+    ).catch(() => {});
+    console.log("✔ Inventory View Ready");
   } catch (e) {
     console.error("Bootstrap Failed:", e);
   }
@@ -67,9 +73,7 @@ function parseOutput(text) {
 // --- ROUTES (Same as before) ---
 app.get("/api/inventory", async (req, res) => {
   try {
-    const sql =
-      "SELECT * FROM products JOIN categories ON products.category_id = categories.id";
-    const raw = await queryDB(sql);
+    const raw = await queryDB("SELECT * FROM inventory");
     res.json(parseOutput(raw));
   } catch (e) {
     res.status(500).json({ error: e.toString() });
@@ -86,15 +90,11 @@ app.get("/api/categories", async (req, res) => {
 });
 
 app.post("/api/products", async (req, res) => {
-  let { name, price, stock, category_id } = req.body;
+  const { name, price, stock, category_id } = req.body;
   try {
-    if (!price.toString().includes(".")) {
-      price = `${price}.0`;
-    }
-    // Use random ID for Primary Key
-    const id = Math.floor(Math.random() * 100000);
+    // The database generates the UUID primary key
     await queryDB(
-      `INSERT INTO products VALUES (${id}, '${name}', ${price}, ${stock}, ${category_id})`
+      `INSERT INTO products (name, price, stock, category_id) VALUES ('${name}', ${price}, ${stock}, '${category_id}')`
     );
     res.json({ success: true });
   } catch (e) {
@@ -105,8 +105,7 @@ app.post("/api/products", async (req, res) => {
 app.post("/api/categories", async (req, res) => {
   const { name } = req.body;
   try {
-    const id = Math.floor(Math.random() * 10000);
-    await queryDB(`INSERT INTO categories VALUES (${id}, '${name}')`);
+    await queryDB(`INSERT INTO categories (name) VALUES ('${name}')`);
     res.json({ success: true });
   } catch (e) {
     res.status(500).json({ error: e.toString() });
@@ -126,7 +125,7 @@ app.post("/api/sell/:id", async (req, res) => {
       return res.status(400).json({ error: "Out of stock" });
 
     const newStock = currentStock - 1;
-    await queryDB(`UPDATE products SET stock = ${newStock} WHERE id = ${id}`);
+    await queryDB(`UPDATE products SET stock = ${newStock} WHERE id = '${id}'`);
     res.json({ success: true, newStock });
   } catch (e) {
     res.status(500).json({ error: e.toString() });
@@ -135,7 +134,7 @@ app.post("/api/sell/:id", async (req, res) => {
 
 app.delete("/api/products/:id", async (req, res) => {
   try {
-    await queryDB(`DELETE FROM products WHERE id = ${req.params.id}`);
+    await queryDB(`DELETE FROM products WHERE id = '${req.params.id}'`);
     res.json({ success: true });
   } catch (e) {
     res.status(500).json({ error: e.toString() });